@@ -0,0 +1,118 @@
+mod spec;
+mod stats;
+
+use common::{
+  game::{RoundOutcome, RoundState},
+  rules::RuleSet,
+  PlayerSymbol,
+};
+use spec::BotSpec;
+use stats::MatchStats;
+
+const USAGE: &str =
+  "usage: uttt-arena <bot-a> <bot-b> [--games <n>] [--seed <seed>] [--rules <rules>]";
+
+fn main() {
+  let config = match Config::from_args(std::env::args().skip(1)) {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("{}", e);
+      eprintln!("{}", USAGE);
+      std::process::exit(1);
+    }
+  };
+
+  println!(
+    "Playing {} games of {} against {} ({} rules).",
+    config.ngames, config.bots[0], config.bots[1], config.rules
+  );
+  let mut stats = MatchStats::default();
+  for igame in 0..config.ngames {
+    let (outcome, nmoves) = config.play_game(igame);
+    stats.add_game(outcome, nmoves);
+  }
+  println!("{}", stats);
+}
+
+struct Config {
+  bots: [BotSpec; 2],
+  ngames: u64,
+  seed: u64,
+  rules: RuleSet,
+}
+
+impl Config {
+  fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+    let mut bots = Vec::new();
+    let mut config = Self {
+      bots: [BotSpec::Random, BotSpec::Random],
+      ngames: 100,
+      seed: 0,
+      rules: RuleSet::default(),
+    };
+    while let Some(arg) = args.next() {
+      let mut value = |name: &str| args.next().ok_or(format!("missing value of {}", name));
+      match arg.as_str() {
+        "--games" => {
+          config.ngames = value(&arg)?
+            .parse()
+            .map_err(|e| format!("invalid number of games: {}", e))?
+        }
+        "--seed" => {
+          config.seed = value(&arg)?
+            .parse()
+            .map_err(|e| format!("invalid seed: {}", e))?
+        }
+        "--rules" => {
+          config.rules = value(&arg)?
+            .parse()
+            .map_err(|e| format!("invalid rules: {}", e))?
+        }
+        _ => bots.push(arg.parse::<BotSpec>().map_err(|e| e.to_string())?),
+      }
+    }
+    config.bots = bots
+      .try_into()
+      .map_err(|_| "expected exactly two bots".to_string())?;
+    Ok(config)
+  }
+
+  /// Plays a game, returning its outcome from the point of view of bot A and its number of moves.
+  ///
+  /// Bot A starts the even games. Bot seeds are hashed from the match seed, the game and the side,
+  /// so matches are reproducible, identical bots do not mirror each other and matches with
+  /// different seeds do not share games.
+  fn play_game(&self, igame: u64) -> (RoundOutcome, usize) {
+    let mut bots = [0, 1].map(|side| {
+      let seed = mix(mix(mix(self.seed) ^ igame) ^ side);
+      self.bots[side as usize].build(seed, self.rules)
+    });
+    // bot A plays X
+    let starting_player = match igame % 2 {
+      0 => PlayerSymbol::X,
+      _ => PlayerSymbol::O,
+    };
+
+    let mut round = RoundState::new(starting_player, self.rules);
+    let outcome = loop {
+      if let Some(outcome) = round.outcome() {
+        break outcome;
+      }
+      let curr_player = round.current_player();
+      let chosen_tile = bots[curr_player.idx()].choose_move(&round);
+      round
+        .try_play_move(curr_player, chosen_tile)
+        .expect("bot chose an illegal move");
+    };
+    let nmoves = round.move_history().len();
+    (outcome, nmoves)
+  }
+}
+
+/// Scrambles the bits of the value like a step of splitmix64.
+fn mix(mut z: u64) -> u64 {
+  z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  z ^ (z >> 31)
+}
@@ -0,0 +1,191 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use common::{
+  bot::{Bot, RandomBot, SearchBot},
+  game::RoundState,
+  mcts::{Mcts, MctsConfig, MctsLimit},
+  protocol::{ExternalEngine, GoLimits},
+  rules::RuleSet,
+  search::{SearchLimits, Searcher},
+  PlayerSymbol,
+};
+
+/// A bot configuration, written as the engine optionally followed by its limit,
+/// e.g. `random`, `search:depth=4`, `search:time=100` or `mcts:iterations=1000`.
+/// Times are given in milliseconds.
+///
+/// External engines are written as `engine:` followed by the command starting them,
+/// e.g. `engine:./my-engine --fast`. They choose their own limits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BotSpec {
+  Random,
+  Search(SearchLimits),
+  Mcts(MctsLimit),
+  Engine(String),
+}
+
+impl BotSpec {
+  /// Creates a fresh bot for a single round.
+  ///
+  /// Panics if an external engine cannot be started.
+  pub fn build(&self, seed: u64, rules: RuleSet) -> Box<dyn Bot> {
+    match *self {
+      Self::Random => Box::new(RandomBot::new(seed)),
+      Self::Search(limits) => Box::new(SearchBot::new(Searcher::default(), limits)),
+      Self::Mcts(limit) => Box::new(Mcts::new(
+        RoundState::new(PlayerSymbol::X, rules),
+        MctsConfig {
+          limit,
+          seed,
+          ..MctsConfig::default()
+        },
+      )),
+      Self::Engine(ref command) => {
+        match ExternalEngine::spawn_command_line(command, GoLimits::default()) {
+          Ok(engine) => Box::new(engine),
+          Err(e) => panic!("starting engine `{}` failed: {}", command, e),
+        }
+      }
+    }
+  }
+}
+
+impl fmt::Display for BotSpec {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Random => write!(f, "random"),
+      Self::Search(limits) => match (limits.time, limits.nodes) {
+        (Some(time), _) => write!(f, "search:time={}", time.as_millis()),
+        (_, Some(nodes)) => write!(f, "search:nodes={}", nodes),
+        _ => write!(f, "search:depth={}", limits.depth),
+      },
+      Self::Mcts(MctsLimit::Iterations(iterations)) => {
+        write!(f, "mcts:iterations={}", iterations)
+      }
+      Self::Mcts(MctsLimit::Time(time)) => write!(f, "mcts:time={}", time.as_millis()),
+      Self::Engine(command) => write!(f, "engine:{}", command),
+    }
+  }
+}
+
+impl FromStr for BotSpec {
+  type Err = BotSpecParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let err = || BotSpecParseError(s.to_string());
+    if let Some(command) = s.strip_prefix("engine:") {
+      return match command.trim().is_empty() {
+        true => Err(err()),
+        false => Ok(Self::Engine(command.trim().to_string())),
+      };
+    }
+    let (engine, limit) = match s.split_once(':') {
+      Some((engine, limit)) => (engine, Some(limit)),
+      None => (s, None),
+    };
+    let limit = limit
+      .map(|limit| {
+        let (name, value) = limit.split_once('=').ok_or_else(err)?;
+        let value: u64 = value.parse().map_err(|_| err())?;
+        Ok((name, value))
+      })
+      .transpose()?;
+
+    match (engine, limit) {
+      ("random", None) => Ok(Self::Random),
+      ("search", None) => Ok(Self::Search(SearchLimits::depth(4))),
+      ("search", Some(("depth", depth))) => Ok(Self::Search(SearchLimits::depth(depth as usize))),
+      ("search", Some(("time", millis))) => Ok(Self::Search(SearchLimits::time(
+        Duration::from_millis(millis),
+      ))),
+      ("search", Some(("nodes", nodes))) => Ok(Self::Search(SearchLimits::nodes(nodes))),
+      ("mcts", None) => Ok(Self::Mcts(MctsConfig::default().limit)),
+      ("mcts", Some(("iterations", iterations))) => {
+        Ok(Self::Mcts(MctsLimit::Iterations(iterations)))
+      }
+      ("mcts", Some(("time", millis))) => {
+        Ok(Self::Mcts(MctsLimit::Time(Duration::from_millis(millis))))
+      }
+      _ => Err(err()),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotSpecParseError(pub String);
+impl fmt::Display for BotSpecParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "invalid bot `{}`, expected e.g. random, search:depth=4, search:time=100, search:nodes=10000, \
+       mcts:iterations=1000, mcts:time=100 or engine:<command>",
+      self.0
+    )
+  }
+}
+impl std::error::Error for BotSpecParseError {}
+
+#[cfg(test)]
+mod test {
+  use std::time::Duration;
+
+  use super::BotSpec;
+  use common::{mcts::MctsLimit, search::SearchLimits};
+
+  #[test]
+  fn check_bot_spec_parsing() {
+    let parse = |s: &str| s.parse::<BotSpec>();
+    assert_eq!(parse("random"), Ok(BotSpec::Random));
+    assert_eq!(parse("search"), Ok(BotSpec::Search(SearchLimits::depth(4))));
+    assert_eq!(
+      parse("search:depth=6"),
+      Ok(BotSpec::Search(SearchLimits::depth(6)))
+    );
+    assert_eq!(
+      parse("search:time=100"),
+      Ok(BotSpec::Search(SearchLimits::time(Duration::from_millis(
+        100
+      ))))
+    );
+    assert_eq!(
+      parse("search:nodes=5000"),
+      Ok(BotSpec::Search(SearchLimits::nodes(5000)))
+    );
+    assert_eq!(
+      parse("mcts:iterations=300"),
+      Ok(BotSpec::Mcts(MctsLimit::Iterations(300)))
+    );
+    assert_eq!(
+      parse("mcts:time=50"),
+      Ok(BotSpec::Mcts(MctsLimit::Time(Duration::from_millis(50))))
+    );
+    assert_eq!(
+      parse("engine: ./engine --fast "),
+      Ok(BotSpec::Engine("./engine --fast".into()))
+    );
+
+    for invalid in [
+      "",
+      "minimax",
+      "random:depth=2",
+      "search:depth",
+      "search:depth=-1",
+      "search:width=3",
+      "mcts:nodes=10",
+      "engine:",
+    ] {
+      assert!(parse(invalid).is_err(), "{}", invalid);
+    }
+
+    for spec in [
+      "random",
+      "search:depth=6",
+      "search:time=100",
+      "search:nodes=5000",
+      "mcts:iterations=300",
+      "mcts:time=50",
+      "engine:./engine --fast",
+    ] {
+      assert_eq!(parse(spec).unwrap().to_string(), spec);
+    }
+  }
+}
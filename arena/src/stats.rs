@@ -0,0 +1,151 @@
+use std::fmt;
+
+use common::{game::RoundOutcome, PlayerSymbol};
+
+/// z-score of the 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// Results of a match, from the point of view of bot A, which plays `X`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatchStats {
+  pub nwins: u64,
+  pub ndraws: u64,
+  pub nlosses: u64,
+  pub nmoves: u64,
+}
+
+impl MatchStats {
+  pub fn add_game(&mut self, outcome: RoundOutcome, nmoves: usize) {
+    match outcome {
+      RoundOutcome::Win(PlayerSymbol::X) => self.nwins += 1,
+      RoundOutcome::Win(PlayerSymbol::O) => self.nlosses += 1,
+      RoundOutcome::Draw => self.ndraws += 1,
+    }
+    self.nmoves += nmoves as u64;
+  }
+
+  pub fn ngames(&self) -> u64 {
+    self.nwins + self.ndraws + self.nlosses
+  }
+
+  /// Mean score per game, counting draws as half a win.
+  pub fn score(&self) -> f64 {
+    (self.nwins as f64 + 0.5 * self.ndraws as f64) / self.ngames() as f64
+  }
+
+  /// Elo difference of bot A over bot B, with the half width of its 95% confidence interval.
+  /// The difference is infinite if either bot scored every point, and so is the width if the
+  /// interval reaches such a score.
+  pub fn elo_difference(&self) -> (f64, f64) {
+    let ngames = self.ngames() as f64;
+    let score = self.score();
+    let variance = [(self.nwins, 1.0), (self.ndraws, 0.5), (self.nlosses, 0.0)]
+      .iter()
+      .map(|&(n, game_score)| n as f64 * (game_score - score).powi(2))
+      .sum::<f64>()
+      / ngames;
+    let margin = Z_95 * (variance / ngames).sqrt();
+    let lower = elo_from_score(score - margin);
+    let upper = elo_from_score(score + margin);
+    match score > 0.0 && score < 1.0 {
+      true => (elo_from_score(score), (upper - lower) / 2.0),
+      false => (elo_from_score(score), f64::INFINITY),
+    }
+  }
+}
+
+/// Elo difference expected to yield the mean score. Infinite for scores of 0 and 1.
+fn elo_from_score(score: f64) -> f64 {
+  let score = score.clamp(0.0, 1.0);
+  -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl fmt::Display for MatchStats {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let ngames = self.ngames();
+    if ngames == 0 {
+      return write!(f, "No games played.");
+    }
+    let (elo, margin) = self.elo_difference();
+    writeln!(
+      f,
+      "Wins: {}, Draws: {}, Losses: {} (score {:.1}%)",
+      self.nwins,
+      self.ndraws,
+      self.nlosses,
+      100.0 * self.score()
+    )?;
+    match margin.is_finite() {
+      true => writeln!(f, "Elo difference: {:.1} +/- {:.1}", elo, margin)?,
+      false => writeln!(f, "Elo difference: {:.1} (unbounded error)", elo)?,
+    }
+    write!(
+      f,
+      "Average game length: {:.1} moves",
+      self.nmoves as f64 / ngames as f64
+    )
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{elo_from_score, MatchStats};
+  use common::{game::RoundOutcome, PlayerSymbol};
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!(
+      (actual - expected).abs() < 0.01,
+      "{} != {}",
+      actual,
+      expected
+    );
+  }
+
+  fn stats(nwins: u64, ndraws: u64, nlosses: u64) -> MatchStats {
+    MatchStats {
+      nwins,
+      ndraws,
+      nlosses,
+      nmoves: 0,
+    }
+  }
+
+  #[test]
+  fn check_elo_from_score() {
+    assert_close(elo_from_score(0.5), 0.0);
+    assert_close(elo_from_score(0.75), 190.85);
+    assert_close(elo_from_score(0.25), -190.85);
+    assert_eq!(elo_from_score(1.0), f64::INFINITY);
+    assert_eq!(elo_from_score(0.0), f64::NEG_INFINITY);
+  }
+
+  #[test]
+  fn check_elo_difference() {
+    let (elo, margin) = stats(10, 0, 10).elo_difference();
+    assert_close(elo, 0.0);
+    assert_close(margin, 163.32);
+
+    let (elo, margin) = stats(6, 2, 2).elo_difference();
+    assert_close(elo, 147.19);
+    assert_close(margin, 268.73);
+
+    let (elo, margin) = stats(3, 0, 0).elo_difference();
+    assert_eq!(elo, f64::INFINITY);
+    assert_eq!(margin, f64::INFINITY);
+    assert!(stats(3, 0, 0).to_string().contains("(unbounded error)"));
+  }
+
+  #[test]
+  fn check_add_game() {
+    let mut stats = MatchStats::default();
+    stats.add_game(RoundOutcome::Win(PlayerSymbol::X), 30);
+    stats.add_game(RoundOutcome::Win(PlayerSymbol::O), 40);
+    stats.add_game(RoundOutcome::Draw, 50);
+    assert_eq!((stats.nwins, stats.nlosses, stats.ndraws), (1, 1, 1));
+    assert_eq!(stats.ngames(), 3);
+    assert_close(stats.score(), 0.5);
+    assert!(stats
+      .to_string()
+      .contains("Average game length: 40.0 moves"));
+  }
+}
@@ -1,5 +1,6 @@
 use common::{
   game::{RoundOutcome, RoundState, Stats},
+  rules::RuleSet,
   PlayerSymbol,
 };
 
@@ -31,7 +32,7 @@ pub fn play_game(this_player: PlayerSymbol) {
 }
 
 pub fn play_round(starting_player: PlayerSymbol) -> RoundOutcome {
-  let round_state = RoundState::new(starting_player);
+  let round_state = RoundState::new(starting_player, RuleSet::default());
 
   loop {
     let chosen_tile = get_move(&round_state);
@@ -22,7 +22,7 @@ fn main() {
 pub enum Client {
   Connecting(ConnectingState),
   WaitingForGameStart(WaitingState),
-  Playing(PlayingState),
+  Playing(Box<PlayingState>),
 }
 impl Default for Client {
   fn default() -> Self {
@@ -1,29 +1,54 @@
 use crate::{
   util::{
     board_ui::{self, build_board_ui},
-    choose_random_tile, player_color,
+    player_color,
     stats_ui::build_stats_ui,
   },
   waiting::WaitingState,
   Client,
 };
 
+#[cfg(feature = "auto_play")]
+use common::{
+  bot::Bot,
+  mcts::{Mcts, MctsConfig, MctsLimit},
+  protocol::{ExternalEngine, GoLimits},
+  GlobalPos,
+};
 use common::{
   game::{PlayerAction, RoundOutcome, RoundState, Stats},
   msg::{
     ClientMsgAction, ClientReqRoundStart, MessageIoHandlerNoBlocking, ServerMsgOpponentAction,
   },
+  rules::RuleSet,
   PlayerSymbol,
 };
 
 use eframe::egui;
 
+#[cfg(feature = "auto_play")]
+use std::thread::{self, JoinHandle};
+
+/// Thinking time of the engine, when playing automatically.
+#[cfg(feature = "auto_play")]
+const ENGINE_TIME: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// A bot that can search on its own thread.
+#[cfg(feature = "auto_play")]
+type AutoBot = Box<dyn Bot + Send>;
+
 pub struct PlayingState {
   msg_handler: MessageIoHandlerNoBlocking,
   this_player: PlayerSymbol,
 
   stats: Stats,
   round: RoundState,
+  /// engine playing for us, `None` while the search thread owns it
+  #[cfg(feature = "auto_play")]
+  bot: Option<AutoBot>,
+  /// search for our next move, running off the UI thread
+  #[cfg(feature = "auto_play")]
+  search: Option<JoinHandle<(AutoBot, GlobalPos)>>,
 
   outcome: Option<RoundOutcome>,
 }
@@ -35,12 +60,18 @@ impl PlayingState {
     stats: Stats,
     starting_player: PlayerSymbol,
   ) -> Self {
-    let round = RoundState::new(starting_player);
+    let round = RoundState::new(starting_player, RuleSet::default());
+    #[cfg(feature = "auto_play")]
+    let bot = Self::auto_bot(&round);
     Self {
       msg_handler,
       this_player,
       stats,
       round,
+      #[cfg(feature = "auto_play")]
+      bot: Some(bot),
+      #[cfg(feature = "auto_play")]
+      search: None,
       outcome: None,
     }
   }
@@ -127,15 +158,16 @@ impl PlayingState {
       self.update_round(action);
     }
 
-    Client::Playing(self)
+    Client::Playing(Box::new(self))
   }
 
   fn update_round(&mut self, mut action: Option<PlayerAction>) {
     let my_turn = self.round.current_player() == self.this_player;
 
     if my_turn {
-      if cfg!(feature = "auto_play") {
-        action = Some(PlayerAction::MakeMove(choose_random_tile(&self.round)));
+      #[cfg(feature = "auto_play")]
+      {
+        action = self.poll_bot();
       }
     } else {
       debug_assert!(
@@ -148,10 +180,12 @@ impl PlayingState {
 
     if let Some(action) = action {
       match action {
-        PlayerAction::MakeMove(chosen_tile) => self
-          .round
-          .try_play_move(self.round.current_player(), chosen_tile)
-          .unwrap(),
+        PlayerAction::MakeMove(chosen_tile) => {
+          self
+            .round
+            .try_play_move(self.round.current_player(), chosen_tile)
+            .unwrap();
+        }
         PlayerAction::GiveUp => {
           self.outcome = Some(RoundOutcome::Win(self.round.current_player().other()));
         }
@@ -170,4 +204,48 @@ impl PlayingState {
       self.stats.update(outcome);
     };
   }
+
+  /// The external engine given by the
+  /// [`ENGINE_ENV_VAR`](common::protocol::ENGINE_ENV_VAR) environment variable if it is set,
+  /// else the built-in engine, which follows the moves of the opponent and keeps its tree.
+  #[cfg(feature = "auto_play")]
+  fn auto_bot(round: &RoundState) -> AutoBot {
+    let limits = GoLimits {
+      movetime: Some(ENGINE_TIME),
+      ..GoLimits::default()
+    };
+    match ExternalEngine::from_env(limits) {
+      Some(Ok(engine)) => return Box::new(engine),
+      Some(Err(e)) => eprintln!(
+        "Starting engine failed, playing with the built-in one: {}",
+        e
+      ),
+      None => (),
+    }
+    Box::new(Mcts::new(
+      round.clone(),
+      MctsConfig {
+        limit: MctsLimit::Time(ENGINE_TIME),
+        seed: rand::random(),
+        ..MctsConfig::default()
+      },
+    ))
+  }
+
+  /// Starts the search for our next move on its own thread, so the UI stays responsive,
+  /// and returns the move once the search has finished.
+  #[cfg(feature = "auto_play")]
+  fn poll_bot(&mut self) -> Option<PlayerAction> {
+    if let Some(mut bot) = self.bot.take() {
+      let round = self.round.clone();
+      self.search = Some(thread::spawn(move || {
+        let chosen_tile = bot.choose_move(&round);
+        (bot, chosen_tile)
+      }));
+    }
+    let search = self.search.take_if(|search| search.is_finished())?;
+    let (bot, chosen_tile) = search.join().expect("search thread panicked");
+    self.bot = Some(bot);
+    Some(PlayerAction::MakeMove(chosen_tile))
+  }
 }
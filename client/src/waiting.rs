@@ -39,12 +39,12 @@ impl WaitingState {
     });
 
     if let Some(ServerMsgRoundStart(starting_player)) = self.msg_handler.try_read_msg().unwrap() {
-      return Client::Playing(PlayingState::new(
+      return Client::Playing(Box::new(PlayingState::new(
         self.msg_handler,
         self.this_player,
         self.stats,
         starting_player,
-      ));
+      )));
     }
 
     Client::WaitingForGameStart(self)
@@ -0,0 +1,349 @@
+//! A compact alternative to the recursive [`OuterBoard`], meant for tight search loops.
+
+use crate::{
+  board::{
+    tile::{TilePos, TrivialTileState},
+    BoardInvariantError, PlaceSymbolError, RemoveSymbolError, TileBoardState,
+  },
+  GlobalPos, InnerPos, OuterBoard, OuterBoardBackend, OuterPos, PlayerSymbol, PLAYERS,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Mask of all 9 tiles of a 3x3 board.
+const FULL_MASK: u16 = 0x1ff;
+
+/// Masks of the 8 lines of a 3x3 board, using [`TilePos::linear_idx`] as bit index.
+const LINE_MASKS: [u16; 8] = [
+  // x axes
+  0b001_001_001,
+  0b010_010_010,
+  0b100_100_100,
+  // y axes
+  0b000_000_111,
+  0b000_111_000,
+  0b111_000_000,
+  // main diagonal
+  0b100_010_001,
+  // anti diagonal
+  0b001_010_100,
+];
+
+/// Lookup table telling whether a 9-bit mask contains a full line.
+const WIN_TABLE: [bool; 512] = {
+  let mut table = [false; 512];
+  let mut mask = 0;
+  while mask < 512 {
+    let mut i = 0;
+    while i < LINE_MASKS.len() {
+      if mask as u16 & LINE_MASKS[i] == LINE_MASKS[i] {
+        table[mask] = true;
+      }
+      i += 1;
+    }
+    mask += 1;
+  }
+  table
+};
+
+/// Board backend storing the whole outer board in a handful of bitmasks.
+///
+/// Trivial tile `GlobalPos` is stored at bit `9 * outer_idx + inner_idx`,
+/// where both indices are [`TilePos::linear_idx`].
+///
+/// (De)serializes through [`OuterBoard`], so both backends share the same checked format.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "OuterBoard", from = "OuterBoard")]
+pub struct BitBoard {
+  /// occupied trivial tiles, per player
+  tiles: [u128; 2],
+
+  /// derived won sub-boards, per player (redundant information)
+  won: [u16; 2],
+  /// derived drawn (including fully drawn) sub-boards (redundant information)
+  drawn: u16,
+  /// derived fully drawn sub-boards (redundant information)
+  fully_drawn: u16,
+  /// derived outer board state (redundant information)
+  board_state: TileBoardState,
+}
+
+impl BitBoard {
+  fn sub_board_tiles(&self, outer_idx: usize) -> [u16; 2] {
+    self
+      .tiles
+      .map(|tiles| ((tiles >> (9 * outer_idx)) as u16) & FULL_MASK)
+  }
+
+  fn update_sub_board_state(&mut self, outer_idx: usize) {
+    let prev_state = self.sub_board_state_by_idx(outer_idx);
+    let state = derive_state(self.sub_board_tiles(outer_idx), 0, prev_state);
+    self.set_sub_board_state(outer_idx, state);
+    self.board_state = derive_state(self.won, self.drawn, self.board_state);
+  }
+
+  fn set_sub_board_state(&mut self, outer_idx: usize, state: TileBoardState) {
+    let bit = 1 << outer_idx;
+    for p in PLAYERS {
+      self.won[p.idx()] &= !bit;
+    }
+    self.drawn &= !bit;
+    self.fully_drawn &= !bit;
+    match state {
+      TileBoardState::Free => {}
+      TileBoardState::Won(p) => self.won[p.idx()] |= bit,
+      TileBoardState::Drawn => self.drawn |= bit,
+      TileBoardState::FullyDrawn => {
+        self.drawn |= bit;
+        self.fully_drawn |= bit;
+      }
+    }
+  }
+
+  fn sub_board_state_by_idx(&self, outer_idx: usize) -> TileBoardState {
+    let bit = 1 << outer_idx;
+    if let Some(p) = PLAYERS.into_iter().find(|p| self.won[p.idx()] & bit != 0) {
+      TileBoardState::Won(p)
+    } else if self.fully_drawn & bit != 0 {
+      TileBoardState::FullyDrawn
+    } else if self.drawn & bit != 0 {
+      TileBoardState::Drawn
+    } else {
+      TileBoardState::Free
+    }
+  }
+}
+
+/// The state a 3x3 board is derived from when building it, which is won by the disputed winner if
+/// both players own a line.
+fn initial_state(
+  owned: [u16; 2],
+  disputed_winner: impl FnOnce() -> Option<PlayerSymbol>,
+) -> Result<TileBoardState, BoardInvariantError> {
+  match owned.iter().all(|&owned| WIN_TABLE[owned as usize]) {
+    true => disputed_winner()
+      .map(TileBoardState::Won)
+      .ok_or(BoardInvariantError::UndecidedWinner),
+    false => Ok(TileBoardState::Free),
+  }
+}
+
+/// Derives the state of a 3x3 board from the tiles owned by each player and the blocked (drawn) tiles.
+/// A won board stays with its winner, as long as one of the winner's lines remains.
+fn derive_state(owned: [u16; 2], blocked: u16, prev_state: TileBoardState) -> TileBoardState {
+  let has_line = |p: PlayerSymbol| WIN_TABLE[owned[p.idx()] as usize];
+  if let TileBoardState::Won(p) = prev_state {
+    if has_line(p) {
+      return TileBoardState::Won(p);
+    }
+  }
+
+  if let Some(p) = PLAYERS.into_iter().find(|&p| has_line(p)) {
+    TileBoardState::Won(p)
+  } else if owned[0] | owned[1] | blocked == FULL_MASK {
+    TileBoardState::FullyDrawn
+  } else if LINE_MASKS
+    .iter()
+    .all(|&line| (owned[0] & line != 0 && owned[1] & line != 0) || blocked & line != 0)
+  {
+    TileBoardState::Drawn
+  } else {
+    TileBoardState::Free
+  }
+}
+
+fn bit_idx(global_pos: GlobalPos) -> (usize, usize) {
+  let outer_idx = TilePos::from(OuterPos::from(global_pos)).linear_idx();
+  let inner_idx = TilePos::from(InnerPos::from(global_pos)).linear_idx();
+  (outer_idx, 9 * outer_idx + inner_idx)
+}
+
+impl OuterBoardBackend for BitBoard {
+  fn board_state(&self) -> TileBoardState {
+    self.board_state
+  }
+  fn sub_board_state(&self, outer_pos: OuterPos) -> TileBoardState {
+    self.sub_board_state_by_idx(TilePos::from(outer_pos).linear_idx())
+  }
+  fn trivial_tile(&self, global_pos: GlobalPos) -> TrivialTileState {
+    let (_, idx) = bit_idx(global_pos);
+    PLAYERS
+      .into_iter()
+      .find(|p| self.tiles[p.idx()] & (1 << idx) != 0)
+      .map(TrivialTileState::Won)
+      .unwrap_or(TrivialTileState::Free)
+  }
+  fn is_board_disputed(&self, outer_pos: Option<OuterPos>) -> bool {
+    let owned = match outer_pos {
+      Some(outer_pos) => self.sub_board_tiles(TilePos::from(outer_pos).linear_idx()),
+      None => self.won,
+    };
+    owned.iter().all(|&owned| WIN_TABLE[owned as usize])
+  }
+
+  fn could_place_symbol(&self, global_pos: GlobalPos) -> bool {
+    self.board_state.is_placeable()
+      && self
+        .sub_board_state(OuterPos::from(global_pos))
+        .is_placeable()
+      && self.trivial_tile(global_pos).is_free()
+  }
+  fn try_place_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError> {
+    if !self.board_state.is_placeable()
+      || !self
+        .sub_board_state(OuterPos::from(global_pos))
+        .is_placeable()
+    {
+      return Err(PlaceSymbolError::BoardNotPlaceable);
+    }
+    self.try_force_place_symbol(global_pos, symbol)
+  }
+  fn try_force_place_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError> {
+    if !self.trivial_tile(global_pos).is_free() {
+      return Err(PlaceSymbolError::TrivialTileNotFree);
+    }
+
+    let (outer_idx, idx) = bit_idx(global_pos);
+    self.tiles[symbol.idx()] |= 1 << idx;
+    self.update_sub_board_state(outer_idx);
+    Ok(())
+  }
+  fn try_remove_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+  ) -> Result<PlayerSymbol, RemoveSymbolError> {
+    let TrivialTileState::Won(symbol) = self.trivial_tile(global_pos) else {
+      return Err(RemoveSymbolError::TrivialTileFree);
+    };
+
+    let (outer_idx, idx) = bit_idx(global_pos);
+    self.tiles[symbol.idx()] &= !(1 << idx);
+    self.update_sub_board_state(outer_idx);
+    Ok(symbol)
+  }
+
+  fn from_trivial_tiles(
+    mut tile: impl FnMut(GlobalPos) -> TrivialTileState,
+    mut disputed_winner: impl FnMut(Option<OuterPos>) -> Option<PlayerSymbol>,
+  ) -> Result<Self, BoardInvariantError> {
+    let mut board = Self::default();
+    for global_pos in GlobalPos::all() {
+      if let TrivialTileState::Won(p) = tile(global_pos) {
+        board.tiles[p.idx()] |= 1 << bit_idx(global_pos).1;
+      }
+    }
+    for outer_pos in OuterPos::all() {
+      let outer_idx = TilePos::from(outer_pos).linear_idx();
+      let owned = board.sub_board_tiles(outer_idx);
+      let prev_state = initial_state(owned, || disputed_winner(Some(outer_pos)))?;
+      board.set_sub_board_state(outer_idx, derive_state(owned, 0, prev_state));
+    }
+    let prev_state = initial_state(board.won, || disputed_winner(None))?;
+    board.board_state = derive_state(board.won, board.drawn, prev_state);
+    Ok(board)
+  }
+}
+
+impl From<&OuterBoard> for BitBoard {
+  fn from(outer_board: &OuterBoard) -> Self {
+    Self::from_trivial_tiles(
+      |global_pos| outer_board.trivial_tile(global_pos),
+      |outer_pos| outer_board.winner(outer_pos),
+    )
+    .expect("the source board decides all winners")
+  }
+}
+
+impl From<&BitBoard> for OuterBoard {
+  fn from(bit_board: &BitBoard) -> Self {
+    OuterBoard::from_trivial_tiles(
+      |global_pos| bit_board.trivial_tile(global_pos),
+      |outer_pos| bit_board.winner(outer_pos),
+    )
+    .expect("the source board decides all winners")
+  }
+}
+
+impl From<OuterBoard> for BitBoard {
+  fn from(outer_board: OuterBoard) -> Self {
+    Self::from(&outer_board)
+  }
+}
+impl From<BitBoard> for OuterBoard {
+  fn from(bit_board: BitBoard) -> Self {
+    Self::from(&bit_board)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::BitBoard;
+  use crate::{
+    game::RoundState, rules::RuleSet, GlobalPos, OuterBoard, OuterBoardBackend, OuterPos,
+    PlayerSymbol,
+  };
+
+  fn assert_boards_agree(outer_board: &OuterBoard, bit_board: &BitBoard) {
+    assert_eq!(
+      OuterBoardBackend::board_state(outer_board),
+      bit_board.board_state()
+    );
+    for outer_pos in OuterPos::all() {
+      assert_eq!(
+        outer_board.sub_board_state(outer_pos),
+        bit_board.sub_board_state(outer_pos)
+      );
+    }
+    for global_pos in GlobalPos::all() {
+      assert_eq!(
+        OuterBoardBackend::trivial_tile(outer_board, global_pos),
+        bit_board.trivial_tile(global_pos)
+      );
+      assert_eq!(
+        OuterBoardBackend::could_place_symbol(outer_board, global_pos),
+        bit_board.could_place_symbol(global_pos)
+      );
+    }
+  }
+
+  #[test]
+  fn check_backends_agree_on_random_games() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..20 {
+      let starting_player: PlayerSymbol = rng.gen();
+      let mut round = RoundState::new(starting_player, RuleSet::default());
+      let mut bit_round =
+        RoundState::<BitBoard>::new_with_backend(starting_player, RuleSet::default());
+
+      while round.outcome().is_none() {
+        assert_boards_agree(round.board(), bit_round.board());
+        assert_eq!(&BitBoard::from(round.board()), bit_round.board());
+        assert_eq!(&OuterBoard::from(bit_round.board()), round.board());
+
+        let legal_moves: Vec<_> = round.legal_moves().collect();
+        assert!(bit_round.legal_moves().eq(legal_moves.iter().copied()));
+
+        let chosen_tile = *legal_moves.choose(&mut rng).unwrap();
+        let player = round.current_player();
+        round.try_play_move(player, chosen_tile).unwrap();
+        bit_round.try_play_move(player, chosen_tile).unwrap();
+      }
+      assert_eq!(round.outcome(), bit_round.outcome());
+
+      while round.undo_move().is_some() {
+        bit_round.undo_move().unwrap();
+        assert_boards_agree(round.board(), bit_round.board());
+      }
+    }
+  }
+}
@@ -1,13 +1,18 @@
 pub mod line;
 pub mod tile;
 
-use line::{LinePos, LineState, LineStates};
+use line::{LineCounts, LinePos, LineState, LineStates};
 use tile::{TilePos, TileStates, TileTrait, TrivialTileState};
 
-use crate::PlayerSymbol;
+use crate::{PlayerSymbol, PLAYERS};
 
+use serde::{Deserialize, Serialize};
+
+/// Side length of the standard board, which is also the default for [`GenericBoard`].
 pub const BOARD_SIDE_LENGTH: u8 = 3;
 pub const BOARD_AREA: u8 = BOARD_SIDE_LENGTH * BOARD_SIDE_LENGTH;
+/// Largest side length a [`GenericBoard`] can have, as limited by [`TilePos`].
+pub const MAX_BOARD_SIDE_LENGTH: u8 = 16;
 
 /// `TrivialBoard` is the bottom of the board hierarchy.
 /// It is the base case of the inductive type `GenericBoard`.
@@ -15,30 +20,107 @@ pub type TrivialBoard = GenericBoard<TrivialTileState>;
 
 /// Inductive board type generating the board hierarchy.
 /// The generic [`TileType`] only needs to implement the [`TileTrait`].
+///
+/// The board has side length `N` and is won by `K` tiles in a row,
+/// horizontally, vertically or diagonally. Both default to the standard 3.
+/// Levels of the hierarchy may differ in `N` and `K`.
+///
+/// Only the board hierarchy itself is generic. [`RoundState`](crate::game::RoundState),
+/// [`GlobalPos`](crate::GlobalPos), [`OuterPos`](crate::OuterPos), [`InnerPos`](crate::InnerPos)
+/// and the [`BitBoard`](crate::bitboard::BitBoard) backend are fixed to the standard 3×3 boards
+/// with 3 in a row. Other sizes are played with [`NestedRoundState`](crate::nested::NestedRoundState),
+/// which requires all levels to share `N`.
+///
+/// Deserialization checks the derived states against the tile states and rejects mismatching input.
 #[allow(private_bounds)]
-#[derive(Debug, Default)]
-pub struct GenericBoard<TileType: TileTrait> {
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+  try_from = "GenericBoardRepr<TileType, N, K>",
+  bound(deserialize = "TileType: Deserialize<'de>")
+)]
+pub struct GenericBoard<TileType: TileTrait, const N: usize = 3, const K: usize = 3> {
   /// ground tile states
-  tile_states: TileStates<TileType>,
+  tile_states: TileStates<TileType, N>,
 
   /// derived line states (redundant information)
-  line_states: LineStates,
+  line_states: LineStates<N, K>,
+  /// derived counts of the line states (redundant information)
+  #[serde(skip)]
+  line_counts: LineCounts,
   /// derived board state (redundant information)
   board_state: TileBoardState,
 }
 
 #[allow(private_bounds)]
-impl<TileType: TileTrait> GenericBoard<TileType> {
+impl<TileType: TileTrait, const N: usize, const K: usize> GenericBoard<TileType, N, K> {
+  /// Number of board levels in the hierarchy, e.g. 2 for an [`OuterBoard`](crate::OuterBoard).
+  pub const DEPTH: usize = <Self as TileTrait>::DEPTH;
+
+  /// Builds a board from arbitrary tiles, deriving all local super states.
+  /// The tiles themselves are taken as they are, so sub-boards should be built with this function too.
+  ///
+  /// The tiles do not decide the winner of a board with lines of both players, which happens when
+  /// playing on in won boards. Such a board is won by `disputed_winner` and rejected without one.
+  /// For other boards, `disputed_winner` is ignored.
+  pub fn from_tiles(
+    tile: impl FnMut(TilePos) -> TileType,
+    disputed_winner: Option<PlayerSymbol>,
+  ) -> Result<Self, BoardInvariantError> {
+    Self::from_tile_states(TileStates::from_fn(tile), disputed_winner)
+  }
+
+  /// Builds a board from the given tile states, deriving all local super states.
+  /// See [`Self::from_tiles`] for the disputed winner.
+  pub(crate) fn from_tile_states(
+    tile_states: TileStates<TileType, N>,
+    disputed_winner: Option<PlayerSymbol>,
+  ) -> Result<Self, BoardInvariantError> {
+    let mut board = Self {
+      tile_states,
+      line_states: LineStates::default(),
+      line_counts: LineCounts::default(),
+      board_state: TileBoardState::default(),
+    };
+    for line in LinePos::all::<N, K>() {
+      board.update_line_state(line);
+    }
+    if board.is_disputed() {
+      let winner = disputed_winner.ok_or(BoardInvariantError::UndecidedWinner)?;
+      board.board_state = TileBoardState::Won(winner);
+    }
+    board.board_state = board.derive_board_state();
+    Ok(board)
+  }
+
   pub fn tile_state(&self, pos: impl Into<TilePos>) -> &TileType {
     &self.tile_states[pos.into()]
   }
-  pub fn line_state(&self, pos: impl Into<LinePos>) -> LineState {
+  pub fn line_state(&self, pos: impl Into<LinePos>) -> LineState<K> {
     self.line_states[pos.into()]
   }
   pub fn board_state(&self) -> TileBoardState {
     self.board_state
   }
 
+  /// Whether the player has completed a line of this board.
+  pub fn has_line(&self, player: PlayerSymbol) -> bool {
+    self.line_counts.has_line(player)
+  }
+  /// Whether both players have completed a line, so that the tiles alone do not decide the winner.
+  pub fn is_disputed(&self) -> bool {
+    PLAYERS.into_iter().all(|p| self.has_line(p))
+  }
+
+  /// Returns the state of the tile at the given position, by recursively walking the board hierarchy.
+  /// The position may stop at any level; an empty position refers to this board itself.
+  pub fn tile_state_at(&self, pos_iter: impl IntoIterator<Item = TilePos>) -> TileBoardState {
+    let mut pos_iter = pos_iter.into_iter();
+    match pos_iter.next() {
+      Some(local_pos) => self.tile_states[local_pos].tile_state_in_tile(pos_iter),
+      None => self.board_state,
+    }
+  }
+
   /// Returns the trivial tile at the given position, by recursively walking the board hierarchy.
   pub fn trivial_tile(&self, pos_iter: impl IntoIterator<Item = TilePos>) -> TrivialTileState {
     let mut pos_iter = pos_iter.into_iter();
@@ -73,33 +155,142 @@ impl<TileType: TileTrait> GenericBoard<TileType> {
     }
   }
 
+  /// Places a symbol on the given trivial tile like [`Self::try_place_symbol`],
+  /// but ignores whether the boards are placeable. Only the trivial tile needs to be free.
+  ///
+  /// Decided boards keep their state, so this allows playing on in boards that are already won.
+  pub fn try_force_place_symbol(
+    &mut self,
+    pos_iter: impl IntoIterator<Item = TilePos>,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError> {
+    let mut pos_iter = pos_iter.into_iter();
+    let local_pos = pos_iter.next().expect("ran out of positions");
+
+    self.tile_states[local_pos].try_force_place_symbol_in_tile(pos_iter, symbol)?;
+    self.update_super_states(local_pos);
+    Ok(())
+  }
+
+  /// Removes the symbol from the given trivial tile, by recursively walking the board hierarchy and
+  /// updating the state of the `TrivialTile` and the hierarchy of super states.
+  ///
+  /// This is the inverse of [`Self::try_place_symbol`] and therefore ignores whether the boards are placeable.
+  pub fn try_remove_symbol(
+    &mut self,
+    pos_iter: impl IntoIterator<Item = TilePos>,
+  ) -> Result<PlayerSymbol, RemoveSymbolError> {
+    let mut pos_iter = pos_iter.into_iter();
+    let local_pos = pos_iter.next().expect("ran out of positions");
+
+    let symbol = self.tile_states[local_pos].try_remove_symbol_in_tile(pos_iter)?;
+    self.update_super_states(local_pos);
+    Ok(symbol)
+  }
+
+  /// Checks that the derived line and board states match the tile states, for the whole hierarchy.
+  ///
+  /// A board with lines of both players may be won by either of them.
+  pub fn validate(&self) -> Result<(), BoardInvariantError> {
+    for x in 0..N as u8 {
+      for y in 0..N as u8 {
+        self.tile_states[TilePos::new(x, y)].validate_tile()?;
+      }
+    }
+    self.validate_local()
+  }
+
+  /// Like [`Self::validate`], but assumes the tiles to be valid.
+  fn validate_local(&self) -> Result<(), BoardInvariantError> {
+    if LinePos::all::<N, K>().any(|line| self.line_states[line] != self.derive_line_state(line))
+      || self.line_counts != LineCounts::of(&self.line_states)
+    {
+      Err(BoardInvariantError::LineStatesMismatch)
+    } else if self.board_state != self.derive_board_state() {
+      Err(BoardInvariantError::BoardStateMismatch)
+    } else {
+      Ok(())
+    }
+  }
+
   /// Updates the local super states (line and board states), after a tile at the given pos has changed.
   fn update_super_states(&mut self, local_pos: TilePos) {
-    for line in LinePos::all_through_point(local_pos) {
-      let line_state = line
-        .iter()
-        .map(|pos| LineState::from(self.tile_states[pos].tile_state()))
-        .reduce(|a, b| a.combine(b))
-        .unwrap();
+    for line in LinePos::all_through_point::<N, K>(local_pos) {
+      self.update_line_state(line);
+    }
+    self.board_state = self.derive_board_state();
+  }
+
+  fn update_line_state(&mut self, line: LinePos) {
+    let state = self.derive_line_state(line);
+    self.line_counts.update(self.line_states[line], state);
+    self.line_states[line] = state;
+  }
 
-      self.line_states[line] = line_state;
-      if let Some(p) = line_state.winner() {
-        self.board_state = TileBoardState::Won(p);
+  fn derive_line_state(&self, line: LinePos) -> LineState<K> {
+    const { assert!(N <= MAX_BOARD_SIDE_LENGTH as usize && 1 <= K && K <= N) };
+    line
+      .iter::<K>()
+      .map(|pos| LineState::from(self.tile_states[pos].tile_state()))
+      .reduce(|a, b| a.combine(b))
+      .unwrap()
+  }
+
+  /// Derives the board state from the counts of the line states.
+  /// A won board stays with its winner, as long as one of the winner's lines remains,
+  /// even if the other player completes a line too.
+  /// Otherwise the board goes to the player with a line. A single placement completes lines of
+  /// only one player, so only boards built from tiles need to be given their winner.
+  fn derive_board_state(&self) -> TileBoardState {
+    let counts = self.line_counts;
+    if let TileBoardState::Won(p) = self.board_state {
+      if counts.has_line(p) {
+        return TileBoardState::Won(p);
       }
     }
-    if LinePos::all().all(|line| self.line_states[line].is_drawn()) {
-      self.board_state = TileBoardState::Drawn;
-    }
-    if LinePos::all().all(|line| self.line_states[line].is_fully_drawn()) {
-      self.board_state = TileBoardState::FullyDrawn;
+    if let Some(p) = PLAYERS.into_iter().find(|&p| counts.has_line(p)) {
+      TileBoardState::Won(p)
+    } else if counts.all_fully_drawn::<N, K>() {
+      TileBoardState::FullyDrawn
+    } else if counts.all_drawn::<N, K>() {
+      TileBoardState::Drawn
+    } else {
+      TileBoardState::Free
     }
   }
 }
 
+/// Unchecked serialized form of a [`GenericBoard`].
+#[derive(Deserialize)]
+#[serde(rename = "GenericBoard")]
+struct GenericBoardRepr<TileType, const N: usize, const K: usize> {
+  tile_states: TileStates<TileType, N>,
+  line_states: LineStates<N, K>,
+  board_state: TileBoardState,
+}
+
+#[allow(private_bounds)]
+impl<TileType: TileTrait, const N: usize, const K: usize> TryFrom<GenericBoardRepr<TileType, N, K>>
+  for GenericBoard<TileType, N, K>
+{
+  type Error = BoardInvariantError;
+  fn try_from(repr: GenericBoardRepr<TileType, N, K>) -> Result<Self, Self::Error> {
+    // the tiles have already been checked by their own deserialization
+    let board = Self {
+      tile_states: repr.tile_states,
+      line_counts: LineCounts::of(&repr.line_states),
+      line_states: repr.line_states,
+      board_state: repr.board_state,
+    };
+    board.validate_local()?;
+    Ok(board)
+  }
+}
+
 /// A `TileBoardState` is a state inside the board hierarchy.
 /// It can be seen as both a tile state and a board state,
 /// depending on what level of the hierarchy you are considering.
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TileBoardState {
   #[default]
   Free,
@@ -121,6 +312,12 @@ impl TileBoardState {
   pub fn is_fully_drawn(self) -> bool {
     matches!(self, Self::FullyDrawn)
   }
+  pub fn winner(self) -> Option<PlayerSymbol> {
+    match self {
+      Self::Won(p) => Some(p),
+      _ => None,
+    }
+  }
 
   pub fn is_decided(self) -> bool {
     self.is_won() || self.is_drawn()
@@ -139,19 +336,53 @@ impl From<TrivialTileState> for TileBoardState {
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlaceSymbolError {
   BoardNotPlaceable,
   TrivialTileNotFree,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoveSymbolError {
+  TrivialTileFree,
+}
+
+/// The derived states of a board do not match its tile states, or cannot be derived from them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardInvariantError {
+  LineStatesMismatch,
+  BoardStateMismatch,
+  /// both players have a line, but no winner was given
+  UndecidedWinner,
+}
+impl std::fmt::Display for BoardInvariantError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::LineStatesMismatch => write!(f, "line states do not match the tile states"),
+      Self::BoardStateMismatch => write!(f, "board state does not match the line states"),
+      Self::UndecidedWinner => write!(f, "both players have a line, but no winner is given"),
+    }
+  }
+}
+impl std::error::Error for BoardInvariantError {}
+
 #[cfg(test)]
 mod test {
   use std::str::FromStr;
 
+  use rand::prelude::*;
+
   use super::{
+    line::LineStates,
     tile::{TilePos, TrivialTileState},
-    TileBoardState, TrivialBoard, BOARD_AREA,
+    BoardInvariantError, GenericBoard, TileBoardState, TrivialBoard, BOARD_AREA,
+  };
+  use crate::{
+    bitboard::BitBoard,
+    game::RoundState,
+    nested::Depth3RoundState,
+    rules::{RuleSet, WonBoardRule},
+    GlobalPos, InnerBoard, OuterBoard, OuterBoardBackend, OuterPos, PlayerSymbol, PLAYERS,
   };
 
   #[derive(Debug)]
@@ -198,4 +429,225 @@ mod test {
     .unwrap();
     assert_eq!(board.board_state, TileBoardState::Drawn);
   }
+
+  #[test]
+  fn check_remove_symbol() {
+    let mut board = r#"
+      XX_
+      OO_
+      ___
+      "#
+    .parse::<TrivialBoard>()
+    .unwrap();
+    let before = board.clone();
+
+    board
+      .try_place_symbol(TilePos::new(0, 2).iter(), PlayerSymbol::X)
+      .unwrap();
+    assert_eq!(board.board_state, TileBoardState::Won(PlayerSymbol::X));
+
+    let symbol = board.try_remove_symbol(TilePos::new(0, 2).iter()).unwrap();
+    assert_eq!(symbol, PlayerSymbol::X);
+    assert_eq!(board, before);
+    assert!(board.try_remove_symbol(TilePos::new(0, 2).iter()).is_err());
+  }
+
+  #[test]
+  fn check_deserialization_rebuilds_derived_states() {
+    let board = r#"
+      XX_
+      OO_
+      ___
+      "#
+    .parse::<TrivialBoard>()
+    .unwrap();
+    let serialized = ron::to_string(&board).unwrap();
+    assert_eq!(ron::from_str::<TrivialBoard>(&serialized).unwrap(), board);
+
+    let tampered = serialized.replace("board_state:Free", "board_state:Won(X)");
+    assert_ne!(tampered, serialized);
+    assert!(ron::from_str::<TrivialBoard>(&tampered).is_err());
+  }
+
+  #[test]
+  fn check_k_in_a_row() {
+    let mut board = GenericBoard::<TrivialTileState, 5, 4>::default();
+    for i in 1..4 {
+      board
+        .try_place_symbol(TilePos::new(i, i).iter(), PlayerSymbol::X)
+        .unwrap();
+    }
+    assert_eq!(board.board_state(), TileBoardState::Free);
+    board
+      .try_place_symbol(TilePos::new(4, 4).iter(), PlayerSymbol::X)
+      .unwrap();
+    assert_eq!(board.board_state(), TileBoardState::Won(PlayerSymbol::X));
+
+    let serialized = ron::to_string(&board).unwrap();
+    let deserialized: GenericBoard<TrivialTileState, 5, 4> = ron::from_str(&serialized).unwrap();
+    assert_eq!(deserialized, board);
+    assert!(ron::from_str::<TrivialBoard>(&serialized).is_err());
+  }
+
+  #[test]
+  fn check_validate_detects_mismatches() {
+    let board = r#"
+      XX_
+      OO_
+      ___
+      "#
+    .parse::<TrivialBoard>()
+    .unwrap();
+    assert_eq!(board.validate(), Ok(()));
+    assert_eq!(
+      TrivialBoard::from_tiles(|pos| *board.tile_state(pos), None),
+      Ok(board.clone())
+    );
+
+    let mut corrupted = board.clone();
+    corrupted.board_state = TileBoardState::Won(PlayerSymbol::O);
+    assert_eq!(
+      corrupted.validate(),
+      Err(BoardInvariantError::BoardStateMismatch)
+    );
+
+    let mut corrupted = board.clone();
+    corrupted.line_states = LineStates::default();
+    assert_eq!(
+      corrupted.validate(),
+      Err(BoardInvariantError::LineStatesMismatch)
+    );
+
+    let outer_board = OuterBoard::from_tiles(
+      |pos| match pos == TilePos::new(1, 1) {
+        true => corrupted.clone(),
+        false => TrivialBoard::default(),
+      },
+      None,
+    )
+    .unwrap();
+    assert_eq!(
+      outer_board.validate(),
+      Err(BoardInvariantError::LineStatesMismatch)
+    );
+  }
+
+  #[test]
+  fn check_both_players_with_a_line() {
+    let board = r#"
+      OOO
+      ___
+      XXX
+      "#;
+    let tiles: Vec<_> = board
+      .chars()
+      .filter(|c| !c.is_whitespace())
+      .map(|c| TrivialTileState::from_char(c).unwrap())
+      .collect();
+    let from_tiles = |winner| TrivialBoard::from_tiles(|pos| tiles[pos.linear_idx()], winner);
+    assert_eq!(from_tiles(None), Err(BoardInvariantError::UndecidedWinner));
+    for p in PLAYERS {
+      let board = from_tiles(Some(p)).unwrap();
+      assert!(board.is_disputed());
+      assert_eq!(board.board_state(), TileBoardState::Won(p));
+      assert_eq!(board.validate(), Ok(()));
+    }
+
+    // X completing a line in a sub-board won by O keeps O as the winner
+    let won_playable = RuleSet {
+      won_board: WonBoardRule::Playable,
+      ..RuleSet::default()
+    };
+    let notation = "OOO3XX1/XX7/9/O8/9/9/9/9/9 X a3 won-playable";
+    let mut round: RoundState = notation.parse().unwrap();
+    let mut bit_round: RoundState<BitBoard> = notation.parse().unwrap();
+    assert_eq!(round.rules(), won_playable);
+    let last_tile = GlobalPos::new(2, 1);
+    round.try_play_move(PlayerSymbol::X, last_tile).unwrap();
+    bit_round.try_play_move(PlayerSymbol::X, last_tile).unwrap();
+    let outer_pos = OuterPos::new(0, 0);
+    assert!(round.board().is_board_disputed(Some(outer_pos)));
+
+    // the winner survives the notation, rebuilding from the tiles, conversions and serde
+    let notation = round.to_string();
+    assert_eq!(
+      notation,
+      "OOO3XX1/XXX6/9/O8/9/9/9/9/9 O c2 won-playable a3:O"
+    );
+    let parsed: RoundState = notation.parse().unwrap();
+    assert_eq!(parsed.zobrist_hash(), round.zobrist_hash());
+    let rebuilt = OuterBoard::from_trivial_tiles(
+      |global_pos| round.board().trivial_tile(global_pos),
+      |outer_pos| round.board().winner(outer_pos),
+    )
+    .unwrap();
+    let serialized = ron::to_string(bit_round.board()).unwrap();
+    let deserialized: BitBoard = ron::from_str(&serialized).unwrap();
+    for state in [
+      round.board().sub_board_state(outer_pos),
+      bit_round.board().sub_board_state(outer_pos),
+      parsed.board().sub_board_state(outer_pos),
+      rebuilt.sub_board_state(outer_pos),
+      BitBoard::from(&rebuilt).sub_board_state(outer_pos),
+      deserialized.sub_board_state(outer_pos),
+    ] {
+      assert_eq!(state, TileBoardState::Won(PlayerSymbol::O));
+    }
+    assert_eq!(bit_round.zobrist_hash(), round.zobrist_hash());
+    assert_eq!(bit_round.to_string(), notation);
+
+    // the same tiles won by X are a different position
+    let x_won: RoundState = notation.replace("a3:O", "a3:X").parse().unwrap();
+    assert_eq!(
+      x_won.board().sub_board_state(outer_pos),
+      TileBoardState::Won(PlayerSymbol::X)
+    );
+    assert_ne!(x_won.zobrist_hash(), round.zobrist_hash());
+  }
+
+  #[test]
+  fn check_invariants_hold_during_random_games() {
+    let mut rng = StdRng::seed_from_u64(9);
+    let won_playable = RuleSet {
+      won_board: WonBoardRule::Playable,
+      ..RuleSet::default()
+    };
+    for rules in [RuleSet::default(), won_playable] {
+      for _ in 0..10 {
+        let mut round = RoundState::new(rng.gen(), rules);
+        while round.outcome().is_none() {
+          let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+          round
+            .try_play_move(round.current_player(), chosen_tile)
+            .unwrap();
+
+          let board = round.board();
+          assert_eq!(board.validate(), Ok(()));
+          let rebuilt = OuterBoard::from_tiles(
+            |outer_pos| {
+              let sub_board = board.tile_state(outer_pos);
+              InnerBoard::from_tiles(
+                |inner_pos| *sub_board.tile_state(inner_pos),
+                sub_board.board_state().winner(),
+              )
+              .unwrap()
+            },
+            board.board_state().winner(),
+          )
+          .unwrap();
+          assert_eq!(&rebuilt, board);
+        }
+        while round.undo_move().is_some() {
+          assert_eq!(round.board().validate(), Ok(()));
+        }
+      }
+    }
+
+    let mut round = Depth3RoundState::new(rng.gen());
+    while round.outcome().is_none() {
+      let chosen = round.legal_moves().choose(&mut rng).unwrap();
+      round.try_play_move(round.current_player(), chosen).unwrap();
+      assert_eq!(round.board().validate(), Ok(()));
+    }
+  }
 }
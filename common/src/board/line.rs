@@ -1,18 +1,21 @@
-use super::{tile::TilePos, TileBoardState, BOARD_SIDE_LENGTH};
+use std::ops::Range;
+
+use super::{tile::TilePos, TileBoardState};
 use crate::PlayerSymbol;
 
-const NLINES: usize = 2 * BOARD_SIDE_LENGTH as usize + 2;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
-pub struct LineState {
+/// The state of a line of `K` tiles.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineState<const K: usize = 3> {
   occupant: Option<PlayerSymbol>,
   noccupied: u8,
 }
 
 #[allow(dead_code)]
-impl LineState {
+impl<const K: usize> LineState<K> {
   pub fn new(occupant: Option<PlayerSymbol>, noccupied: u8) -> Self {
-    assert!(noccupied <= BOARD_SIDE_LENGTH);
+    assert!(noccupied as usize <= K);
     assert!(noccupied != 0 || occupant.is_none());
     Self {
       occupant,
@@ -28,14 +31,14 @@ impl LineState {
     Self::new(Some(player), count)
   }
   pub fn won(player: PlayerSymbol) -> Self {
-    Self::new(Some(player), BOARD_SIDE_LENGTH)
+    Self::new(Some(player), K as u8)
   }
   pub fn drawn(count: u8) -> Self {
     assert!(count != 0);
     Self::new(None, count)
   }
   pub fn fully_drawn() -> Self {
-    Self::new(None, BOARD_SIDE_LENGTH)
+    Self::new(None, K as u8)
   }
 
   pub fn is_free(self) -> bool {
@@ -51,13 +54,22 @@ impl LineState {
     self.occupant.is_none() && self.noccupied != 0
   }
   pub fn is_won(self) -> bool {
-    self.occupant.is_some() && self.noccupied == BOARD_SIDE_LENGTH
+    self.occupant.is_some() && self.noccupied as usize == K
   }
   pub fn is_drawn(self) -> bool {
     self.occupant.is_none() && self.noccupied != 0
   }
   pub fn is_fully_drawn(self) -> bool {
-    self.occupant.is_none() && self.noccupied == BOARD_SIDE_LENGTH
+    self.occupant.is_none() && self.noccupied as usize == K
+  }
+
+  /// The only player occupying tiles of the line, if there is one.
+  pub fn occupant(self) -> Option<PlayerSymbol> {
+    self.occupant
+  }
+  /// The number of occupied tiles of the line.
+  pub fn noccupied(self) -> u8 {
+    self.noccupied
   }
 
   pub fn winner(self) -> Option<PlayerSymbol> {
@@ -83,7 +95,7 @@ impl LineState {
   }
 }
 
-impl From<TileBoardState> for LineState {
+impl<const K: usize> From<TileBoardState> for LineState<K> {
   fn from(t: TileBoardState) -> Self {
     match t {
       TileBoardState::Free => Self::free(),
@@ -93,124 +105,232 @@ impl From<TileBoardState> for LineState {
   }
 }
 
-/// A type of line in the board.
-///
-/// guranteed to be valid
-#[derive(Clone, Copy)]
-pub(crate) enum LinePos {
-  XAxis(u8),
-  YAxis(u8),
+/// The direction of a line, given as the step between its tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LineDir {
+  XAxis,
+  YAxis,
   MainDiagonal,
   AntiDiagonal,
 }
-impl LinePos {
-  pub(crate) fn x_axis(y: u8) -> Self {
-    assert!(y < BOARD_SIDE_LENGTH);
-    Self::XAxis(y)
-  }
-  pub(crate) fn y_axis(x: u8) -> Self {
-    assert!(x < BOARD_SIDE_LENGTH);
-    Self::YAxis(x)
-  }
+impl LineDir {
+  const ALL: [Self; 4] = [
+    Self::XAxis,
+    Self::YAxis,
+    Self::MainDiagonal,
+    Self::AntiDiagonal,
+  ];
 
-  pub(crate) fn idx(self) -> usize {
-    let line_length = BOARD_SIDE_LENGTH as usize;
+  fn step(self) -> [i8; 2] {
     match self {
-      LinePos::XAxis(y) => {
-        assert!(y < BOARD_SIDE_LENGTH);
-        y as usize
-      }
-      LinePos::YAxis(x) => {
-        assert!(x < BOARD_SIDE_LENGTH);
-        x as usize + line_length
-      }
-      LinePos::MainDiagonal => 2 * line_length,
-      LinePos::AntiDiagonal => 2 * line_length + 1,
-    }
-  }
-  pub(crate) fn from_idx(idx: usize) -> Self {
-    assert!(idx < NLINES);
-    let idx = idx as u8;
-    if (0..BOARD_SIDE_LENGTH).contains(&idx) {
-      LinePos::XAxis(idx)
-    } else if (BOARD_SIDE_LENGTH..2 * BOARD_SIDE_LENGTH).contains(&idx) {
-      LinePos::YAxis(idx - BOARD_SIDE_LENGTH)
-    } else if idx == 2 * BOARD_SIDE_LENGTH {
-      LinePos::MainDiagonal
-    } else {
-      LinePos::AntiDiagonal
+      Self::XAxis => [1, 0],
+      Self::YAxis => [0, 1],
+      Self::MainDiagonal => [1, 1],
+      Self::AntiDiagonal => [1, -1],
     }
   }
-  pub(crate) fn iter(self) -> LineTilePosIter {
-    LineTilePosIter {
-      line_pos: self,
-      i: 0,
-    }
+}
+
+/// A line of `K` consecutive tiles in a board of side length `N`,
+/// given by its direction and the tile it starts at.
+///
+/// guranteed to be valid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LinePos {
+  dir: LineDir,
+  start: TilePos,
+}
+impl LinePos {
+  /// Returns the line, if it fits into a board of side length `N`.
+  fn try_new<const N: usize, const K: usize>(dir: LineDir, start: TilePos) -> Option<Self> {
+    let [dx, dy] = dir.step();
+    let end = |v: u8, d: i8| v as isize + d as isize * (K as isize - 1);
+    let fits = |v: isize| (0..N as isize).contains(&v);
+    let fits = (start.x() as usize) < N
+      && (start.y() as usize) < N
+      && fits(end(start.x(), dx))
+      && fits(end(start.y(), dy));
+    fits.then_some(Self { dir, start })
   }
 
-  pub(crate) fn all_through_point(pos: TilePos) -> impl Iterator<Item = Self> {
-    let mut l = vec![Self::x_axis(pos.y()), Self::y_axis(pos.x())];
-    if pos.x() == pos.y() {
-      l.push(Self::MainDiagonal);
-    }
-    if pos.x() + pos.y() == 2 {
-      l.push(Self::AntiDiagonal);
-    }
-    l.into_iter()
+  pub(crate) fn iter<const K: usize>(self) -> impl Iterator<Item = TilePos> {
+    let [dx, dy] = self.dir.step();
+    (0..K as i8).map(move |i| {
+      TilePos::new(
+        (self.start.x() as i8 + i * dx) as u8,
+        (self.start.y() as i8 + i * dy) as u8,
+      )
+    })
   }
 
-  pub(crate) fn all() -> impl Iterator<Item = Self> {
-    (0..NLINES).map(Self::from_idx)
+  /// All lines of a board with side length `N` containing the given tile.
+  pub(crate) fn all_through_point<const N: usize, const K: usize>(
+    pos: TilePos,
+  ) -> impl Iterator<Item = Self> {
+    LineDir::ALL.into_iter().flat_map(move |dir| {
+      let [dx, dy] = dir.step();
+      (0..K as i8).filter_map(move |i| {
+        let x = pos.x() as i8 - i * dx;
+        let y = pos.y() as i8 - i * dy;
+        (x >= 0 && y >= 0)
+          .then(|| Self::try_new::<N, K>(dir, TilePos::new(x as u8, y as u8)))
+          .flatten()
+      })
+    })
   }
-}
 
-/// Iterator yielding `TilePos` along a line.
-pub(crate) struct LineTilePosIter {
-  line_pos: LinePos,
-  i: u8,
-}
-impl Iterator for LineTilePosIter {
-  type Item = TilePos;
-
-  fn next(&mut self) -> Option<Self::Item> {
-    if self.i >= 3 {
-      return None;
-    }
-    let i = self.i;
-    self.i += 1;
-    Some(match self.line_pos {
-      LinePos::XAxis(y) => TilePos::new(i, y),
-      LinePos::YAxis(x) => TilePos::new(x, i),
-      LinePos::MainDiagonal => TilePos::new(i, i),
-      LinePos::AntiDiagonal => TilePos::new(i, 2 - i),
+  /// The coordinates of the start tiles of all lines in the direction that fit into a board of side length `N`.
+  fn start_ranges<const N: usize, const K: usize>(dir: LineDir) -> [Range<u8>; 2] {
+    let (n, k) = (N as u8, K as u8);
+    let [dx, dy] = dir.step();
+    let x_range = match dx {
+      0 => 0..n,
+      _ => 0..n - k + 1,
+    };
+    let y_range = match dy {
+      0 => 0..n,
+      1 => 0..n - k + 1,
+      _ => k - 1..n,
+    };
+    [x_range, y_range]
+  }
+
+  /// The number of lines of a board with side length `N`:
+  /// `N` rows and columns of `N - K + 1` lines each and `N - K + 1` squared lines per diagonal.
+  pub(crate) const fn count<const N: usize, const K: usize>() -> usize {
+    let nstarts = N + 1 - K;
+    2 * N * nstarts + 2 * nstarts * nstarts
+  }
+
+  /// All lines of a board with side length `N`.
+  pub(crate) fn all<const N: usize, const K: usize>() -> impl Iterator<Item = Self> {
+    LineDir::ALL.into_iter().flat_map(|dir| {
+      let [x_range, y_range] = Self::start_ranges::<N, K>(dir);
+      x_range.flat_map(move |x| {
+        y_range.clone().map(move |y| Self {
+          dir,
+          start: TilePos::new(x, y),
+        })
+      })
     })
   }
 }
 
-/// A container of tile states for a board.
-/// Allows for easy indexing using TilePos.
-#[derive(Debug, Default)]
-pub struct LineStates([LineState; 8]);
-impl std::ops::Index<LinePos> for LineStates {
-  type Output = LineState;
+/// A container of line states for a board.
+/// Stores the states inline by direction and start tile, so boards stay cheap to copy.
+/// The slots of lines that do not fit into the board stay free.
+/// Allows for easy indexing using LinePos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineStates<const N: usize = 3, const K: usize = 3>([[[LineState<K>; N]; N]; 4]);
+impl<const N: usize, const K: usize> Default for LineStates<N, K> {
+  fn default() -> Self {
+    Self([[[LineState::default(); N]; N]; 4])
+  }
+}
+impl<const N: usize, const K: usize> std::ops::Index<LinePos> for LineStates<N, K> {
+  type Output = LineState<K>;
   fn index(&self, line: LinePos) -> &Self::Output {
-    &self.0[line.idx()]
+    &self.0[line.dir as usize][line.start.x() as usize][line.start.y() as usize]
   }
 }
-impl std::ops::IndexMut<LinePos> for LineStates {
+impl<const N: usize, const K: usize> std::ops::IndexMut<LinePos> for LineStates<N, K> {
   fn index_mut(&mut self, line: LinePos) -> &mut Self::Output {
-    &mut self.0[line.idx()]
+    &mut self.0[line.dir as usize][line.start.x() as usize][line.start.y() as usize]
+  }
+}
+
+/// Serialized as the list of states of all lines that fit, in the order of [`LinePos::all`].
+impl<const N: usize, const K: usize> Serialize for LineStates<N, K> {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(LinePos::all::<N, K>().map(|line| self[line]))
+  }
+}
+impl<'de, const N: usize, const K: usize> Deserialize<'de> for LineStates<N, K> {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let states = Vec::<LineState<K>>::deserialize(deserializer)?;
+    let nlines = LinePos::count::<N, K>();
+    if states.len() != nlines {
+      return Err(serde::de::Error::invalid_length(
+        states.len(),
+        &format!("{} line states", nlines).as_str(),
+      ));
+    }
+    let mut line_states = Self::default();
+    for (line, state) in LinePos::all::<N, K>().zip(states) {
+      line_states[line] = state;
+    }
+    Ok(line_states)
+  }
+}
+
+/// How many lines of a board are in which state.
+/// Allows deriving the board state without looking at every line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LineCounts {
+  /// won lines, per player
+  won: [u16; 2],
+  /// drawn lines, including the fully drawn ones
+  drawn: u16,
+  fully_drawn: u16,
+}
+impl LineCounts {
+  /// Counts the states of all lines of a board.
+  pub(crate) fn of<const N: usize, const K: usize>(line_states: &LineStates<N, K>) -> Self {
+    let mut counts = Self::default();
+    for line in LinePos::all::<N, K>() {
+      counts.add(line_states[line]);
+    }
+    counts
+  }
+
+  /// Replaces a counted state of a line by its new state.
+  pub(crate) fn update<const K: usize>(&mut self, old: LineState<K>, new: LineState<K>) {
+    if old != new {
+      self.remove(old);
+      self.add(new);
+    }
+  }
+
+  fn add<const K: usize>(&mut self, state: LineState<K>) {
+    self.change(state, |count| *count += 1);
+  }
+  fn remove<const K: usize>(&mut self, state: LineState<K>) {
+    self.change(state, |count| *count -= 1);
+  }
+  fn change<const K: usize>(&mut self, state: LineState<K>, mut f: impl FnMut(&mut u16)) {
+    if let Some(p) = state.winner() {
+      f(&mut self.won[p.idx()]);
+    }
+    if state.is_drawn() {
+      f(&mut self.drawn);
+    }
+    if state.is_fully_drawn() {
+      f(&mut self.fully_drawn);
+    }
+  }
+
+  pub(crate) fn has_line(self, player: PlayerSymbol) -> bool {
+    self.won[player.idx()] != 0
+  }
+  pub(crate) fn all_drawn<const N: usize, const K: usize>(self) -> bool {
+    self.drawn as usize == LinePos::count::<N, K>()
+  }
+  pub(crate) fn all_fully_drawn<const N: usize, const K: usize>(self) -> bool {
+    self.fully_drawn as usize == LinePos::count::<N, K>()
   }
 }
 
 #[cfg(test)]
 mod test {
-  use super::LineState;
-  use crate::{board::BOARD_SIDE_LENGTH, PLAYERS};
+  use super::{LinePos, LineState, LineStates};
+  use crate::{
+    board::{tile::TilePos, BOARD_SIDE_LENGTH},
+    PLAYERS,
+  };
 
   #[test]
   fn check_line_state_cominator() {
-    use LineState as L;
+    type L = LineState;
 
     assert_eq!(L::free().combine(L::free()), L::free());
 
@@ -242,4 +362,46 @@ mod test {
       );
     }
   }
+
+  #[test]
+  fn check_line_positions() {
+    assert_eq!(LinePos::all::<3, 3>().count(), 8);
+    assert_eq!(LinePos::all::<4, 4>().count(), 10);
+    assert_eq!(LinePos::all::<5, 4>().count(), 28);
+
+    fn check_slots<const N: usize, const K: usize>() {
+      assert_eq!(LinePos::count::<N, K>(), LinePos::all::<N, K>().count());
+      // every line has its own slot
+      let mut line_states = LineStates::<N, K>::default();
+      for (i, line) in LinePos::all::<N, K>().enumerate() {
+        assert!(line.iter::<K>().all(|p| p.x() < N as u8 && p.y() < N as u8));
+        assert!(line_states[line].is_free());
+        line_states[line] = LineState::drawn(1 + (i % K) as u8);
+      }
+      assert!(LinePos::all::<N, K>()
+        .enumerate()
+        .all(|(i, line)| line_states[line] == LineState::drawn(1 + (i % K) as u8)));
+    }
+    check_slots::<3, 3>();
+    check_slots::<4, 4>();
+    check_slots::<5, 4>();
+    check_slots::<5, 2>();
+
+    fn check_through_point<const N: usize, const K: usize>() {
+      for x in 0..N as u8 {
+        for y in 0..N as u8 {
+          let pos = TilePos::new(x, y);
+          let through: Vec<_> = LinePos::all_through_point::<N, K>(pos).collect();
+          let expected: Vec<_> = LinePos::all::<N, K>()
+            .filter(|line| line.iter::<K>().any(|p| p == pos))
+            .collect();
+          assert_eq!(through.len(), expected.len());
+          assert!(expected.iter().all(|line| through.contains(line)));
+        }
+      }
+    }
+    check_through_point::<3, 3>();
+    check_through_point::<4, 4>();
+    check_through_point::<5, 4>();
+  }
 }
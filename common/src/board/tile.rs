@@ -1,10 +1,25 @@
-use super::{GenericBoard, PlaceSymbolError, TileBoardState};
+use super::{
+  BoardInvariantError, GenericBoard, PlaceSymbolError, RemoveSymbolError, TileBoardState,
+  BOARD_SIDE_LENGTH, MAX_BOARD_SIDE_LENGTH,
+};
 
-use crate::PlayerSymbol;
+use crate::{impl_pos_conversions, PlayerSymbol};
+
+use serde::{Deserialize, Serialize};
 
 /// Trait to allow recursion on inductive tile hierarchy.
 pub(crate) trait TileTrait {
+  /// number of board levels above the trivial tiles
+  const DEPTH: usize;
+  /// side length of the board, 0 for the trivial tile
+  const SIDE_LENGTH: usize;
+  /// whether all board levels of the hierarchy share the same side length
+  const UNIFORM_SIDE_LENGTH: bool;
+
   fn tile_state(&self) -> TileBoardState;
+  /// Checks the derived states of the whole tile hierarchy.
+  fn validate_tile(&self) -> Result<(), BoardInvariantError>;
+  fn tile_state_in_tile(&self, pos_iter: impl Iterator<Item = TilePos>) -> TileBoardState;
   fn trivial_tile_in_tile(&self, pos_iter: impl Iterator<Item = TilePos>) -> TrivialTileState;
 
   fn could_place_symbol_in_tile(&self, pos_iter: impl Iterator<Item = TilePos>) -> bool;
@@ -13,13 +28,35 @@ pub(crate) trait TileTrait {
     pos_iter: impl Iterator<Item = TilePos>,
     symbol: PlayerSymbol,
   ) -> Result<(), PlaceSymbolError>;
+  fn try_force_place_symbol_in_tile(
+    &mut self,
+    pos_iter: impl Iterator<Item = TilePos>,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError>;
+  fn try_remove_symbol_in_tile(
+    &mut self,
+    pos_iter: impl Iterator<Item = TilePos>,
+  ) -> Result<PlayerSymbol, RemoveSymbolError>;
 }
 
 /// Induction step of the inductive tile hierarchy.
-impl<TileType: TileTrait> TileTrait for GenericBoard<TileType> {
+impl<TileType: TileTrait, const N: usize, const K: usize> TileTrait
+  for GenericBoard<TileType, N, K>
+{
+  const DEPTH: usize = TileType::DEPTH + 1;
+  const SIDE_LENGTH: usize = N;
+  const UNIFORM_SIDE_LENGTH: bool =
+    TileType::SIDE_LENGTH == 0 || (TileType::SIDE_LENGTH == N && TileType::UNIFORM_SIDE_LENGTH);
+
   fn tile_state(&self) -> TileBoardState {
     self.board_state()
   }
+  fn validate_tile(&self) -> Result<(), BoardInvariantError> {
+    GenericBoard::validate(self)
+  }
+  fn tile_state_in_tile(&self, pos_iter: impl Iterator<Item = TilePos>) -> TileBoardState {
+    GenericBoard::tile_state_at(self, pos_iter)
+  }
   fn trivial_tile_in_tile(&self, pos_iter: impl Iterator<Item = TilePos>) -> TrivialTileState {
     GenericBoard::trivial_tile(self, pos_iter)
   }
@@ -34,10 +71,23 @@ impl<TileType: TileTrait> TileTrait for GenericBoard<TileType> {
   ) -> Result<(), PlaceSymbolError> {
     GenericBoard::try_place_symbol(self, pos_iter, symbol)
   }
+  fn try_force_place_symbol_in_tile(
+    &mut self,
+    pos_iter: impl Iterator<Item = TilePos>,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError> {
+    GenericBoard::try_force_place_symbol(self, pos_iter, symbol)
+  }
+  fn try_remove_symbol_in_tile(
+    &mut self,
+    pos_iter: impl Iterator<Item = TilePos>,
+  ) -> Result<PlayerSymbol, RemoveSymbolError> {
+    GenericBoard::try_remove_symbol(self, pos_iter)
+  }
 }
 
 /// The trivial tile is at the bottom of the tile hierarchy.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TrivialTileState {
   #[default]
   Free,
@@ -46,9 +96,20 @@ pub enum TrivialTileState {
 
 /// Base case of the inductive tile hierarchy.
 impl TileTrait for TrivialTileState {
+  const DEPTH: usize = 0;
+  const SIDE_LENGTH: usize = 0;
+  const UNIFORM_SIDE_LENGTH: bool = true;
+
   fn tile_state(&self) -> TileBoardState {
     (*self).into()
   }
+  fn validate_tile(&self) -> Result<(), BoardInvariantError> {
+    Ok(())
+  }
+  fn tile_state_in_tile(&self, mut pos_iter: impl Iterator<Item = TilePos>) -> TileBoardState {
+    assert!(pos_iter.next().is_none());
+    self.tile_state()
+  }
 
   fn trivial_tile_in_tile(&self, mut pos_iter: impl Iterator<Item = TilePos>) -> TrivialTileState {
     assert!(pos_iter.next().is_none());
@@ -72,6 +133,26 @@ impl TileTrait for TrivialTileState {
       Err(PlaceSymbolError::TrivialTileNotFree)
     }
   }
+  fn try_force_place_symbol_in_tile(
+    &mut self,
+    pos_iter: impl Iterator<Item = TilePos>,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError> {
+    self.try_place_symbol_in_tile(pos_iter, symbol)
+  }
+  fn try_remove_symbol_in_tile(
+    &mut self,
+    mut pos_iter: impl Iterator<Item = TilePos>,
+  ) -> Result<PlayerSymbol, RemoveSymbolError> {
+    assert!(pos_iter.next().is_none());
+    match *self {
+      TrivialTileState::Won(symbol) => {
+        *self = TrivialTileState::Free;
+        Ok(symbol)
+      }
+      TrivialTileState::Free => Err(RemoveSymbolError::TrivialTileFree),
+    }
+  }
 }
 
 impl TrivialTileState {
@@ -100,13 +181,16 @@ impl TrivialTileState {
 /// This position is purely local and only describes the tiles
 /// location in relation to it's direct board.
 ///
-/// Instance guranteed to be valid.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Instance guranteed to be valid for boards up to [`MAX_BOARD_SIDE_LENGTH`].
+/// Boards with a smaller side length reject positions outside of them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "[u8; 2]", into = "[u8; 2]")]
 pub struct TilePos([u8; 2]);
+impl_pos_conversions!(TilePos, MAX_BOARD_SIDE_LENGTH);
 
 impl TilePos {
   pub const fn new_arr(arr: [u8; 2]) -> Self {
-    assert!(arr[0] < 3 && arr[1] < 3);
+    assert!(arr[0] < MAX_BOARD_SIDE_LENGTH && arr[1] < MAX_BOARD_SIDE_LENGTH);
     Self(arr)
   }
   pub const fn new(x: u8, y: u8) -> Self {
@@ -119,11 +203,24 @@ impl TilePos {
   pub fn y(self) -> u8 {
     self.0[1]
   }
+
+  /// Index of the tile in a board of the given side length.
+  pub fn linear_idx_in(self, side_length: u8) -> usize {
+    assert!(self.x() < side_length && self.y() < side_length);
+    self.x() as usize * side_length as usize + self.y() as usize
+  }
+  pub fn from_linear_idx_in(idx: usize, side_length: u8) -> Self {
+    Self::new(
+      (idx / side_length as usize) as u8,
+      (idx % side_length as usize) as u8,
+    )
+  }
+  /// Index of the tile in a board of the standard side length [`BOARD_SIDE_LENGTH`].
   pub fn linear_idx(self) -> usize {
-    (self.x() * 3 + self.y()) as usize
+    self.linear_idx_in(BOARD_SIDE_LENGTH)
   }
   pub fn from_linear_idx(idx: usize) -> Self {
-    Self::new(idx as u8 / 3, idx as u8 % 3)
+    Self::from_linear_idx_in(idx, BOARD_SIDE_LENGTH)
   }
 
   pub fn iter(self) -> impl Iterator<Item = Self> {
@@ -131,18 +228,53 @@ impl TilePos {
   }
 }
 
-/// A container of tile states for a board.
+/// A container of tile states for a board of side length `N`.
 /// Allows for easy indexing using TilePos.
-#[derive(Debug, Default)]
-pub struct TileStates<T>([T; 9]);
-impl<T> std::ops::Index<TilePos> for TileStates<T> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileStates<T, const N: usize = 3>([[T; N]; N]);
+impl<T, const N: usize> TileStates<T, N> {
+  pub(crate) fn from_fn(mut f: impl FnMut(TilePos) -> T) -> Self {
+    Self(std::array::from_fn(|x| {
+      std::array::from_fn(|y| f(TilePos::new(x as u8, y as u8)))
+    }))
+  }
+  fn iter(&self) -> impl Iterator<Item = &T> {
+    self.0.iter().flatten()
+  }
+}
+impl<T: Default, const N: usize> Default for TileStates<T, N> {
+  fn default() -> Self {
+    Self::from_fn(|_| T::default())
+  }
+}
+impl<T, const N: usize> std::ops::Index<TilePos> for TileStates<T, N> {
   type Output = T;
   fn index(&self, pos: TilePos) -> &Self::Output {
-    &self.0[pos.linear_idx()]
+    &self.0[pos.x() as usize][pos.y() as usize]
   }
 }
-impl<T> std::ops::IndexMut<TilePos> for TileStates<T> {
+impl<T, const N: usize> std::ops::IndexMut<TilePos> for TileStates<T, N> {
   fn index_mut(&mut self, pos: TilePos) -> &mut Self::Output {
-    &mut self.0[pos.linear_idx()]
+    &mut self.0[pos.x() as usize][pos.y() as usize]
+  }
+}
+
+/// Serialized as the list of all `N * N` tile states, ordered by [`TilePos::linear_idx_in`].
+impl<T: Serialize, const N: usize> Serialize for TileStates<T, N> {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(self.iter())
+  }
+}
+impl<'de, T: Deserialize<'de>, const N: usize> Deserialize<'de> for TileStates<T, N> {
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let states = Vec::<T>::deserialize(deserializer)?;
+    if states.len() != N * N {
+      return Err(serde::de::Error::invalid_length(
+        states.len(),
+        &format!("{} tile states", N * N).as_str(),
+      ));
+    }
+    let mut states = states.into_iter();
+    Ok(Self::from_fn(|_| states.next().unwrap()))
   }
 }
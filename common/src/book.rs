@@ -0,0 +1,311 @@
+//! Opening books, storing the best moves of early positions so they can be played instantly.
+//!
+//! Positions are stored by their canonical zobrist hash (see [`RoundState::canonical`]), so the
+//! 8 symmetric images of a position share a single entry. The moves are stored for the canonical
+//! image and mapped back onto the position looked up.
+//!
+//! Books are saved as RON files. The zobrist keys are fixed, so books stay valid across builds.
+
+use std::{
+  collections::{BTreeMap, HashSet},
+  path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  bot::Bot,
+  eval::Score,
+  game::RoundState,
+  rules::RuleSet,
+  search::{SearchLimits, Searcher},
+  GlobalPos, OuterBoardBackend, PlayerSymbol, PLAYERS,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookMove {
+  pub global_pos: GlobalPos,
+  /// search score of the move, from the point of view of the player to move
+  pub score: Score,
+}
+
+/// Best moves by position, for rounds played by a single rule set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpeningBook {
+  rules: RuleSet,
+  /// best move of the canonical image, by canonical hash
+  entries: BTreeMap<u64, BookMove>,
+}
+
+impl OpeningBook {
+  pub fn new(rules: RuleSet) -> Self {
+    Self {
+      rules,
+      entries: BTreeMap::new(),
+    }
+  }
+
+  pub fn rules(&self) -> RuleSet {
+    self.rules
+  }
+  /// The number of stored positions, counting symmetric positions once.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// The best move of the position, if it is in the book.
+  pub fn lookup<Board: OuterBoardBackend>(&self, round: &RoundState<Board>) -> Option<BookMove> {
+    if round.rules() != self.rules {
+      return None;
+    }
+    let symmetry = round.canonical_symmetry();
+    let book_move = self.entries.get(&round.symmetric_hash(symmetry))?;
+    Some(BookMove {
+      global_pos: book_move.global_pos.transformed(symmetry.inverse()),
+      ..*book_move
+    })
+  }
+
+  /// Stores the best move of the position, replacing the one of any symmetric position.
+  pub fn insert<Board: OuterBoardBackend>(
+    &mut self,
+    round: &RoundState<Board>,
+    book_move: BookMove,
+  ) {
+    assert_eq!(round.rules(), self.rules, "rules of the book");
+    let symmetry = round.canonical_symmetry();
+    let canonical_move = BookMove {
+      global_pos: book_move.global_pos.transformed(symmetry),
+      ..book_move
+    };
+    self
+      .entries
+      .insert(round.symmetric_hash(symmetry), canonical_move);
+  }
+
+  /// Adds the entries of the other book, keeping the own ones for shared positions.
+  pub fn merge(&mut self, other: Self) {
+    assert_eq!(other.rules, self.rules, "rules of the books");
+    for (hash, book_move) in other.entries {
+      self.entries.entry(hash).or_insert(book_move);
+    }
+  }
+
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BookError> {
+    let serialized = ron::to_string(self).map_err(BookError::Serialize)?;
+    std::fs::write(path, serialized).map_err(BookError::Io)
+  }
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, BookError> {
+    let serialized = std::fs::read_to_string(path).map_err(BookError::Io)?;
+    ron::from_str(&serialized).map_err(BookError::Deserialize)
+  }
+}
+
+#[derive(Debug)]
+pub enum BookError {
+  Io(std::io::Error),
+  Serialize(ron::Error),
+  Deserialize(ron::error::SpannedError),
+}
+impl std::fmt::Display for BookError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Io(e) => write!(f, "opening book file: {}", e),
+      Self::Serialize(e) => write!(f, "serializing opening book: {}", e),
+      Self::Deserialize(e) => write!(f, "invalid opening book: {}", e),
+    }
+  }
+}
+impl std::error::Error for BookError {}
+
+/// Builds books by searching all positions up to a fixed ply, which a player following the book
+/// can reach against any opponent. Both players may start the round and play either side.
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+  searcher: Searcher,
+  limits: SearchLimits,
+  max_ply: usize,
+}
+
+impl BookBuilder {
+  /// Positions with less than `max_ply` moves get an entry.
+  pub fn new(searcher: Searcher, limits: SearchLimits, max_ply: usize) -> Self {
+    Self {
+      searcher,
+      limits,
+      max_ply,
+    }
+  }
+
+  pub fn build(&self, rules: RuleSet) -> OpeningBook {
+    let mut book = OpeningBook::new(rules);
+    for starting_player in PLAYERS {
+      for book_player in PLAYERS {
+        let mut round = RoundState::new(starting_player, rules);
+        let mut visited = HashSet::new();
+        self.expand(&mut book, &mut round, book_player, &mut visited);
+      }
+    }
+    book
+  }
+
+  /// Follows the book moves of the book player and all moves of the opponent.
+  fn expand(
+    &self,
+    book: &mut OpeningBook,
+    round: &mut RoundState,
+    book_player: PlayerSymbol,
+    visited: &mut HashSet<u64>,
+  ) {
+    if round.move_history().len() >= self.max_ply || round.outcome().is_some() {
+      return;
+    }
+    if !visited.insert(round.symmetric_hash(round.canonical_symmetry())) {
+      return;
+    }
+
+    let moves: Vec<_> = match round.current_player() == book_player {
+      true => {
+        let book_move = book.lookup(round).unwrap_or_else(|| {
+          let result = self
+            .searcher
+            .search(round, self.limits)
+            .expect("unfinished round without legal moves");
+          let book_move = BookMove {
+            global_pos: result.best_move,
+            score: result.score,
+          };
+          book.insert(round, book_move);
+          book_move
+        });
+        vec![book_move.global_pos]
+      }
+      false => round.legal_moves().collect(),
+    };
+    for global_pos in moves {
+      round
+        .try_play_move(round.current_player(), global_pos)
+        .expect("legal move must be playable");
+      self.expand(book, round, book_player, visited);
+      round.undo_move();
+    }
+  }
+}
+
+/// Plays from the opening book while possible, and like the wrapped bot after that.
+/// Book moves which are not legal in the round, e.g. from a hash collision, are ignored.
+#[derive(Debug, Clone)]
+pub struct BookBot<B> {
+  book: OpeningBook,
+  bot: B,
+}
+
+impl<B: Bot> BookBot<B> {
+  pub fn new(book: OpeningBook, bot: B) -> Self {
+    Self { book, bot }
+  }
+}
+
+impl<B: Bot> Bot for BookBot<B> {
+  fn choose_move(&mut self, round: &RoundState) -> GlobalPos {
+    match self.book.lookup(round) {
+      Some(book_move) if round.could_play_move(round.current_player(), book_move.global_pos) => {
+        book_move.global_pos
+      }
+      _ => self.bot.choose_move(round),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{BookBot, BookBuilder, BookMove, OpeningBook};
+  use crate::{
+    bot::{Bot, RandomBot},
+    game::RoundState,
+    rules::{DrawRule, RuleSet},
+    search::{SearchLimits, Searcher},
+    symmetry::Symmetry,
+    GlobalPos, PlayerSymbol,
+  };
+
+  #[test]
+  fn check_symmetric_positions_share_entries() {
+    let mut book = OpeningBook::new(RuleSet::default());
+    let mut round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    round
+      .try_play_move(PlayerSymbol::X, GlobalPos::new(0, 1))
+      .unwrap();
+    let book_move = BookMove {
+      global_pos: GlobalPos::new(1, 3),
+      score: 7,
+    };
+    book.insert(&round, book_move);
+    assert_eq!(book.len(), 1);
+
+    for symmetry in Symmetry::ALL {
+      let image = round.transformed(symmetry);
+      let image_move = book.lookup(&image).unwrap();
+      assert_eq!(
+        image_move.global_pos,
+        book_move.global_pos.transformed(symmetry)
+      );
+      assert!(image.could_play_move(PlayerSymbol::O, image_move.global_pos));
+    }
+
+    let majority = RuleSet {
+      draw: DrawRule::MajorityOfWonBoards,
+      ..RuleSet::default()
+    };
+    let other_rules = RoundState::new(PlayerSymbol::X, majority);
+    assert_eq!(book.lookup(&other_rules), None);
+  }
+
+  #[test]
+  fn check_book_bot_skips_illegal_moves() {
+    let mut book = OpeningBook::new(RuleSet::default());
+    let mut round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    round
+      .try_play_move(PlayerSymbol::X, GlobalPos::new(4, 4))
+      .unwrap();
+    // the tile X just took
+    book.insert(
+      &round,
+      BookMove {
+        global_pos: GlobalPos::new(4, 4),
+        score: 0,
+      },
+    );
+    assert!(book.lookup(&round).is_some());
+
+    let mut bot = BookBot::new(book, RandomBot::new(0));
+    let chosen_tile = bot.choose_move(&round);
+    assert!(round.could_play_move(PlayerSymbol::O, chosen_tile));
+  }
+
+  #[test]
+  fn check_built_books() {
+    let builder = BookBuilder::new(Searcher::default(), SearchLimits::depth(1), 2);
+    let book = builder.build(RuleSet::default());
+    // the empty boards and the 15 distinct first moves of either player
+    assert_eq!(book.len(), 2 + 2 * 15);
+
+    let mut round = RoundState::new(PlayerSymbol::O, RuleSet::default());
+    for _ in 0..2 {
+      let book_move = book.lookup(&round).unwrap();
+      round
+        .try_play_move(round.current_player(), book_move.global_pos)
+        .unwrap();
+    }
+    assert_eq!(book.lookup(&round), None);
+
+    let path = std::env::temp_dir().join(format!("uttt-book-{}.ron", std::process::id()));
+    book.save(&path).unwrap();
+    let loaded = OpeningBook::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, book);
+  }
+}
@@ -0,0 +1,99 @@
+//! Computer players behind a common interface, so that engines can be swapped freely.
+
+use rand::prelude::*;
+
+use crate::{
+  game::RoundState,
+  search::{SearchLimits, Searcher},
+  solver::{solve, SolvedValue},
+  GlobalPos, OuterBoardBackend, OuterPos,
+};
+
+/// Nodes the [`SearchBot`] spends on trying to solve a position, before searching it.
+const SOLVER_NODE_LIMIT: u64 = 10_000;
+/// Number of playable tiles at or below which a position counts as an endgame.
+const SOLVER_MAX_FREE_TILES: usize = 24;
+/// Number of decided sub-boards at or above which a position counts as an endgame.
+const SOLVER_MIN_DECIDED_SUB_BOARDS: usize = 5;
+
+/// A computer player.
+pub trait Bot {
+  /// Chooses a move for the current player of the round.
+  ///
+  /// Panics if the round has no legal moves.
+  fn choose_move(&mut self, round: &RoundState) -> GlobalPos;
+}
+
+/// Plays uniformly random legal moves.
+#[derive(Debug, Clone)]
+pub struct RandomBot {
+  rng: StdRng,
+}
+
+impl RandomBot {
+  pub fn new(seed: u64) -> Self {
+    Self {
+      rng: StdRng::seed_from_u64(seed),
+    }
+  }
+}
+
+impl Bot for RandomBot {
+  fn choose_move(&mut self, round: &RoundState) -> GlobalPos {
+    round
+      .legal_moves()
+      .choose(&mut self.rng)
+      .expect("no legal moves left")
+  }
+}
+
+/// Plays the best move found by an alpha-beta [`Searcher`] within the given limits.
+/// Endgames which can be solved quickly are played perfectly.
+#[derive(Debug, Clone)]
+pub struct SearchBot {
+  searcher: Searcher,
+  limits: SearchLimits,
+}
+
+impl SearchBot {
+  pub fn new(searcher: Searcher, limits: SearchLimits) -> Self {
+    Self { searcher, limits }
+  }
+}
+
+impl Bot for SearchBot {
+  fn choose_move(&mut self, round: &RoundState) -> GlobalPos {
+    // in lost positions, the search picks the move making it hardest for the opponent
+    if is_endgame(round) {
+      if let Ok(solution) = solve(round, SOLVER_NODE_LIMIT) {
+        if solution.value != SolvedValue::Loss {
+          return solution.best_move.expect("no legal moves left");
+        }
+      }
+    }
+    self
+      .searcher
+      .search(round, self.limits)
+      .expect("no legal moves left")
+      .best_move
+  }
+}
+
+/// Whether the round is late enough for the solver to have a chance, which the [`SearchBot`]
+/// checks before trying to solve it. That is the case with at most [`SOLVER_MAX_FREE_TILES`]
+/// playable tiles left, or with at least [`SOLVER_MIN_DECIDED_SUB_BOARDS`] decided sub-boards.
+fn is_endgame(round: &RoundState) -> bool {
+  let board = round.board();
+  let ndecided = OuterPos::all()
+    .filter(|&outer_pos| board.sub_board_state(outer_pos).is_decided())
+    .count();
+  let nfree = GlobalPos::all()
+    .filter(|&global_pos| {
+      round
+        .rules()
+        .is_placeable(board.sub_board_state(OuterPos::from(global_pos)))
+        && board.trivial_tile(global_pos).is_free()
+    })
+    .count();
+  nfree <= SOLVER_MAX_FREE_TILES || ndecided >= SOLVER_MIN_DECIDED_SUB_BOARDS
+}
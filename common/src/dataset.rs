@@ -0,0 +1,237 @@
+//! Training data for machine learning, recorded from searched positions.
+//!
+//! # Encoding
+//!
+//! Positions are encoded independently of the board backend:
+//!
+//! - The 81 cells are indexed row by row from the top left, cell `9 * y + x` is the tile at
+//!   [`GlobalPos::new(x, y)`](GlobalPos::new), the same order as in the position notation.
+//!   A cell is `0` if free, `1` if taken by `X` and `2` if taken by `O`.
+//! - The 9 sub-boards are indexed the same way, sub-board `3 * y + x` is the one at
+//!   [`OuterPos::new(x, y)`](OuterPos::new). A sub-board is `0` if free, `1` if won by `X`,
+//!   `2` if won by `O`, `3` if drawn but still placeable and `4` if fully drawn.
+//! - The side to move is `0` for `X` and `1` for `O`.
+//!
+//! # CSV format
+//!
+//! [`CsvWriter`] writes one sample per line after a header line, all values being integers:
+//!
+//! | columns | content |
+//! | --- | --- |
+//! | `game`, `ply` | index of the round and the number of moves played before the position |
+//! | `side` | side to move |
+//! | `cell_0` to `cell_80` | cells |
+//! | `board_0` to `board_8` | sub-boards |
+//! | `legal_0` to `legal_80` | `1` if the side to move may play in the cell, else `0` |
+//! | `visits_0` to `visits_80` | search visits of the move into the cell, `0` for illegal moves |
+//! | `result` | final result for the side to move, `1` for a win, `0` for a draw, `-1` for a loss |
+
+use std::io::{self, Write};
+
+use crate::{
+  board::TileBoardState,
+  game::{RoundOutcome, RoundState},
+  GlobalPos, OuterBoardBackend, OuterPos, PlayerSymbol, TrivialTileState,
+};
+
+pub const NCELLS: usize = 81;
+pub const NSUB_BOARDS: usize = 9;
+
+/// The index of the cell of the tile.
+pub fn cell_idx(global_pos: GlobalPos) -> usize {
+  9 * global_pos.y() as usize + global_pos.x() as usize
+}
+/// The tile of the cell. Panics if the index is out of range.
+pub fn cell_pos(idx: usize) -> GlobalPos {
+  assert!(idx < NCELLS, "cell index out of range");
+  GlobalPos::new((idx % 9) as u8, (idx / 9) as u8)
+}
+/// The index of the sub-board.
+pub fn sub_board_idx(outer_pos: OuterPos) -> usize {
+  let [x, y] = <[u8; 2]>::from(outer_pos);
+  3 * y as usize + x as usize
+}
+
+pub fn encode_cell(tile: TrivialTileState) -> u8 {
+  match tile {
+    TrivialTileState::Free => 0,
+    TrivialTileState::Won(player) => 1 + player.idx() as u8,
+  }
+}
+pub fn encode_sub_board(state: TileBoardState) -> u8 {
+  match state {
+    TileBoardState::Free => 0,
+    TileBoardState::Won(player) => 1 + player.idx() as u8,
+    TileBoardState::Drawn => 3,
+    TileBoardState::FullyDrawn => 4,
+  }
+}
+
+/// A position in the encoding described in the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodedPosition {
+  pub cells: [u8; NCELLS],
+  pub sub_boards: [u8; NSUB_BOARDS],
+  pub side_to_move: PlayerSymbol,
+  /// by cell, whether the side to move may play there
+  pub legal_moves: [bool; NCELLS],
+}
+
+impl EncodedPosition {
+  pub fn new<Board: OuterBoardBackend>(round: &RoundState<Board>) -> Self {
+    let board = round.board();
+    let mut cells = [0; NCELLS];
+    for global_pos in GlobalPos::all() {
+      cells[cell_idx(global_pos)] = encode_cell(board.trivial_tile(global_pos));
+    }
+    let mut sub_boards = [0; NSUB_BOARDS];
+    for outer_pos in OuterPos::all() {
+      sub_boards[sub_board_idx(outer_pos)] = encode_sub_board(board.sub_board_state(outer_pos));
+    }
+    let mut legal_moves = [false; NCELLS];
+    for global_pos in round.legal_moves() {
+      legal_moves[cell_idx(global_pos)] = true;
+    }
+    Self {
+      cells,
+      sub_boards,
+      side_to_move: round.current_player(),
+      legal_moves,
+    }
+  }
+}
+
+/// A searched position of a round, labelled with the outcome of the round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+  /// index of the round within the dataset
+  pub game: u64,
+  /// number of moves played before the position
+  pub ply: usize,
+  pub position: EncodedPosition,
+  /// by cell, how often the search visited the move
+  pub visits: [u32; NCELLS],
+  pub outcome: RoundOutcome,
+}
+
+impl Sample {
+  /// The outcome for the side to move, `1` for a win, `0` for a draw and `-1` for a loss.
+  pub fn result(&self) -> i8 {
+    match self.outcome {
+      RoundOutcome::Win(player) if player == self.position.side_to_move => 1,
+      RoundOutcome::Win(_) => -1,
+      RoundOutcome::Draw => 0,
+    }
+  }
+}
+
+/// Writes samples in the CSV format described in the [module docs](self).
+#[derive(Debug)]
+pub struct CsvWriter<W: Write> {
+  writer: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+  /// Writes the header line.
+  pub fn new(mut writer: W) -> io::Result<Self> {
+    let mut columns = vec!["game".to_string(), "ply".to_string(), "side".to_string()];
+    let mut add_columns =
+      |name: &str, n: usize| columns.extend((0..n).map(|i| format!("{}_{}", name, i)));
+    add_columns("cell", NCELLS);
+    add_columns("board", NSUB_BOARDS);
+    add_columns("legal", NCELLS);
+    add_columns("visits", NCELLS);
+    columns.push("result".to_string());
+    writeln!(writer, "{}", columns.join(","))?;
+    Ok(Self { writer })
+  }
+
+  pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+    let position = &sample.position;
+    let mut values = vec![
+      sample.game.to_string(),
+      sample.ply.to_string(),
+      position.side_to_move.idx().to_string(),
+    ];
+    values.extend(position.cells.iter().map(u8::to_string));
+    values.extend(position.sub_boards.iter().map(u8::to_string));
+    values.extend(
+      position
+        .legal_moves
+        .iter()
+        .map(|&legal| (legal as u8).to_string()),
+    );
+    values.extend(sample.visits.iter().map(u32::to_string));
+    values.push(sample.result().to_string());
+    writeln!(self.writer, "{}", values.join(","))
+  }
+
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{cell_idx, cell_pos, sub_board_idx, CsvWriter, EncodedPosition, Sample, NCELLS};
+  use crate::{
+    game::{RoundOutcome, RoundState},
+    GlobalPos, OuterPos, PlayerSymbol,
+  };
+
+  #[test]
+  fn check_encoding() {
+    for idx in 0..NCELLS {
+      assert_eq!(cell_idx(cell_pos(idx)), idx);
+    }
+    assert_eq!(cell_idx(GlobalPos::new(8, 0)), 8);
+    assert_eq!(sub_board_idx(OuterPos::new(2, 0)), 2);
+    assert_eq!(sub_board_idx(OuterPos::new(0, 2)), 6);
+
+    // X won the top left and top middle sub-boards and has to play in the top right one
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X c3"
+      .parse()
+      .unwrap();
+    let position = EncodedPosition::new(&round);
+    assert_eq!(position.side_to_move, PlayerSymbol::X);
+    assert_eq!(position.cells[..9], [1, 1, 1, 1, 1, 1, 0, 0, 0]);
+    assert_eq!(position.cells[27..30], [2, 2, 0]);
+    assert_eq!(position.sub_boards, [1, 1, 0, 0, 0, 0, 0, 0, 0]);
+    let legal: Vec<_> = (0..NCELLS)
+      .filter(|&idx| position.legal_moves[idx])
+      .collect();
+    assert_eq!(legal, [6, 7, 8, 17, 24, 25, 26]);
+  }
+
+  #[test]
+  fn check_csv_rows() {
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 O c3"
+      .parse()
+      .unwrap();
+    let mut visits = [0; NCELLS];
+    visits[cell_idx(GlobalPos::new(8, 0))] = 5;
+    let sample = Sample {
+      game: 3,
+      ply: 17,
+      position: EncodedPosition::new(&round),
+      visits,
+      outcome: RoundOutcome::Win(PlayerSymbol::X),
+    };
+    assert_eq!(sample.result(), -1);
+
+    let mut writer = CsvWriter::new(Vec::new()).unwrap();
+    writer.write(&sample).unwrap();
+    let csv = String::from_utf8(writer.into_inner()).unwrap();
+    let lines: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].len(), 3 + 3 * NCELLS + 9 + 1);
+    assert_eq!(lines[1].len(), lines[0].len());
+    assert_eq!(lines[0][3], "cell_0");
+    assert_eq!(lines[1][..4], ["3", "17", "1", "1"]);
+    let column = |name: &str| lines[0].iter().position(|&column| column == name).unwrap();
+    assert_eq!(lines[1][column("visits_8")], "5");
+    assert_eq!(lines[1][column("legal_8")], "1");
+    assert_eq!(lines[1][column("legal_0")], "0");
+    assert_eq!(lines[1][column("result")], "-1");
+  }
+}
@@ -0,0 +1,409 @@
+//! A reinforcement learning environment in the style of OpenAI Gym, and batches of them.
+//!
+//! Both players act in the same environment, taking turns. An action is the index of the cell
+//! to play in, see [`crate::dataset`] for the order of the cells. The reward of a step goes to
+//! the player who acted, `1` for winning the round and `0` otherwise, as a move cannot lose.
+//!
+//! # Observations
+//!
+//! An [`Observation`] consists of [`NPLANES`] planes of 81 cells each, plane after plane,
+//! so it can be reshaped into a tensor of shape `[4, 9, 9]`. A cell is `1` if
+//!
+//! 0. it is taken by `X`,
+//! 1. it is taken by `O`,
+//! 2. the player to move may play in it,
+//! 3. its sub-board is decided, i.e. won or drawn,
+//!
+//! and `0` otherwise. The player to move is reported in the [`StepInfo`].
+
+use std::fmt;
+
+use rand::prelude::*;
+
+use crate::{
+  dataset::{cell_idx, cell_pos, NCELLS},
+  game::{RoundOutcome, RoundState},
+  rules::RuleSet,
+  GlobalPos, OuterBoardBackend, OuterPos, PlayerSymbol, TrivialTileState,
+};
+
+pub const NACTIONS: usize = NCELLS;
+pub const NPLANES: usize = 4;
+pub const OBSERVATION_LEN: usize = NPLANES * NCELLS;
+
+/// The planes of a position, see the [module docs](self).
+pub type Observation = [f32; OBSERVATION_LEN];
+
+/// Encodes the round into the planes described in the [module docs](self).
+pub fn observe<Board: OuterBoardBackend>(round: &RoundState<Board>) -> Observation {
+  let mut observation = [0.0; OBSERVATION_LEN];
+  let mut set = |plane: usize, global_pos: GlobalPos| {
+    observation[plane * NCELLS + cell_idx(global_pos)] = 1.0;
+  };
+  for global_pos in GlobalPos::all() {
+    if let TrivialTileState::Won(player) = round.board().trivial_tile(global_pos) {
+      set(player.idx(), global_pos);
+    }
+    if round
+      .board()
+      .sub_board_state(OuterPos::from(global_pos))
+      .is_decided()
+    {
+      set(3, global_pos);
+    }
+  }
+  for global_pos in round.legal_moves() {
+    set(2, global_pos);
+  }
+  observation
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+  pub observation: Observation,
+  /// reward of the player who acted
+  pub reward: f32,
+  /// whether the round is over and the environment has to be reset
+  pub done: bool,
+  pub info: StepInfo,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepInfo {
+  /// the player to act next, or the one who would if the round is over
+  pub current_player: PlayerSymbol,
+  pub outcome: Option<RoundOutcome>,
+  /// the observation of the finished round, if a [`VecEnv`] reset the environment
+  pub terminal_observation: Option<Box<Observation>>,
+}
+
+/// A single round of Ultimate Tic-Tac-Toe.
+#[derive(Debug, Clone)]
+pub struct Env {
+  rules: RuleSet,
+  round: RoundState,
+}
+
+impl Env {
+  /// Creates an environment whose rounds are played by the rules. It starts reset with seed `0`.
+  pub fn new(rules: RuleSet) -> Self {
+    let mut env = Self {
+      rules,
+      round: RoundState::new(PlayerSymbol::X, rules),
+    };
+    env.reset(0);
+    env
+  }
+
+  /// Creates an environment continuing the round, e.g. to start from chosen positions.
+  /// Resets start new rounds by the rules of the round.
+  pub fn from_round(round: RoundState) -> Self {
+    Self {
+      rules: round.rules(),
+      round,
+    }
+  }
+
+  pub fn round(&self) -> &RoundState {
+    &self.round
+  }
+
+  /// Starts a new round, with a starting player chosen by the seed.
+  pub fn reset(&mut self, seed: u64) -> Observation {
+    let starting_player = StdRng::seed_from_u64(seed).gen();
+    self.round = RoundState::new(starting_player, self.rules);
+    observe(&self.round)
+  }
+
+  /// Plays the action for the player to move.
+  pub fn step(&mut self, action: usize) -> Result<Step, ActionError> {
+    self.check_action(action)?;
+    let player = self.round.current_player();
+    self
+      .round
+      .try_play_move(player, cell_pos(action))
+      .expect("legal action must be playable");
+
+    let outcome = self.round.outcome();
+    let reward = match outcome {
+      Some(RoundOutcome::Win(winner)) if winner == player => 1.0,
+      _ => 0.0,
+    };
+    Ok(Step {
+      observation: observe(&self.round),
+      reward,
+      done: outcome.is_some(),
+      info: StepInfo {
+        current_player: self.round.current_player(),
+        outcome,
+        terminal_observation: None,
+      },
+    })
+  }
+
+  /// By action, whether the player to move may take it.
+  pub fn legal_action_mask(&self) -> [bool; NACTIONS] {
+    let mut mask = [false; NACTIONS];
+    for global_pos in self.round.legal_moves() {
+      mask[cell_idx(global_pos)] = true;
+    }
+    mask
+  }
+
+  fn check_action(&self, action: usize) -> Result<(), ActionError> {
+    if action >= NACTIONS {
+      return Err(ActionError::OutOfRange(action));
+    }
+    if self.round.outcome().is_some() {
+      return Err(ActionError::RoundOver);
+    }
+    match self
+      .round
+      .could_play_move(self.round.current_player(), cell_pos(action))
+    {
+      true => Ok(()),
+      false => Err(ActionError::Illegal(action)),
+    }
+  }
+}
+
+/// A batch of environments, stepped together.
+///
+/// Finished environments are reset right away, so every step returns the observation of a
+/// round in progress. The last observation of a finished round is kept in its [`StepInfo`].
+#[derive(Debug, Clone)]
+pub struct VecEnv {
+  envs: Vec<Env>,
+  /// draws the seeds of automatic resets
+  rng: StdRng,
+}
+
+impl VecEnv {
+  pub fn new(nenvs: usize, rules: RuleSet) -> Self {
+    Self {
+      envs: vec![Env::new(rules); nenvs],
+      rng: StdRng::seed_from_u64(0),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.envs.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.envs.is_empty()
+  }
+  pub fn envs(&self) -> &[Env] {
+    &self.envs
+  }
+
+  /// Resets all environments. The seed also determines the seeds of the automatic resets.
+  pub fn reset(&mut self, seed: u64) -> Vec<Observation> {
+    self.rng = StdRng::seed_from_u64(seed);
+    let rng = &mut self.rng;
+    self
+      .envs
+      .iter_mut()
+      .map(|env| env.reset(rng.gen()))
+      .collect()
+  }
+
+  /// Takes an action in every environment. If any action cannot be taken,
+  /// no environment is stepped.
+  pub fn step(&mut self, actions: &[usize]) -> Result<Vec<Step>, VecActionError> {
+    if actions.len() != self.envs.len() {
+      return Err(VecActionError::BatchSize {
+        expected: self.envs.len(),
+        found: actions.len(),
+      });
+    }
+    for (ienv, (env, &action)) in self.envs.iter().zip(actions).enumerate() {
+      env
+        .check_action(action)
+        .map_err(|error| VecActionError::Action { ienv, error })?;
+    }
+
+    let rng = &mut self.rng;
+    let steps = self
+      .envs
+      .iter_mut()
+      .zip(actions)
+      .map(|(env, &action)| {
+        let mut step = env.step(action).expect("actions have been checked");
+        if step.done {
+          let observation = env.reset(rng.gen());
+          step.info.terminal_observation = Some(Box::new(std::mem::replace(
+            &mut step.observation,
+            observation,
+          )));
+          step.info.current_player = env.round().current_player();
+        }
+        step
+      })
+      .collect();
+    Ok(steps)
+  }
+
+  /// The legal action masks of all environments.
+  pub fn legal_action_masks(&self) -> Vec<[bool; NACTIONS]> {
+    self.envs.iter().map(Env::legal_action_mask).collect()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionError {
+  OutOfRange(usize),
+  Illegal(usize),
+  RoundOver,
+}
+
+impl fmt::Display for ActionError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::OutOfRange(action) => write!(f, "action {} is not below {}", action, NACTIONS),
+      Self::Illegal(action) => write!(f, "action {} is illegal", action),
+      Self::RoundOver => write!(f, "the round is over, the environment has to be reset"),
+    }
+  }
+}
+impl std::error::Error for ActionError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VecActionError {
+  BatchSize { expected: usize, found: usize },
+  Action { ienv: usize, error: ActionError },
+}
+
+impl fmt::Display for VecActionError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::BatchSize { expected, found } => {
+        write!(f, "expected {} actions, found {}", expected, found)
+      }
+      Self::Action { ienv, error } => write!(f, "environment {}: {}", ienv, error),
+    }
+  }
+}
+impl std::error::Error for VecActionError {}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::{ActionError, Env, VecActionError, VecEnv, NACTIONS, NCELLS};
+  use crate::{
+    dataset::cell_idx,
+    game::{RoundOutcome, RoundState},
+    rules::RuleSet,
+    GlobalPos, PlayerSymbol,
+  };
+
+  fn random_action(mask: &[bool; NACTIONS], rng: &mut impl Rng) -> usize {
+    (0..NACTIONS)
+      .filter(|&action| mask[action])
+      .choose(rng)
+      .unwrap()
+  }
+
+  #[test]
+  fn check_env_steps() {
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X c3"
+      .parse()
+      .unwrap();
+    let mut env = Env::from_round(round);
+    let mask = env.legal_action_mask();
+    assert_eq!(mask.iter().filter(|&&legal| legal).count(), 7);
+    assert_eq!(env.step(0), Err(ActionError::Illegal(0)));
+    assert_eq!(env.step(NACTIONS), Err(ActionError::OutOfRange(NACTIONS)));
+
+    let win = cell_idx(GlobalPos::new(8, 1));
+    let step = env.step(win).unwrap();
+    assert!(step.done);
+    assert_eq!(step.reward, 1.0);
+    assert_eq!(step.info.outcome, Some(RoundOutcome::Win(PlayerSymbol::X)));
+    let plane = |plane: usize| &step.observation[plane * NCELLS..(plane + 1) * NCELLS];
+    assert_eq!(plane(0)[win], 1.0);
+    assert_eq!(plane(0).iter().sum::<f32>(), 9.0);
+    assert_eq!(plane(1).iter().sum::<f32>(), 8.0);
+    assert_eq!(plane(2).iter().sum::<f32>(), 0.0);
+    // the three won sub-boards of the top row
+    assert_eq!(plane(3).iter().sum::<f32>(), 27.0);
+    assert_eq!(env.step(win), Err(ActionError::RoundOver));
+
+    // random rounds end with a reward for the winner only
+    let mut rng = StdRng::seed_from_u64(0);
+    for seed in 0..5 {
+      let observation = env.reset(seed);
+      assert_eq!(observation[2 * NCELLS..3 * NCELLS], [1.0; NCELLS]);
+      loop {
+        let player = env.round().current_player();
+        let step = env
+          .step(random_action(&env.legal_action_mask(), &mut rng))
+          .unwrap();
+        if !step.done {
+          assert_eq!(step.reward, 0.0);
+          continue;
+        }
+        let expected = match step.info.outcome.unwrap() {
+          RoundOutcome::Win(winner) => (winner == player) as u8 as f32,
+          RoundOutcome::Draw => 0.0,
+        };
+        assert_eq!(step.reward, expected);
+        break;
+      }
+    }
+  }
+
+  #[test]
+  fn check_vec_env_resets() {
+    let mut envs = VecEnv::new(3, RuleSet::default());
+    assert_eq!(envs.reset(7).len(), 3);
+    assert_eq!(
+      envs.step(&[0, 0]),
+      Err(VecActionError::BatchSize {
+        expected: 3,
+        found: 2
+      })
+    );
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut nfinished = 0;
+    while nfinished < 5 {
+      let masks = envs.legal_action_masks();
+      let mut actions: Vec<_> = masks
+        .iter()
+        .map(|mask| random_action(mask, &mut rng))
+        .collect();
+      if let Some(illegal) = (0..NACTIONS).find(|&action| !masks[1][action]) {
+        // a single illegal action keeps all environments from being stepped
+        let legal = actions[1];
+        actions[1] = illegal;
+        let nmoves: Vec<_> = envs
+          .envs()
+          .iter()
+          .map(|env| env.round().move_history().len())
+          .collect();
+        assert!(matches!(
+          envs.step(&actions),
+          Err(VecActionError::Action { ienv: 1, .. })
+        ));
+        assert!(envs.envs().iter().zip(nmoves).all(|(env, nmoves)| env
+          .round()
+          .move_history()
+          .len()
+          == nmoves));
+        actions[1] = legal;
+      }
+
+      for step in envs.step(&actions).unwrap() {
+        if step.done {
+          nfinished += 1;
+          assert!(step.info.terminal_observation.is_some());
+        }
+      }
+      for (env, mask) in envs.envs().iter().zip(envs.legal_action_masks()) {
+        assert!(env.round().outcome().is_none());
+        assert!(mask.iter().any(|&legal| legal));
+      }
+    }
+  }
+}
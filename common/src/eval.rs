@@ -0,0 +1,258 @@
+//! Static evaluation of positions, the foundation of the search based players.
+//!
+//! Scores are given from the point of view of the current player: positive scores favour them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  board::{
+    line::{LinePos, LineState},
+    tile::{TilePos, TrivialTileState},
+    TileBoardState,
+  },
+  game::{RoundOutcome, RoundState},
+  GlobalPos, InnerPos, OuterBoardBackend, OuterPos, PLAYERS,
+};
+
+pub type Score = i32;
+
+/// Score of a won round. All heuristic scores stay far below it.
+pub const WIN_SCORE: Score = 1_000_000;
+
+/// Weights of a tile, depending on its place in the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaceWeights {
+  pub centre: Score,
+  pub corner: Score,
+  pub edge: Score,
+}
+
+impl PlaceWeights {
+  pub fn weight(self, pos: impl Into<TilePos>) -> Score {
+    let pos = pos.into();
+    match [pos.x() == 1, pos.y() == 1] {
+      [true, true] => self.centre,
+      [false, false] => self.corner,
+      _ => self.edge,
+    }
+  }
+}
+
+/// The weights of the evaluation terms.
+///
+/// Line terms count the lines a single player occupies, separately for lines with one
+/// and with two occupied tiles. A line with two occupied tiles is one move away from being won.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalWeights {
+  /// per won sub-board
+  pub won_sub_board: Score,
+  /// per won sub-board, depending on its place in the outer board
+  pub sub_board_place: PlaceWeights,
+  /// per outer line holding one won sub-board of a single player
+  pub outer_one_in_line: Score,
+  /// per outer line holding two won sub-boards of a single player and a free one
+  pub outer_two_in_line: Score,
+
+  /// per symbol in an undecided sub-board, depending on its place in the sub-board
+  pub tile_place: PlaceWeights,
+  /// per line of an undecided sub-board holding one symbol of a single player
+  pub inner_one_in_line: Score,
+  /// per line of an undecided sub-board holding two symbols of a single player and a free tile
+  pub inner_two_in_line: Score,
+
+  /// penalty for the player who sent the opponent to a free choice
+  pub free_choice: Score,
+}
+
+impl Default for EvalWeights {
+  fn default() -> Self {
+    Self {
+      won_sub_board: 100,
+      sub_board_place: PlaceWeights {
+        centre: 30,
+        corner: 20,
+        edge: 10,
+      },
+      outer_one_in_line: 20,
+      outer_two_in_line: 120,
+
+      tile_place: PlaceWeights {
+        centre: 3,
+        corner: 2,
+        edge: 1,
+      },
+      inner_one_in_line: 2,
+      inner_two_in_line: 10,
+
+      free_choice: 40,
+    }
+  }
+}
+
+/// Scores the round from the point of view of its current player.
+///
+/// Finished rounds score [`WIN_SCORE`] for a win, its negation for a loss and zero for a draw.
+pub fn evaluate<Board: OuterBoardBackend>(
+  round: &RoundState<Board>,
+  weights: &EvalWeights,
+) -> Score {
+  let curr_player = round.current_player();
+  match round.outcome() {
+    Some(RoundOutcome::Win(p)) if p == curr_player => return WIN_SCORE,
+    Some(RoundOutcome::Win(_)) => return -WIN_SCORE,
+    Some(RoundOutcome::Draw) => return 0,
+    None => {}
+  }
+
+  let board = round.board();
+  let mut scores = [0; PLAYERS.len()];
+
+  for outer_pos in OuterPos::all() {
+    match board.sub_board_state(outer_pos) {
+      TileBoardState::Won(p) => {
+        scores[p.idx()] += weights.won_sub_board + weights.sub_board_place.weight(outer_pos)
+      }
+      TileBoardState::Free => {
+        for inner_pos in InnerPos::all() {
+          let global_pos = GlobalPos::from((outer_pos, inner_pos));
+          if let TrivialTileState::Won(p) = board.trivial_tile(global_pos) {
+            scores[p.idx()] += weights.tile_place.weight(inner_pos);
+          }
+        }
+        let tile_state = |pos: TilePos| {
+          let inner_pos = InnerPos::new(pos.x(), pos.y());
+          TileBoardState::from(board.trivial_tile(GlobalPos::from((outer_pos, inner_pos))))
+        };
+        add_line_scores(
+          &mut scores,
+          tile_state,
+          weights.inner_one_in_line,
+          weights.inner_two_in_line,
+        );
+      }
+      TileBoardState::Drawn | TileBoardState::FullyDrawn => {}
+    }
+  }
+  add_line_scores(
+    &mut scores,
+    |pos| board.sub_board_state(OuterPos::new(pos.x(), pos.y())),
+    weights.outer_one_in_line,
+    weights.outer_two_in_line,
+  );
+
+  // the last move gave the current player a free choice, decided from the board alone
+  // so that positions without history score the same
+  let started = GlobalPos::all().any(|global_pos| board.trivial_tile(global_pos).is_won());
+  if round.current_outer_pos().is_none() && started {
+    scores[curr_player.idx()] += weights.free_choice;
+  }
+
+  scores[curr_player.idx()] - scores[curr_player.other().idx()]
+}
+
+/// Adds the scores of the lines of a 3x3 board occupied by a single player.
+fn add_line_scores(
+  scores: &mut [Score; PLAYERS.len()],
+  tile_state: impl Fn(TilePos) -> TileBoardState,
+  one_in_line: Score,
+  two_in_line: Score,
+) {
+  for line in LinePos::all::<3, 3>() {
+    let line_state = line
+      .iter::<3>()
+      .map(|pos| LineState::<3>::from(tile_state(pos)))
+      .fold(LineState::free(), LineState::combine);
+    let Some(p) = line_state.occupant() else {
+      continue;
+    };
+    scores[p.idx()] += match line_state.noccupied() {
+      1 => one_in_line,
+      2 => two_in_line,
+      _ => 0,
+    };
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::{evaluate, EvalWeights, PlaceWeights, WIN_SCORE};
+  use crate::{game::RoundState, rules::RuleSet, GlobalPos, PlayerSymbol};
+
+  #[test]
+  fn check_evaluation_perspective() {
+    let weights = EvalWeights::default();
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    assert_eq!(evaluate(&round, &weights), 0);
+
+    // X takes the centre of the centre sub-board, O answers in a corner
+    let mut round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    round
+      .try_play_move(PlayerSymbol::X, GlobalPos::new(4, 4))
+      .unwrap();
+    assert!(evaluate(&round, &weights) < 0);
+    round
+      .try_play_move(PlayerSymbol::O, GlobalPos::new(3, 3))
+      .unwrap();
+    assert!(evaluate(&round, &weights) > 0);
+
+    let mut rng = StdRng::seed_from_u64(11);
+    for _ in 0..20 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      while round.outcome().is_none() {
+        let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+        let score = evaluate(&round, &weights);
+        assert!(score.abs() < WIN_SCORE || round.outcome().is_some());
+      }
+      assert_eq!(evaluate(&round, &weights).abs() % WIN_SCORE, 0);
+    }
+  }
+
+  #[test]
+  fn check_lines_are_rewarded() {
+    let place_zero = PlaceWeights {
+      centre: 0,
+      corner: 0,
+      edge: 0,
+    };
+    let weights = EvalWeights {
+      won_sub_board: 0,
+      sub_board_place: place_zero,
+      outer_one_in_line: 0,
+      outer_two_in_line: 0,
+      tile_place: place_zero,
+      inner_one_in_line: 0,
+      inner_two_in_line: 1,
+      free_choice: 0,
+    };
+    // X gets two symbols in the left column of the top left sub-board, O none in a line
+    let mut round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    for (x, y) in [(0, 0), (1, 1), (3, 4), (0, 3), (0, 1)] {
+      round
+        .try_play_move(round.current_player(), GlobalPos::new(x, y))
+        .unwrap();
+    }
+    assert_eq!(evaluate(&round, &weights), -1);
+  }
+
+  #[test]
+  fn check_free_choice_without_history() {
+    let weights = EvalWeights::default();
+    let empty = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    assert_eq!(evaluate(&empty, &weights), 0);
+
+    // X sent O to the won top left sub-board
+    let mut round: RoundState = "XXX6/1O7/9/O8/O8/9/9/9/9 X -".parse().unwrap();
+    round
+      .try_play_move(PlayerSymbol::X, GlobalPos::new(3, 0))
+      .unwrap();
+    assert_eq!(round.current_outer_pos(), None);
+    let parsed: RoundState = round.to_string().parse().unwrap();
+    assert_eq!(parsed.move_history().len(), 0);
+    assert_eq!(evaluate(&parsed, &weights), evaluate(&round, &weights));
+  }
+}
@@ -1,43 +1,217 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-  board::{PlaceSymbolError, TileBoardState},
-  PlayerSymbol,
+  board::{tile::TrivialTileState, PlaceSymbolError, TileBoardState},
+  rules::RuleSet,
+  symmetry::Symmetry,
+  zobrist, PlayerSymbol,
 };
 
-use crate::{GlobalPos, InnerPos, OuterBoard, OuterPos};
+use crate::{GlobalPos, InnerPos, OuterBoard, OuterBoardBackend, OuterPos};
 
-pub struct RoundState {
-  outer_board: OuterBoard,
+/// The state of a single round.
+///
+/// Generic over the [`OuterBoardBackend`] storing the board, defaulting to the recursive [`OuterBoard`].
+///
+/// The [`RuleSet`] decides which sub-boards are playable and when the round is over.
+///
+/// Deserialization replays the history under the rules, checking that it leads to the board, player
+/// and outer pos, and recomputes the zobrist hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+  try_from = "RoundStateRepr<Board>",
+  bound(deserialize = "Board: OuterBoardBackend + Deserialize<'de>")
+)]
+pub struct RoundState<Board = OuterBoard> {
+  outer_board: Board,
   curr_player: PlayerSymbol,
   curr_outer_pos: Option<OuterPos>,
+  rules: RuleSet,
+
+  /// stack of played moves, allowing to undo them
+  history: Vec<PlayedMove>,
+  /// incrementally updated zobrist hash (redundant information)
+  #[serde(skip)]
+  hash: u64,
+}
+
+/// A move on the history stack, together with the state needed to undo it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct PlayedMove {
+  pos: GlobalPos,
+  prev_outer_pos: Option<OuterPos>,
+}
+
+/// Unchecked serialized form of a [`RoundState`].
+#[derive(Deserialize)]
+#[serde(rename = "RoundState")]
+struct RoundStateRepr<Board> {
+  outer_board: Board,
+  curr_player: PlayerSymbol,
+  curr_outer_pos: Option<OuterPos>,
+  #[serde(default)]
+  rules: RuleSet,
+  history: Vec<PlayedMove>,
+}
+
+impl<Board: OuterBoardBackend> TryFrom<RoundStateRepr<Board>> for RoundState<Board> {
+  type Error = InvalidRoundStateError;
+  fn try_from(repr: RoundStateRepr<Board>) -> Result<Self, Self::Error> {
+    // take back the history to find the start position, then replay it under the rules
+    let mut start_board = repr.outer_board.clone();
+    let mut start_player = repr.curr_player;
+    for played in repr.history.iter().rev() {
+      start_board
+        .try_remove_symbol(played.pos)
+        .map_err(|_| InvalidRoundStateError::HistoryTileFree)?;
+      start_player.switch();
+    }
+    let start_outer_pos = match repr.history.first() {
+      Some(played) => played.prev_outer_pos,
+      None => repr.curr_outer_pos,
+    };
+
+    let mut round = Self::from_position(start_board, start_player, start_outer_pos, repr.rules);
+    if let Some(outer_pos) = round.curr_outer_pos {
+      if !round.is_sub_board_playable(outer_pos) {
+        return Err(InvalidRoundStateError::OuterPosNotPlaceable);
+      }
+    }
+    for played in &repr.history {
+      if played.prev_outer_pos != round.curr_outer_pos {
+        return Err(InvalidRoundStateError::IllegalHistory);
+      }
+      round
+        .try_play_move(round.curr_player, played.pos)
+        .map_err(|_| InvalidRoundStateError::IllegalHistory)?;
+    }
+
+    let board = &repr.outer_board;
+    let matches = GlobalPos::all()
+      .all(|pos| round.outer_board.trivial_tile(pos) == board.trivial_tile(pos))
+      && OuterPos::all()
+        .all(|pos| round.outer_board.sub_board_state(pos) == board.sub_board_state(pos))
+      && round.outer_board.board_state() == board.board_state()
+      && round.curr_outer_pos == repr.curr_outer_pos;
+    match matches {
+      true => Ok(round),
+      false => Err(InvalidRoundStateError::HistoryMismatch),
+    }
+  }
 }
 
 impl RoundState {
-  pub fn new(starting_player: PlayerSymbol) -> Self {
+  pub fn new(starting_player: PlayerSymbol, rules: RuleSet) -> Self {
+    Self::new_with_backend(starting_player, rules)
+  }
+}
+
+impl<Board: OuterBoardBackend> RoundState<Board> {
+  pub fn new_with_backend(starting_player: PlayerSymbol, rules: RuleSet) -> Self {
     Self {
-      outer_board: OuterBoard::default(),
+      outer_board: Board::default(),
       curr_player: starting_player,
       curr_outer_pos: None,
+      rules,
+      history: Vec::new(),
+      hash: zobrist::side_key(starting_player),
     }
   }
 
+  /// Builds a round state from a position without history.
+  pub(crate) fn from_position(
+    outer_board: Board,
+    curr_player: PlayerSymbol,
+    curr_outer_pos: Option<OuterPos>,
+    rules: RuleSet,
+  ) -> Self {
+    let mut round = Self {
+      outer_board,
+      curr_player,
+      curr_outer_pos,
+      rules,
+      history: Vec::new(),
+      hash: 0,
+    };
+    round.hash = round.compute_zobrist_hash();
+    round
+  }
+
   pub fn could_play_move(&self, player: PlayerSymbol, global_pos: GlobalPos) -> bool {
     self.could_place_symbol(player, global_pos)
   }
 
+  /// Iterates over all moves the current player could play.
+  /// Yields nothing once the round has an outcome.
+  pub fn legal_moves(&self) -> impl Iterator<Item = GlobalPos> + '_ {
+    let outer_positions = match self.outcome() {
+      Some(_) => None,
+      None => Some(OuterPos::all().filter(move |&outer_pos| {
+        self
+          .curr_outer_pos
+          .map(|curr_outer_pos| curr_outer_pos == outer_pos)
+          .unwrap_or(true)
+      })),
+    };
+    outer_positions
+      .into_iter()
+      .flatten()
+      .filter(move |&outer_pos| self.is_sub_board_playable(outer_pos))
+      .flat_map(move |outer_pos| {
+        InnerPos::all()
+          .map(move |inner_pos| GlobalPos::from((outer_pos, inner_pos)))
+          .filter(move |&global_pos| self.outer_board.trivial_tile(global_pos).is_free())
+      })
+  }
+
   pub fn try_play_move(
     &mut self,
     player: PlayerSymbol,
     chosen_tile: GlobalPos,
   ) -> Result<(), MoveError> {
+    let outer_pos = OuterPos::from(chosen_tile);
+    let prev_winner_keys = self.disputed_winner_keys(outer_pos);
     self.try_place_symbol(player, chosen_tile)?;
+    self.history.push(PlayedMove {
+      pos: chosen_tile,
+      prev_outer_pos: self.curr_outer_pos,
+    });
+    self.hash ^= prev_winner_keys ^ self.disputed_winner_keys(outer_pos);
+    self.hash ^= zobrist::tile_key(player, chosen_tile);
+    self.hash ^= zobrist::outer_pos_key(self.curr_outer_pos);
     self.update_outer_pos(chosen_tile);
-    self.curr_player.switch();
+    self.hash ^= zobrist::outer_pos_key(self.curr_outer_pos);
+    self.switch_player();
     Ok(())
   }
 
-  pub fn board(&self) -> &OuterBoard {
+  /// Takes back the last played move and returns its position.
+  /// Returns `None` if no move has been played yet.
+  pub fn undo_move(&mut self) -> Option<GlobalPos> {
+    let PlayedMove {
+      pos,
+      prev_outer_pos,
+    } = self.history.pop()?;
+    let prev_winner_keys = self.disputed_winner_keys(OuterPos::from(pos));
+    let symbol = self
+      .outer_board
+      .try_remove_symbol(pos)
+      .expect("played move must be removable");
+    self.hash ^= prev_winner_keys ^ self.disputed_winner_keys(OuterPos::from(pos));
+    self.hash ^= zobrist::tile_key(symbol, pos);
+    self.hash ^= zobrist::outer_pos_key(self.curr_outer_pos);
+    self.curr_outer_pos = prev_outer_pos;
+    self.hash ^= zobrist::outer_pos_key(self.curr_outer_pos);
+    self.switch_player();
+    Some(pos)
+  }
+
+  /// The moves played so far, in order.
+  pub fn move_history(&self) -> impl ExactSizeIterator<Item = GlobalPos> + '_ {
+    self.history.iter().map(|played| played.pos)
+  }
+
+  pub fn board(&self) -> &Board {
     &self.outer_board
   }
   pub fn current_player(&self) -> PlayerSymbol {
@@ -46,8 +220,97 @@ impl RoundState {
   pub fn current_outer_pos(&self) -> Option<OuterPos> {
     self.curr_outer_pos
   }
+  pub fn rules(&self) -> RuleSet {
+    self.rules
+  }
+
+  /// Zobrist hash of the position, covering the trivial tiles, the winners of boards with lines of
+  /// both players, the player to move and the current outer pos.
+  /// It is updated incrementally and therefore cheap to query.
+  pub fn zobrist_hash(&self) -> u64 {
+    self.hash
+  }
+
+  /// The image of the round under the given symmetry, including its move history.
+  ///
+  /// The board is rebuilt from the transformed trivial tiles, keeping the winners of sub-boards
+  /// with lines of both players.
+  pub fn transformed(&self, symmetry: Symmetry) -> Self {
+    let inverse = symmetry.inverse();
+    let mut round = Self {
+      outer_board: Board::from_trivial_tiles(
+        |global_pos| {
+          self
+            .outer_board
+            .trivial_tile(global_pos.transformed(inverse))
+        },
+        |outer_pos| {
+          self
+            .outer_board
+            .winner(outer_pos.map(|pos| pos.transformed(inverse)))
+        },
+      )
+      .expect("the board decides all winners"),
+      curr_player: self.curr_player,
+      curr_outer_pos: self.curr_outer_pos.map(|pos| pos.transformed(symmetry)),
+      rules: self.rules,
+      history: self
+        .history
+        .iter()
+        .map(|played| PlayedMove {
+          pos: played.pos.transformed(symmetry),
+          prev_outer_pos: played.prev_outer_pos.map(|pos| pos.transformed(symmetry)),
+        })
+        .collect(),
+      hash: 0,
+    };
+    round.hash = round.compute_zobrist_hash();
+    round
+  }
+
+  /// The zobrist hash of [`Self::transformed`], without building the image.
+  pub fn symmetric_hash(&self, symmetry: Symmetry) -> u64 {
+    let winner_keys = OuterPos::all().map(Some).chain([None]).map(|outer_pos| {
+      zobrist::disputed_winner_key(
+        outer_pos.map(|pos| pos.transformed(symmetry)),
+        self.disputed_winner(outer_pos),
+      )
+    });
+    GlobalPos::all()
+      .filter_map(|pos| match self.outer_board.trivial_tile(pos) {
+        TrivialTileState::Won(p) => Some(zobrist::tile_key(p, pos.transformed(symmetry))),
+        TrivialTileState::Free => None,
+      })
+      .chain(winner_keys)
+      .fold(
+        zobrist::side_key(self.curr_player)
+          ^ zobrist::outer_pos_key(self.curr_outer_pos.map(|pos| pos.transformed(symmetry))),
+        |hash, key| hash ^ key,
+      )
+  }
+
+  /// The image with the smallest zobrist hash among all 8 symmetries, together with the symmetry mapping
+  /// this round onto it. Equivalent positions share their canonical image.
+  pub fn canonical(&self) -> (Self, Symmetry) {
+    let symmetry = self.canonical_symmetry();
+    (self.transformed(symmetry), symmetry)
+  }
+
+  /// The symmetry of [`Self::canonical`], without building the image.
+  pub fn canonical_symmetry(&self) -> Symmetry {
+    Symmetry::ALL
+      .into_iter()
+      .min_by_key(|&symmetry| self.symmetric_hash(symmetry))
+      .unwrap()
+  }
 
   pub fn outcome(&self) -> Option<RoundOutcome> {
+    if !self.rules.is_standard() {
+      return self.rules.outcome(
+        |outer_pos| self.outer_board.sub_board_state(outer_pos),
+        self.curr_player.other(),
+      );
+    }
     match self.outer_board.board_state() {
       TileBoardState::Won(p) => Some(RoundOutcome::Win(p)),
       TileBoardState::Drawn => Some(RoundOutcome::Draw),
@@ -58,14 +321,41 @@ impl RoundState {
 }
 
 // private methods
-impl RoundState {
+impl<Board: OuterBoardBackend> RoundState<Board> {
+  fn switch_player(&mut self) {
+    self.hash ^= zobrist::side_key(self.curr_player);
+    self.curr_player.switch();
+    self.hash ^= zobrist::side_key(self.curr_player);
+  }
+
+  /// The winner of the board at the position if both players have a line in it.
+  fn disputed_winner(&self, outer_pos: Option<OuterPos>) -> Option<PlayerSymbol> {
+    match self.outer_board.is_board_disputed(outer_pos) {
+      true => self.outer_board.winner(outer_pos),
+      false => None,
+    }
+  }
+
+  /// The zobrist keys of the disputed winners of the sub-board and the outer board.
+  fn disputed_winner_keys(&self, outer_pos: OuterPos) -> u64 {
+    zobrist::disputed_winner_key(Some(outer_pos), self.disputed_winner(Some(outer_pos)))
+      ^ zobrist::disputed_winner_key(None, self.disputed_winner(None))
+  }
+
+  /// Computes the zobrist hash from scratch.
+  fn compute_zobrist_hash(&self) -> u64 {
+    self.symmetric_hash(Symmetry::Identity)
+  }
+
   fn could_place_symbol(&self, player: PlayerSymbol, global_pos: GlobalPos) -> bool {
-    self.curr_player == player
+    self.outcome().is_none()
+      && self.curr_player == player
       && self
         .curr_outer_pos
         .map(|curr_outer_pos| curr_outer_pos == OuterPos::from(global_pos))
         .unwrap_or(true)
-      && self.outer_board.could_place_symbol(global_pos)
+      && self.is_sub_board_playable(OuterPos::from(global_pos))
+      && self.outer_board.trivial_tile(global_pos).is_free()
   }
 
   fn try_place_symbol(
@@ -73,37 +363,53 @@ impl RoundState {
     player: PlayerSymbol,
     global_pos: GlobalPos,
   ) -> Result<(), MoveError> {
+    self
+      .outcome()
+      .is_none()
+      .then_some(())
+      .ok_or(MoveError::RoundOver)?;
     (self.curr_player == player)
       .then_some(())
-      .ok_or(MoveError::WrongPlayer)
-      .and(
-        self
-          .curr_outer_pos
-          .map(|curr_outer_pos| curr_outer_pos == OuterPos::from(global_pos))
-          .unwrap_or(true)
-          .then_some(())
-          .ok_or(MoveError::WrongOuterPos),
-      )
-      .and(
-        self
-          .outer_board
-          .try_place_symbol(global_pos, self.curr_player)
-          .map_err(MoveError::PlaceSymbol),
-      )
+      .ok_or(MoveError::WrongPlayer)?;
+    self
+      .curr_outer_pos
+      .map(|curr_outer_pos| curr_outer_pos == OuterPos::from(global_pos))
+      .unwrap_or(true)
+      .then_some(())
+      .ok_or(MoveError::WrongOuterPos)?;
+    self
+      .is_sub_board_playable(OuterPos::from(global_pos))
+      .then_some(())
+      .ok_or(MoveError::PlaceSymbol(PlaceSymbolError::BoardNotPlaceable))?;
+    // the rules decided placeability, the board only needs to check the trivial tile
+    self
+      .outer_board
+      .try_force_place_symbol(global_pos, self.curr_player)
+      .map_err(MoveError::PlaceSymbol)
+  }
+
+  /// Whether the sub-board accepts symbols under the rules of this round.
+  pub(crate) fn is_sub_board_playable(&self, outer_pos: OuterPos) -> bool {
+    let state = self.outer_board.sub_board_state(outer_pos);
+    self.rules.is_placeable(state)
+      && (!state.is_won()
+        || InnerPos::all().any(|inner_pos| {
+          self
+            .outer_board
+            .trivial_tile(GlobalPos::from((outer_pos, inner_pos)))
+            .is_free()
+        }))
   }
 
   fn update_outer_pos(&mut self, last_move_pos: GlobalPos) {
     let next_outer_pos = InnerPos::from(last_move_pos).as_outer();
     self.curr_outer_pos = self
-      .outer_board
-      .tile_state(next_outer_pos)
-      .board_state()
-      .is_placeable()
+      .is_sub_board_playable(next_outer_pos)
       .then_some(next_outer_pos);
   }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PlayerAction {
   MakeMove(GlobalPos),
   GiveUp,
@@ -124,13 +430,13 @@ impl PlayerAction {
   }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RoundOutcome {
   Win(PlayerSymbol),
   Draw,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Stats {
   pub ngames: usize,
   pub scores: [usize; 2],
@@ -145,9 +451,204 @@ impl Stats {
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MoveError {
   PlaceSymbol(PlaceSymbolError),
   WrongOuterPos,
   WrongPlayer,
+  RoundOver,
+}
+
+/// A deserialized round state that is inconsistent with its board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidRoundStateError {
+  OuterPosNotPlaceable,
+  /// a move of the history is on a free tile or repeated
+  HistoryTileFree,
+  /// a move of the history is illegal when replayed from the start position
+  IllegalHistory,
+  /// replaying the history does not lead to the stored position
+  HistoryMismatch,
+}
+impl std::fmt::Display for InvalidRoundStateError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::OuterPosNotPlaceable => write!(f, "current outer pos is not placeable"),
+      Self::HistoryTileFree => write!(f, "move history contains a free or repeated tile"),
+      Self::IllegalHistory => write!(f, "move history contains an illegal move"),
+      Self::HistoryMismatch => write!(f, "move history does not lead to the position"),
+    }
+  }
+}
+impl std::error::Error for InvalidRoundStateError {}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::{InvalidRoundStateError, RoundState, RoundStateRepr};
+  use crate::{bitboard::BitBoard, rules::RuleSet, GlobalPos, OuterPos, PlayerSymbol};
+
+  #[test]
+  fn check_legal_moves_match_could_play_move() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..100 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      assert_eq!(round.legal_moves().count(), 81);
+      while round.outcome().is_none() {
+        let player = round.current_player();
+        let legal_moves: Vec<_> = round.legal_moves().collect();
+        let expected: Vec<_> = GlobalPos::all()
+          .filter(|&pos| round.could_play_move(player, pos))
+          .collect();
+        assert_eq!(legal_moves.len(), expected.len());
+        assert!(expected.iter().all(|pos| legal_moves.contains(pos)));
+
+        let chosen_tile = *legal_moves.choose(&mut rng).unwrap();
+        round.try_play_move(player, chosen_tile).unwrap();
+      }
+      assert_eq!(round.legal_moves().count(), 0);
+    }
+  }
+
+  #[test]
+  fn check_undo_restores_state() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..100 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      let mut snapshots = Vec::new();
+      while round.outcome().is_none() {
+        snapshots.push(round.clone());
+        let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+      }
+      assert_eq!(round.move_history().len(), snapshots.len());
+
+      while let Some(snapshot) = snapshots.pop() {
+        round.undo_move().unwrap();
+        assert_eq!(round.board(), snapshot.board());
+        assert_eq!(round.current_player(), snapshot.current_player());
+        assert_eq!(round.current_outer_pos(), snapshot.current_outer_pos());
+        assert!(round.move_history().eq(snapshot.move_history()));
+      }
+      assert!(round.undo_move().is_none());
+    }
+  }
+
+  #[test]
+  fn check_zobrist_hash_incremental() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..100 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      let mut hashes = vec![round.zobrist_hash()];
+      while round.outcome().is_none() {
+        let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+        assert_eq!(round.zobrist_hash(), round.compute_zobrist_hash());
+        hashes.push(round.zobrist_hash());
+      }
+      while round.undo_move().is_some() {
+        hashes.pop();
+        assert_eq!(round.zobrist_hash(), *hashes.last().unwrap());
+      }
+    }
+  }
+
+  #[test]
+  fn check_serde_roundtrip() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let mut round = RoundState::new(rng.gen(), RuleSet::default());
+    let mut bit_round =
+      RoundState::<BitBoard>::new_with_backend(round.current_player(), RuleSet::default());
+    for _ in 0..30 {
+      let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+      round
+        .try_play_move(round.current_player(), chosen_tile)
+        .unwrap();
+      bit_round
+        .try_play_move(bit_round.current_player(), chosen_tile)
+        .unwrap();
+    }
+
+    let serialized = ron::to_string(&round).unwrap();
+    let mut deserialized: RoundState = ron::from_str(&serialized).unwrap();
+    assert_eq!(deserialized.board(), round.board());
+    assert_eq!(deserialized.zobrist_hash(), round.zobrist_hash());
+    assert!(deserialized.move_history().eq(round.move_history()));
+    deserialized.undo_move().unwrap();
+
+    let bit_serialized = ron::to_string(&bit_round).unwrap();
+    assert_eq!(bit_serialized, serialized);
+    let bit_deserialized: RoundState<BitBoard> = ron::from_str(&bit_serialized).unwrap();
+    assert_eq!(bit_deserialized.board(), bit_round.board());
+    assert_eq!(bit_deserialized.zobrist_hash(), round.zobrist_hash());
+  }
+
+  #[test]
+  fn check_deserialization_replays_history() {
+    let mut rng = StdRng::seed_from_u64(6);
+    let mut round = RoundState::new(rng.gen(), RuleSet::default());
+    for _ in 0..20 {
+      let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+      round
+        .try_play_move(round.current_player(), chosen_tile)
+        .unwrap();
+    }
+    let repr = |round: &RoundState| RoundStateRepr {
+      outer_board: round.outer_board.clone(),
+      curr_player: round.curr_player,
+      curr_outer_pos: round.curr_outer_pos,
+      rules: round.rules,
+      history: round.history.clone(),
+    };
+    let replayed = RoundState::try_from(repr(&round)).unwrap();
+    assert_eq!(replayed.board(), round.board());
+    assert_eq!(replayed.zobrist_hash(), round.zobrist_hash());
+
+    let mut repeated = repr(&round);
+    repeated.history.push(repeated.history[0]);
+    assert_eq!(
+      RoundState::try_from(repeated).unwrap_err(),
+      InvalidRoundStateError::HistoryTileFree
+    );
+
+    let mut wrong_player = repr(&round);
+    wrong_player.curr_player.switch();
+    assert_eq!(
+      RoundState::try_from(wrong_player).unwrap_err(),
+      InvalidRoundStateError::HistoryMismatch
+    );
+
+    let mut wrong_outer_pos = repr(&round);
+    let last = wrong_outer_pos.history.last_mut().unwrap();
+    last.prev_outer_pos = match last.prev_outer_pos {
+      Some(_) => None,
+      None => Some(OuterPos::from(last.pos)),
+    };
+    assert_eq!(
+      RoundState::try_from(wrong_outer_pos).unwrap_err(),
+      InvalidRoundStateError::IllegalHistory
+    );
+
+    let mut wrong_order = repr(&round);
+    wrong_order.history.swap(0, 1);
+    assert!(RoundState::try_from(wrong_order).is_err());
+  }
+
+  #[test]
+  fn check_legal_moves_respect_outer_pos() {
+    let mut round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    round
+      .try_play_move(PlayerSymbol::X, GlobalPos::new(4, 4))
+      .unwrap();
+    let legal_moves: Vec<_> = round.legal_moves().collect();
+    assert_eq!(legal_moves.len(), 8);
+    assert!(legal_moves
+      .iter()
+      .all(|&pos| OuterPos::from(pos) == OuterPos::new(1, 1)));
+  }
 }
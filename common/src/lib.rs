@@ -1,10 +1,33 @@
+pub mod bitboard;
 pub mod board;
+pub mod book;
+pub mod bot;
+pub mod dataset;
+pub mod env;
+pub mod eval;
 pub mod game;
+pub mod mcts;
 pub mod msg;
+pub mod nested;
+pub mod notation;
+pub mod perft;
+pub mod protocol;
+pub mod record;
+pub mod rules;
+pub mod search;
+pub mod solver;
+pub mod symmetry;
+pub mod tt;
+
+mod zobrist;
 
 use std::net::{Ipv4Addr, SocketAddrV4};
 
-use board::{tile::TilePos, GenericBoard, TrivialBoard};
+use board::{
+  tile::{TilePos, TileStates, TrivialTileState},
+  BoardInvariantError, GenericBoard, PlaceSymbolError, RemoveSymbolError, TileBoardState,
+  TrivialBoard,
+};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -19,6 +42,108 @@ pub const PLAYERS: [PlayerSymbol; NPLAYERS as usize] = [PlayerSymbol::X, PlayerS
 pub type OuterBoard = GenericBoard<InnerBoard>;
 pub type InnerBoard = TrivialBoard;
 
+/// The interface a two-level board needs to provide to back a [`game::RoundState`].
+///
+/// Implemented by the recursive [`OuterBoard`] and the compact [`bitboard::BitBoard`].
+pub trait OuterBoardBackend: Default + Clone + std::fmt::Debug {
+  fn board_state(&self) -> TileBoardState;
+  fn sub_board_state(&self, outer_pos: OuterPos) -> TileBoardState;
+  fn trivial_tile(&self, global_pos: GlobalPos) -> TrivialTileState;
+  /// Whether both players have a line in the sub-board at the position or, given `None`, in the
+  /// outer board, so that its tiles alone do not decide its winner.
+  fn is_board_disputed(&self, outer_pos: Option<OuterPos>) -> bool;
+
+  fn could_place_symbol(&self, global_pos: GlobalPos) -> bool;
+  fn try_place_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError>;
+  /// Like [`Self::try_place_symbol`], but only requires the trivial tile to be free.
+  fn try_force_place_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError>;
+  fn try_remove_symbol(&mut self, global_pos: GlobalPos)
+    -> Result<PlayerSymbol, RemoveSymbolError>;
+
+  /// Builds a board from arbitrary trivial tiles, deriving all super states.
+  ///
+  /// Boards with lines of both players are won by the player given by `disputed_winner`,
+  /// which is asked for the sub-board at the position or, given `None`, for the outer board.
+  /// Fails if it has no winner for such a board.
+  fn from_trivial_tiles(
+    tile: impl FnMut(GlobalPos) -> TrivialTileState,
+    disputed_winner: impl FnMut(Option<OuterPos>) -> Option<PlayerSymbol>,
+  ) -> Result<Self, BoardInvariantError>;
+
+  /// The winner of the board at the position, as asked for by [`Self::from_trivial_tiles`].
+  fn winner(&self, outer_pos: Option<OuterPos>) -> Option<PlayerSymbol> {
+    match outer_pos {
+      Some(outer_pos) => self.sub_board_state(outer_pos).winner(),
+      None => self.board_state().winner(),
+    }
+  }
+}
+
+impl OuterBoardBackend for OuterBoard {
+  fn board_state(&self) -> TileBoardState {
+    GenericBoard::board_state(self)
+  }
+  fn sub_board_state(&self, outer_pos: OuterPos) -> TileBoardState {
+    self.tile_state(outer_pos).board_state()
+  }
+  fn trivial_tile(&self, global_pos: GlobalPos) -> TrivialTileState {
+    GenericBoard::trivial_tile(self, global_pos)
+  }
+  fn is_board_disputed(&self, outer_pos: Option<OuterPos>) -> bool {
+    match outer_pos {
+      Some(outer_pos) => self.tile_state(outer_pos).is_disputed(),
+      None => GenericBoard::is_disputed(self),
+    }
+  }
+
+  fn could_place_symbol(&self, global_pos: GlobalPos) -> bool {
+    GenericBoard::could_place_symbol(self, global_pos)
+  }
+  fn try_place_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError> {
+    GenericBoard::try_place_symbol(self, global_pos, symbol)
+  }
+  fn try_force_place_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+    symbol: PlayerSymbol,
+  ) -> Result<(), PlaceSymbolError> {
+    GenericBoard::try_force_place_symbol(self, global_pos, symbol)
+  }
+  fn try_remove_symbol(
+    &mut self,
+    global_pos: GlobalPos,
+  ) -> Result<PlayerSymbol, RemoveSymbolError> {
+    GenericBoard::try_remove_symbol(self, global_pos)
+  }
+
+  fn from_trivial_tiles(
+    mut tile: impl FnMut(GlobalPos) -> TrivialTileState,
+    mut disputed_winner: impl FnMut(Option<OuterPos>) -> Option<PlayerSymbol>,
+  ) -> Result<Self, BoardInvariantError> {
+    let mut sub_boards = TileStates::<InnerBoard>::default();
+    for outer_pos in OuterPos::all() {
+      let inner_tiles = TileStates::from_fn(|inner_pos| {
+        tile(GlobalPos::from((outer_pos, InnerPos(inner_pos.into()))))
+      });
+      sub_boards[outer_pos.into()] =
+        InnerBoard::from_tile_states(inner_tiles, disputed_winner(Some(outer_pos)))?;
+    }
+    Self::from_tile_states(sub_boards, disputed_winner(None))
+  }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum PlayerSymbol {
   X = 0,
@@ -67,6 +192,7 @@ impl Distribution<PlayerSymbol> for rand::distributions::Standard {
 
 /// instance guranteed to be valid
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "[u8; 2]", into = "[u8; 2]")]
 pub struct GlobalPos([u8; 2]);
 
 impl GlobalPos {
@@ -77,6 +203,24 @@ impl GlobalPos {
   pub fn new(x: u8, y: u8) -> Self {
     Self::new_arr([x, y])
   }
+
+  pub fn x(self) -> u8 {
+    self.0[0]
+  }
+  pub fn y(self) -> u8 {
+    self.0[1]
+  }
+  pub fn linear_idx(self) -> usize {
+    (self.x() * 9 + self.y()) as usize
+  }
+  pub fn from_linear_idx(idx: usize) -> Self {
+    Self::new(idx as u8 / 9, idx as u8 % 9)
+  }
+
+  /// Iterates over all 81 global positions, ordered by [`Self::linear_idx`].
+  pub fn all() -> impl Iterator<Item = Self> {
+    (0..81).map(Self::from_linear_idx)
+  }
 }
 
 impl IntoIterator for GlobalPos {
@@ -125,7 +269,8 @@ impl From<GlobalPos> for InnerPos {
 }
 
 /// instance guranteed to be valid
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "[u8; 2]", into = "[u8; 2]")]
 pub struct OuterPos([u8; 2]);
 
 impl OuterPos {
@@ -136,6 +281,10 @@ impl OuterPos {
   pub fn new(x: u8, y: u8) -> Self {
     Self::new_arr([x, y])
   }
+
+  pub fn all() -> impl Iterator<Item = Self> {
+    (0..3).flat_map(|x| (0..3).map(move |y| Self::new(x, y)))
+  }
 }
 
 impl From<OuterPos> for TilePos {
@@ -145,7 +294,8 @@ impl From<OuterPos> for TilePos {
 }
 
 /// instance guranteed to be valid
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "[u8; 2]", into = "[u8; 2]")]
 pub struct InnerPos([u8; 2]);
 
 impl InnerPos {
@@ -159,6 +309,10 @@ impl InnerPos {
   pub fn as_outer(self) -> OuterPos {
     OuterPos(self.0)
   }
+
+  pub fn all() -> impl Iterator<Item = Self> {
+    (0..3).flat_map(|x| (0..3).map(move |y| Self::new(x, y)))
+  }
 }
 
 impl From<InnerPos> for TilePos {
@@ -166,3 +320,38 @@ impl From<InnerPos> for TilePos {
     Self::new_arr(inner.0)
   }
 }
+
+/// Implements the checked conversions from and to raw coordinates, used for (de)serialization.
+macro_rules! impl_pos_conversions {
+  ($pos:ty, $side_length:expr) => {
+    impl TryFrom<[u8; 2]> for $pos {
+      type Error = $crate::PosOutOfRangeError;
+      fn try_from(arr: [u8; 2]) -> Result<Self, Self::Error> {
+        match arr[0] < $side_length && arr[1] < $side_length {
+          true => Ok(Self(arr)),
+          false => Err($crate::PosOutOfRangeError(arr)),
+        }
+      }
+    }
+    impl From<$pos> for [u8; 2] {
+      fn from(pos: $pos) -> Self {
+        pos.0
+      }
+    }
+  };
+}
+pub(crate) use impl_pos_conversions;
+
+impl_pos_conversions!(GlobalPos, 9);
+impl_pos_conversions!(OuterPos, 3);
+impl_pos_conversions!(InnerPos, 3);
+
+/// Coordinates lying outside of the board they are meant for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PosOutOfRangeError(pub [u8; 2]);
+impl std::fmt::Display for PosOutOfRangeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "position {:?} is out of range", self.0)
+  }
+}
+impl std::error::Error for PosOutOfRangeError {}
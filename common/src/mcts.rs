@@ -0,0 +1,490 @@
+//! Monte Carlo tree search using the UCT selection rule and uniformly random playouts.
+//!
+//! The tree is kept between moves: [`Mcts::advance`] makes the subtree of the played move the new
+//! root, so the work spent on it is not lost.
+//!
+//! Searches can be root parallel: helper threads grow their own trees of the same position with
+//! their own playouts, and their trees are merged into the main tree, so the subtrees kept by
+//! [`Mcts::advance`] include the work of the helpers.
+
+use std::{
+  thread,
+  time::{Duration, Instant},
+};
+
+use rand::prelude::*;
+
+use crate::{
+  bot::Bot,
+  game::{MoveError, RoundOutcome, RoundState},
+  GlobalPos, OuterBoard, OuterBoardBackend,
+};
+
+const ROOT: usize = 0;
+
+/// When to stop searching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MctsLimit {
+  Iterations(u64),
+  Time(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MctsConfig {
+  pub limit: MctsLimit,
+  /// weight of the exploration term of UCT
+  pub exploration: f64,
+  /// seed of the random playouts, making searches reproducible
+  pub seed: u64,
+  /// number of threads growing trees, each one within the limit
+  pub threads: usize,
+}
+
+impl Default for MctsConfig {
+  fn default() -> Self {
+    Self {
+      limit: MctsLimit::Iterations(10_000),
+      exploration: std::f64::consts::SQRT_2,
+      seed: 0,
+      threads: 1,
+    }
+  }
+}
+
+/// The search statistics of a move of the current player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveStats {
+  pub global_pos: GlobalPos,
+  pub visits: u32,
+  /// mean reward of the move for the current player, 1 for a win and 0.5 for a draw
+  pub mean_reward: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+  /// move leading to this node, `None` for the root
+  global_pos: Option<GlobalPos>,
+  children: Vec<usize>,
+  /// legal moves without a child yet
+  untried: Vec<GlobalPos>,
+  visits: u32,
+  /// summed rewards of the player who played the move leading to this node
+  reward: f64,
+}
+
+impl Node {
+  fn new<Board: OuterBoardBackend>(
+    global_pos: Option<GlobalPos>,
+    round: &RoundState<Board>,
+  ) -> Self {
+    Self {
+      global_pos,
+      untried: round.legal_moves().collect(),
+      ..Self::default()
+    }
+  }
+}
+
+/// A search tree rooted at the current position of a round.
+#[derive(Debug, Clone)]
+pub struct Mcts<Board = OuterBoard> {
+  config: MctsConfig,
+  rng: StdRng,
+  round: RoundState<Board>,
+  /// arena of the tree nodes, the root is the first one
+  nodes: Vec<Node>,
+}
+
+impl<Board: OuterBoardBackend> Mcts<Board> {
+  pub fn new(round: RoundState<Board>, config: MctsConfig) -> Self {
+    Self {
+      config,
+      rng: StdRng::seed_from_u64(config.seed),
+      nodes: vec![Node::new(None, &round)],
+      round,
+    }
+  }
+
+  /// Changes the limit of the following searches.
+  pub fn set_limit(&mut self, limit: MctsLimit) {
+    self.config.limit = limit;
+  }
+
+  /// The position at the root of the tree.
+  pub fn round(&self) -> &RoundState<Board> {
+    &self.round
+  }
+
+  /// Grows the tree until the configured limit is reached and returns the number of iterations,
+  /// over all threads. The helper seeds are drawn from the main thread, so searches limited by
+  /// iterations stay reproducible.
+  pub fn search(&mut self) -> u64
+  where
+    Board: Send,
+  {
+    if self.config.threads <= 1 {
+      return self.search_alone();
+    }
+
+    let helpers: Vec<_> = (1..self.config.threads)
+      .map(|_| {
+        let config = MctsConfig {
+          seed: self.rng.gen(),
+          threads: 1,
+          ..self.config
+        };
+        Mcts::new(self.round.clone(), config)
+      })
+      .collect();
+    let (niterations, helpers) = thread::scope(|scope| {
+      let handles: Vec<_> = helpers
+        .into_iter()
+        .map(|mut helper| {
+          scope.spawn(move || {
+            let niterations = helper.search_alone();
+            (niterations, helper)
+          })
+        })
+        .collect();
+      let niterations = self.search_alone();
+      let helpers: Vec<_> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("search thread panicked"))
+        .collect();
+      (niterations, helpers)
+    });
+
+    let mut total = niterations;
+    for (niterations, helper) in helpers {
+      self.merge_node(ROOT, &helper, ROOT);
+      total += niterations;
+    }
+    total
+  }
+
+  /// The most visited move. `None` if nothing has been searched or the round has no legal moves.
+  pub fn best_move(&self) -> Option<GlobalPos> {
+    self.nodes[ROOT]
+      .children
+      .iter()
+      // the first one on ties, like in `move_stats`
+      .min_by_key(|&&child| std::cmp::Reverse(self.nodes[child].visits))
+      .and_then(|&child| self.nodes[child].global_pos)
+  }
+
+  /// Statistics of all searched moves, ordered by their number of visits, most visited first.
+  pub fn move_stats(&self) -> Vec<MoveStats> {
+    let mut stats: Vec<_> = self.nodes[ROOT]
+      .children
+      .iter()
+      .map(|&child| {
+        let node = &self.nodes[child];
+        MoveStats {
+          global_pos: node.global_pos.unwrap(),
+          visits: node.visits,
+          mean_reward: node.reward / node.visits as f64,
+        }
+      })
+      .collect();
+    stats.sort_by_key(|stats| std::cmp::Reverse(stats.visits));
+    stats
+  }
+
+  /// Plays the move at the root, keeping the subtree of the move.
+  pub fn advance(&mut self, global_pos: GlobalPos) -> Result<(), MoveError> {
+    self
+      .round
+      .try_play_move(self.round.current_player(), global_pos)?;
+
+    let child = self.nodes[ROOT]
+      .children
+      .iter()
+      .copied()
+      .find(|&child| self.nodes[child].global_pos == Some(global_pos));
+    let Some(child) = child else {
+      self.nodes = vec![Node::new(None, &self.round)];
+      return Ok(());
+    };
+    // moves the subtree into a new arena, breadth first
+    let mut old_nodes = std::mem::take(&mut self.nodes);
+    let mut nodes = vec![std::mem::take(&mut old_nodes[child])];
+    let mut i = 0;
+    while i < nodes.len() {
+      let children = std::mem::take(&mut nodes[i].children);
+      nodes[i].children = children
+        .into_iter()
+        .map(|child| {
+          nodes.push(std::mem::take(&mut old_nodes[child]));
+          nodes.len() - 1
+        })
+        .collect();
+      i += 1;
+    }
+    nodes[ROOT].global_pos = None;
+    self.nodes = nodes;
+    Ok(())
+  }
+
+  /// Moves the root to the position of the round,
+  /// reusing the tree if the round continues the root position.
+  pub fn sync(&mut self, round: &RoundState<Board>) {
+    let played: Vec<_> = round.move_history().collect();
+    let nknown = self.round.move_history().len();
+    let continues = played.len() >= nknown
+      && self
+        .round
+        .move_history()
+        .eq(played[..nknown].iter().copied())
+      && played[nknown..]
+        .iter()
+        .all(|&pos| self.advance(pos).is_ok())
+      && self.round.zobrist_hash() == round.zobrist_hash();
+    if !continues {
+      self.round = round.clone();
+      self.nodes = vec![Node::new(None, &self.round)];
+    }
+  }
+}
+
+// private methods
+impl<Board: OuterBoardBackend> Mcts<Board> {
+  fn search_alone(&mut self) -> u64 {
+    let start = Instant::now();
+    let mut niterations = 0;
+    loop {
+      let done = match self.config.limit {
+        MctsLimit::Iterations(limit) => niterations >= limit,
+        MctsLimit::Time(time) => start.elapsed() >= time,
+      };
+      if done {
+        return niterations;
+      }
+      self.run_iteration();
+      niterations += 1;
+    }
+  }
+
+  /// Adds the statistics of a node of another tree and of all its descendants to the node of the
+  /// same position, creating the nodes missing in this tree.
+  /// The round must be at the position of the node.
+  fn merge_node(&mut self, node: usize, other: &Self, other_node: usize) {
+    self.nodes[node].visits += other.nodes[other_node].visits;
+    self.nodes[node].reward += other.nodes[other_node].reward;
+    for &other_child in &other.nodes[other_node].children {
+      let global_pos = other.nodes[other_child]
+        .global_pos
+        .expect("only the root has no move");
+      let known = self.nodes[node]
+        .children
+        .iter()
+        .copied()
+        .find(|&child| self.nodes[child].global_pos == Some(global_pos));
+      self.play(global_pos);
+      let child = match known {
+        Some(child) => child,
+        None => {
+          self.nodes[node].untried.retain(|&pos| pos != global_pos);
+          let child = self.nodes.len();
+          self.nodes.push(Node::new(Some(global_pos), &self.round));
+          self.nodes[node].children.push(child);
+          child
+        }
+      };
+      self.merge_node(child, other, other_child);
+      self.round.undo_move();
+    }
+  }
+
+  fn run_iteration(&mut self) {
+    let root_player = self.round.current_player();
+    let mut path = vec![ROOT];
+    let mut nplayed = 0;
+
+    // selection
+    let mut node = ROOT;
+    while self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+      node = self.select_child(node);
+      self.play(self.nodes[node].global_pos.unwrap());
+      nplayed += 1;
+      path.push(node);
+    }
+
+    // expansion
+    if !self.nodes[node].untried.is_empty() {
+      let untried = &mut self.nodes[node].untried;
+      let global_pos = untried.swap_remove(self.rng.gen_range(0..untried.len()));
+      self.play(global_pos);
+      nplayed += 1;
+      let child = self.nodes.len();
+      self.nodes.push(Node::new(Some(global_pos), &self.round));
+      self.nodes[node].children.push(child);
+      path.push(child);
+    }
+
+    // playout
+    while let Some(global_pos) = self.round.legal_moves().choose(&mut self.rng) {
+      self.play(global_pos);
+      nplayed += 1;
+    }
+    let outcome = self.round.outcome();
+    for _ in 0..nplayed {
+      self.round.undo_move();
+    }
+
+    // backpropagation, the root player moves into the nodes at odd depths
+    for (depth, &node) in path.iter().enumerate() {
+      let mover = match depth % 2 {
+        1 => root_player,
+        _ => root_player.other(),
+      };
+      let node = &mut self.nodes[node];
+      node.visits += 1;
+      node.reward += match outcome {
+        Some(RoundOutcome::Win(p)) if p == mover => 1.0,
+        Some(RoundOutcome::Draw) => 0.5,
+        _ => 0.0,
+      };
+    }
+  }
+
+  /// Selects the child with the highest upper confidence bound.
+  fn select_child(&self, node: usize) -> usize {
+    let ln_visits = (self.nodes[node].visits as f64).ln();
+    let ucb = |child: usize| {
+      let child = &self.nodes[child];
+      let visits = child.visits as f64;
+      child.reward / visits + self.config.exploration * (ln_visits / visits).sqrt()
+    };
+    self.nodes[node]
+      .children
+      .iter()
+      .copied()
+      .max_by(|&a, &b| ucb(a).total_cmp(&ucb(b)))
+      .unwrap()
+  }
+
+  fn play(&mut self, global_pos: GlobalPos) {
+    self
+      .round
+      .try_play_move(self.round.current_player(), global_pos)
+      .expect("legal move must be playable");
+  }
+}
+
+impl Bot for Mcts {
+  fn choose_move(&mut self, round: &RoundState) -> GlobalPos {
+    self.sync(round);
+    self.search();
+    let best_move = self.best_move().expect("no legal moves left");
+    self.advance(best_move).unwrap();
+    best_move
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Mcts, MctsConfig, MctsLimit};
+  use crate::{
+    bot::{Bot, RandomBot},
+    game::{RoundOutcome, RoundState},
+    rules::RuleSet,
+    GlobalPos, PlayerSymbol,
+  };
+
+  fn config(iterations: u64, seed: u64) -> MctsConfig {
+    MctsConfig {
+      limit: MctsLimit::Iterations(iterations),
+      seed,
+      ..MctsConfig::default()
+    }
+  }
+
+  #[test]
+  fn check_mcts_statistics() {
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    let mut mcts = Mcts::new(round.clone(), config(200, 1));
+    assert_eq!(mcts.search(), 200);
+    let stats = mcts.move_stats();
+    assert_eq!(stats.len(), 81);
+    assert_eq!(stats.iter().map(|stats| stats.visits).sum::<u32>(), 200);
+    assert_eq!(mcts.best_move(), Some(stats[0].global_pos));
+
+    let mut same_seed = Mcts::new(round, config(200, 1));
+    same_seed.search();
+    assert_eq!(same_seed.move_stats(), stats);
+
+    // the subtree of the move is kept
+    let best_move = stats[0].global_pos;
+    mcts.advance(best_move).unwrap();
+    assert_eq!(mcts.round().move_history().last(), Some(best_move));
+    assert_eq!(
+      mcts
+        .move_stats()
+        .iter()
+        .map(|stats| stats.visits)
+        .sum::<u32>(),
+      stats[0].visits - 1
+    );
+  }
+
+  #[test]
+  fn check_mcts_finds_win() {
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X -".parse().unwrap();
+    let mut mcts = Mcts::new(round.clone(), config(1000, 2));
+    let best_move = mcts.choose_move(&round);
+    let mut round = round;
+    round.try_play_move(PlayerSymbol::X, best_move).unwrap();
+    assert_eq!(round.outcome(), Some(RoundOutcome::Win(PlayerSymbol::X)));
+  }
+
+  #[test]
+  fn check_parallel_mcts() {
+    let parallel = |iterations, seed| MctsConfig {
+      threads: 4,
+      ..config(iterations, seed)
+    };
+    let round = RoundState::new(PlayerSymbol::O, RuleSet::default());
+    let mut mcts = Mcts::new(round.clone(), parallel(25, 4));
+    assert_eq!(mcts.search(), 100);
+    let stats = mcts.move_stats();
+    assert_eq!(stats.iter().map(|stats| stats.visits).sum::<u32>(), 100);
+
+    let mut same_seed = Mcts::new(round, parallel(25, 4));
+    same_seed.search();
+    assert_eq!(same_seed.move_stats(), stats);
+
+    // the kept subtree holds the visits of all trees, each of which visited the node
+    // once more than its children when expanding it
+    let best_move = stats[0].global_pos;
+    mcts.advance(best_move).unwrap();
+    let child_visits: u32 = mcts.move_stats().iter().map(|stats| stats.visits).sum();
+    assert_eq!(mcts.nodes[0].visits, stats[0].visits);
+    assert!(stats[0].visits - child_visits <= 4);
+
+    // the winning move is forced into the top right sub-board, so few iterations find it
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X c3"
+      .parse()
+      .unwrap();
+    let mut mcts = Mcts::new(round.clone(), parallel(50, 5));
+    let best_move = mcts.choose_move(&round);
+    assert_eq!(best_move, GlobalPos::new(8, 1));
+  }
+
+  #[test]
+  fn check_mcts_plays_rounds() {
+    let mut round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    let mut mcts = Mcts::new(round.clone(), config(30, 3));
+    let mut random = RandomBot::new(3);
+    while round.outcome().is_none() {
+      let global_pos = match round.current_player() {
+        PlayerSymbol::X => mcts.choose_move(&round),
+        PlayerSymbol::O => random.choose_move(&round),
+      };
+      round
+        .try_play_move(round.current_player(), global_pos)
+        .unwrap();
+      mcts.sync(&round);
+      assert_eq!(mcts.round().zobrist_hash(), round.zobrist_hash());
+    }
+  }
+}
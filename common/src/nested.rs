@@ -0,0 +1,330 @@
+//! Rounds on board hierarchies of arbitrary depth.
+//!
+//! [`game::RoundState`](crate::game::RoundState) is specialized to the two levels of ultimate tic-tac-toe.
+//! [`NestedRoundState`] plays the same rules on any [`GenericBoard`], e.g. the 729 trivial tiles of
+//! "ultimate ultimate tic-tac-toe" ([`Depth3RoundState`]).
+//!
+//! The position of a move inside the sub-board at some level decides which sub-board at that level
+//! the next move has to be played in. If that board is not placeable, the constraint is relaxed
+//! one level at a time, until only the root board is left, which gives a free choice.
+
+use crate::{
+  board::{
+    tile::{TilePos, TileTrait},
+    GenericBoard, TileBoardState,
+  },
+  game::{MoveError, RoundOutcome},
+  GlobalPos, InnerPos, OuterBoard, OuterPos, PlayerSymbol,
+};
+
+/// Board of "ultimate ultimate tic-tac-toe", with 729 trivial tiles.
+pub type Depth3Board = GenericBoard<OuterBoard>;
+pub type Depth3RoundState = NestedRoundState<OuterBoard, 3>;
+
+/// Position of a trivial tile in a board hierarchy of depth `DEPTH` and side length `N`,
+/// given as the local positions from the root board down to the trivial tile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NestedPos<const DEPTH: usize, const N: usize = 3>([TilePos; DEPTH]);
+
+impl<const DEPTH: usize, const N: usize> NestedPos<DEPTH, N> {
+  pub fn new(path: [TilePos; DEPTH]) -> Self {
+    Self(path)
+  }
+  pub fn path(&self) -> &[TilePos; DEPTH] {
+    &self.0
+  }
+
+  /// Reads the local positions as base `N * N` digits, starting with the root board.
+  pub fn linear_idx(self) -> usize {
+    self
+      .0
+      .iter()
+      .fold(0, |idx, pos| idx * N * N + pos.linear_idx_in(N as u8))
+  }
+  pub fn from_linear_idx(mut idx: usize) -> Self {
+    let mut path = [TilePos::new(0, 0); DEPTH];
+    for pos in path.iter_mut().rev() {
+      *pos = TilePos::from_linear_idx_in(idx % (N * N), N as u8);
+      idx /= N * N;
+    }
+    assert!(idx == 0, "linear index out of range");
+    Self(path)
+  }
+
+  /// Iterates over all `(N * N)^DEPTH` positions, ordered by [`Self::linear_idx`].
+  pub fn all() -> impl Iterator<Item = Self> {
+    (0..(N * N).pow(DEPTH as u32)).map(Self::from_linear_idx)
+  }
+}
+
+impl<const DEPTH: usize, const N: usize> IntoIterator for NestedPos<DEPTH, N> {
+  type Item = TilePos;
+  type IntoIter = std::array::IntoIter<TilePos, DEPTH>;
+  fn into_iter(self) -> Self::IntoIter {
+    self.0.into_iter()
+  }
+}
+
+impl From<GlobalPos> for NestedPos<2> {
+  fn from(global_pos: GlobalPos) -> Self {
+    Self([
+      OuterPos::from(global_pos).into(),
+      InnerPos::from(global_pos).into(),
+    ])
+  }
+}
+impl From<NestedPos<2>> for GlobalPos {
+  fn from(nested_pos: NestedPos<2>) -> Self {
+    let [outer, inner] = nested_pos.0;
+    GlobalPos::from((
+      OuterPos::new(outer.x(), outer.y()),
+      InnerPos::new(inner.x(), inner.y()),
+    ))
+  }
+}
+
+/// The state of a single round on a board hierarchy of depth `DEPTH`.
+///
+/// `TileType` is the tile type of the root board, so the board is a `GenericBoard<TileType, N, K>`.
+/// `DEPTH` must match the depth of that board, which is checked at compile time.
+/// All levels of the hierarchy need to share the side length `N`,
+/// so that a tile position can name a board on the level below, which is checked at compile time too.
+#[allow(private_bounds)]
+#[derive(Debug, Clone)]
+pub struct NestedRoundState<
+  TileType: TileTrait,
+  const DEPTH: usize,
+  const N: usize = 3,
+  const K: usize = 3,
+> {
+  board: GenericBoard<TileType, N, K>,
+  curr_player: PlayerSymbol,
+  /// path of the board the next move has to be played in, empty for a free choice
+  curr_board_path: Vec<TilePos>,
+
+  /// stack of played moves, allowing to undo them
+  history: Vec<NestedPlayedMove<DEPTH, N>>,
+}
+
+/// A move on the history stack, together with the state needed to undo it.
+#[derive(Debug, Clone)]
+struct NestedPlayedMove<const DEPTH: usize, const N: usize> {
+  pos: NestedPos<DEPTH, N>,
+  prev_board_path: Vec<TilePos>,
+}
+
+#[allow(private_bounds)]
+impl<TileType: TileTrait + Default, const DEPTH: usize, const N: usize, const K: usize>
+  NestedRoundState<TileType, DEPTH, N, K>
+{
+  pub fn new(starting_player: PlayerSymbol) -> Self {
+    const {
+      assert!(
+        GenericBoard::<TileType, N, K>::DEPTH == DEPTH,
+        "depth mismatch"
+      );
+      assert!(
+        <GenericBoard<TileType, N, K> as TileTrait>::UNIFORM_SIDE_LENGTH,
+        "side length mismatch between levels"
+      );
+    };
+    Self {
+      board: GenericBoard::default(),
+      curr_player: starting_player,
+      curr_board_path: Vec::new(),
+      history: Vec::new(),
+    }
+  }
+
+  pub fn could_play_move(&self, player: PlayerSymbol, pos: NestedPos<DEPTH, N>) -> bool {
+    self.outcome().is_none()
+      && self.curr_player == player
+      && pos.0.starts_with(&self.curr_board_path)
+      && self.is_board_path_placeable(&pos.0[..DEPTH - 1])
+      && self.board.trivial_tile(pos).is_free()
+  }
+
+  /// Iterates over all moves the current player could play, ordered by [`NestedPos::linear_idx`].
+  /// Only the tiles of the current board are enumerated.
+  /// Yields nothing once the round has an outcome.
+  pub fn legal_moves(&self) -> impl Iterator<Item = NestedPos<DEPTH, N>> + '_ {
+    let path_len = self.curr_board_path.len();
+    (0..(N * N).pow((DEPTH - path_len) as u32))
+      .map(move |idx| {
+        // the index only covers the levels below the current board
+        let mut pos = NestedPos::from_linear_idx(idx);
+        pos.0[..path_len].copy_from_slice(&self.curr_board_path);
+        pos
+      })
+      .filter(move |&pos| self.could_play_move(self.curr_player, pos))
+  }
+
+  pub fn try_play_move(
+    &mut self,
+    player: PlayerSymbol,
+    pos: NestedPos<DEPTH, N>,
+  ) -> Result<(), MoveError> {
+    if self.outcome().is_some() {
+      return Err(MoveError::RoundOver);
+    }
+    if self.curr_player != player {
+      return Err(MoveError::WrongPlayer);
+    }
+    if !pos.0.starts_with(&self.curr_board_path) {
+      return Err(MoveError::WrongOuterPos);
+    }
+    self
+      .board
+      .try_place_symbol(pos, player)
+      .map_err(MoveError::PlaceSymbol)?;
+
+    let prev_board_path = std::mem::take(&mut self.curr_board_path);
+    self.history.push(NestedPlayedMove {
+      pos,
+      prev_board_path,
+    });
+    self.update_board_path(pos);
+    self.curr_player.switch();
+    Ok(())
+  }
+
+  /// Takes back the last played move and returns its position.
+  /// Returns `None` if no move has been played yet.
+  pub fn undo_move(&mut self) -> Option<NestedPos<DEPTH, N>> {
+    let NestedPlayedMove {
+      pos,
+      prev_board_path,
+    } = self.history.pop()?;
+    self
+      .board
+      .try_remove_symbol(pos)
+      .expect("played move must be removable");
+    self.curr_board_path = prev_board_path;
+    self.curr_player.switch();
+    Some(pos)
+  }
+
+  /// The moves played so far, in order.
+  pub fn move_history(&self) -> impl ExactSizeIterator<Item = NestedPos<DEPTH, N>> + '_ {
+    self.history.iter().map(|played| played.pos)
+  }
+
+  pub fn board(&self) -> &GenericBoard<TileType, N, K> {
+    &self.board
+  }
+  pub fn current_player(&self) -> PlayerSymbol {
+    self.curr_player
+  }
+  /// Path of the board the next move has to be played in. Empty for a free choice.
+  pub fn current_board_path(&self) -> &[TilePos] {
+    &self.curr_board_path
+  }
+
+  pub fn outcome(&self) -> Option<RoundOutcome> {
+    match self.board.board_state() {
+      TileBoardState::Won(p) => Some(RoundOutcome::Win(p)),
+      TileBoardState::Drawn => Some(RoundOutcome::Draw),
+      TileBoardState::FullyDrawn => Some(RoundOutcome::Draw),
+      _ => None,
+    }
+  }
+}
+
+// private methods
+#[allow(private_bounds)]
+impl<TileType: TileTrait + Default, const DEPTH: usize, const N: usize, const K: usize>
+  NestedRoundState<TileType, DEPTH, N, K>
+{
+  /// Whether all boards along the path, excluding the root board, are placeable.
+  fn is_board_path_placeable(&self, path: &[TilePos]) -> bool {
+    (1..=path.len()).all(|len| {
+      self
+        .board
+        .tile_state_at(path[..len].iter().copied())
+        .is_placeable()
+    })
+  }
+
+  /// Sends the next move to the board at the path of the last move without its root position,
+  /// relaxing this constraint until the board is placeable.
+  fn update_board_path(&mut self, last_move_pos: NestedPos<DEPTH, N>) {
+    let mut path = last_move_pos.0[1..].to_vec();
+    while !self.is_board_path_placeable(&path) {
+      path.pop();
+    }
+    self.curr_board_path = path;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::{Depth3RoundState, NestedPos, NestedRoundState};
+  use crate::{
+    board::{tile::TrivialTileState, GenericBoard},
+    game::RoundState,
+    rules::RuleSet,
+    GlobalPos, InnerBoard, PlayerSymbol,
+  };
+
+  #[test]
+  fn check_depth_2_matches_round_state() {
+    let mut rng = StdRng::seed_from_u64(6);
+    for _ in 0..30 {
+      let starting_player: PlayerSymbol = rng.gen();
+      let mut round = RoundState::new(starting_player, RuleSet::default());
+      let mut nested = NestedRoundState::<InnerBoard, 2>::new(starting_player);
+      while round.outcome().is_none() {
+        let mut legal_moves: Vec<_> = round.legal_moves().collect();
+        let mut nested_moves: Vec<_> = nested.legal_moves().map(GlobalPos::from).collect();
+        legal_moves.sort_by_key(|pos| pos.linear_idx());
+        nested_moves.sort_by_key(|pos| pos.linear_idx());
+        assert_eq!(legal_moves, nested_moves);
+
+        let chosen_tile = *legal_moves.choose(&mut rng).unwrap();
+        let player = round.current_player();
+        round.try_play_move(player, chosen_tile).unwrap();
+        nested.try_play_move(player, chosen_tile.into()).unwrap();
+      }
+      assert_eq!(nested.outcome(), round.outcome());
+    }
+  }
+
+  #[test]
+  fn check_depth_3_rounds() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut round = Depth3RoundState::new(PlayerSymbol::X);
+    assert_eq!(round.legal_moves().count(), 729);
+
+    let first: NestedPos<3> = "a3/b2/c1".parse().unwrap();
+    round.try_play_move(PlayerSymbol::X, first).unwrap();
+    assert_eq!(round.current_board_path(), &first.path()[1..]);
+    assert_eq!(round.legal_moves().count(), 9);
+
+    while round.outcome().is_none() {
+      let path = round.current_board_path().to_vec();
+      assert!(round.legal_moves().all(|pos| pos.path().starts_with(&path)));
+      let chosen = round.legal_moves().choose(&mut rng).unwrap();
+      round.try_play_move(round.current_player(), chosen).unwrap();
+    }
+    assert_eq!(round.legal_moves().count(), 0);
+    while round.undo_move().is_some() {}
+    assert_eq!(round.legal_moves().count(), 729);
+  }
+
+  #[test]
+  fn check_4x4_boards() {
+    type Board4x4 = GenericBoard<TrivialTileState, 4, 4>;
+    let mut rng = StdRng::seed_from_u64(8);
+    let mut round = NestedRoundState::<Board4x4, 2, 4, 4>::new(PlayerSymbol::O);
+    assert_eq!(round.legal_moves().count(), 256);
+    while round.outcome().is_none() {
+      let chosen = round.legal_moves().choose(&mut rng).unwrap();
+      round.try_play_move(round.current_player(), chosen).unwrap();
+      if let [pos] = round.current_board_path() {
+        assert_eq!(*pos, chosen.path()[1]);
+      }
+    }
+  }
+}
@@ -0,0 +1,621 @@
+//! Text notations for moves and whole [`RoundState`] positions.
+//!
+//! # Moves
+//!
+//! [`OuterPos`] and [`InnerPos`] are named by a file `a`-`c` (left to right)
+//! and a rank `1`-`3` (bottom to top), so `a3` is the top left and `b2` the center tile.
+//! A [`GlobalPos`] names its outer board and its inner tile separated by a slash,
+//! e.g. `a3/b2` is the center tile of the top left board.
+//! A [`NestedPos`] of any depth lists its local positions the same way, starting with the root board.
+//! A [`PlayerAction`] is either such a move or `resign`.
+//!
+//! # Positions
+//!
+//! Positions are written in the spirit of chess FEN and consist of three to five fields separated
+//! by whitespace:
+//!
+//! 1. The 81 trivial tiles as 9 rows separated by `/`, starting with the top row (`y = 0`).
+//!    Each row lists its tiles from left to right (`x = 0` to `x = 8`),
+//!    using `X` and `O` for occupied tiles and a single digit `1`-`9` for a run of free tiles.
+//!    Runs are not split, so `45` is invalid where `9` is meant.
+//! 2. The player to move, `X` or `O`.
+//! 3. The outer board the player has to play in, e.g. `b2`, or `-` for a free choice.
+//! 4. The [`RuleSet`] the round is played by, e.g. `won-playable`.
+//!    The field is omitted for the standard rules, unless the winners field follows.
+//! 5. The winners of boards with lines of both players, which their tiles do not decide, e.g.
+//!    `a3:O,c1:X`. `-` stands for the outer board. The field is omitted if there are no such boards.
+//!
+//! The empty board with `X` to move reads `9/9/9/9/9/9/9/9/9 X -`.
+//!
+//! The notation only describes the position, so a parsed [`RoundState`] has no move history.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+  board::tile::{TilePos, TrivialTileState},
+  game::{PlayerAction, RoundState},
+  nested::NestedPos,
+  rules::RuleSet,
+  GlobalPos, InnerPos, OuterBoardBackend, OuterPos, PlayerSymbol,
+};
+
+const NO_OUTER_POS: &str = "-";
+const GIVE_UP: &str = "resign";
+
+fn fmt_local_pos(f: &mut fmt::Formatter<'_>, [x, y]: [u8; 2]) -> fmt::Result {
+  write!(f, "{}{}", (b'a' + x) as char, (b'3' - y) as char)
+}
+fn parse_local_pos(s: &str) -> Option<[u8; 2]> {
+  match *s.as_bytes() {
+    [file @ b'a'..=b'c', rank @ b'1'..=b'3'] => Some([file - b'a', b'3' - rank]),
+    _ => None,
+  }
+}
+
+impl fmt::Display for OuterPos {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt_local_pos(f, (*self).into())
+  }
+}
+impl FromStr for OuterPos {
+  type Err = PosParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    parse_local_pos(s)
+      .map(OuterPos::new_arr)
+      .ok_or_else(|| PosParseError::new(s, "a1-c3"))
+  }
+}
+
+impl fmt::Display for InnerPos {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt_local_pos(f, (*self).into())
+  }
+}
+impl FromStr for InnerPos {
+  type Err = PosParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    parse_local_pos(s)
+      .map(InnerPos::new_arr)
+      .ok_or_else(|| PosParseError::new(s, "a1-c3"))
+  }
+}
+
+impl fmt::Display for GlobalPos {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}/{}", OuterPos::from(*self), InnerPos::from(*self))
+  }
+}
+impl FromStr for GlobalPos {
+  type Err = PosParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let err = || PosParseError::new(s, "a1/a1-c3/c3");
+    let (outer, inner) = s.split_once('/').ok_or_else(err)?;
+    let outer = outer.parse::<OuterPos>().map_err(|_| err())?;
+    let inner = inner.parse::<InnerPos>().map_err(|_| err())?;
+    Ok(GlobalPos::from((outer, inner)))
+  }
+}
+
+impl<const DEPTH: usize> fmt::Display for NestedPos<DEPTH> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, &pos) in self.path().iter().enumerate() {
+      if i > 0 {
+        write!(f, "/")?;
+      }
+      fmt_local_pos(f, pos.into())?;
+    }
+    Ok(())
+  }
+}
+impl<const DEPTH: usize> FromStr for NestedPos<DEPTH> {
+  type Err = PosParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let err = || PosParseError::new(s, "a1-c3 per level, separated by `/`");
+    let mut parts = s.split('/');
+    let mut path = [TilePos::new(0, 0); DEPTH];
+    for pos in &mut path {
+      let part = parts.next().ok_or_else(err)?;
+      *pos = TilePos::new_arr(parse_local_pos(part).ok_or_else(err)?);
+    }
+    match parts.next() {
+      Some(_) => Err(err()),
+      None => Ok(NestedPos::new(path)),
+    }
+  }
+}
+
+impl fmt::Display for PlayerAction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PlayerAction::MakeMove(global_pos) => write!(f, "{}", global_pos),
+      PlayerAction::GiveUp => write!(f, "{}", GIVE_UP),
+    }
+  }
+}
+impl FromStr for PlayerAction {
+  type Err = PosParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      GIVE_UP => Ok(PlayerAction::GiveUp),
+      _ => s
+        .parse()
+        .map(PlayerAction::MakeMove)
+        .map_err(|_| PosParseError::new(s, "a move like a1/c3 or resign")),
+    }
+  }
+}
+
+/// A move or position in move notation that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosParseError {
+  input: String,
+  expected: &'static str,
+}
+impl PosParseError {
+  fn new(input: &str, expected: &'static str) -> Self {
+    Self {
+      input: input.to_string(),
+      expected,
+    }
+  }
+}
+impl fmt::Display for PosParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid `{}`, expected {}", self.input, self.expected)
+  }
+}
+impl std::error::Error for PosParseError {}
+
+impl<Board: OuterBoardBackend> fmt::Display for RoundState<Board> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for y in 0..9 {
+      if y != 0 {
+        write!(f, "/")?;
+      }
+      let mut nfree = 0;
+      for x in 0..9 {
+        match self.board().trivial_tile(GlobalPos::new(x, y)) {
+          TrivialTileState::Free => nfree += 1,
+          TrivialTileState::Won(p) => {
+            if nfree != 0 {
+              write!(f, "{}", nfree)?;
+              nfree = 0;
+            }
+            write!(f, "{}", p.as_char())?;
+          }
+        }
+      }
+      if nfree != 0 {
+        write!(f, "{}", nfree)?;
+      }
+    }
+
+    write!(f, " {} ", self.current_player().as_char())?;
+    match self.current_outer_pos() {
+      Some(outer_pos) => write!(f, "{}", outer_pos)?,
+      None => write!(f, "{}", NO_OUTER_POS)?,
+    }
+
+    let board = self.board();
+    let mut disputed = OuterPos::all()
+      .map(Some)
+      .chain([None])
+      .filter(|&outer_pos| board.is_board_disputed(outer_pos))
+      .peekable();
+    if disputed.peek().is_none() {
+      return match self.rules().is_standard() {
+        true => Ok(()),
+        false => write!(f, " {}", self.rules()),
+      };
+    }
+    write!(f, " {} ", self.rules())?;
+    for (i, outer_pos) in disputed.enumerate() {
+      if i > 0 {
+        write!(f, ",")?;
+      }
+      let winner = board
+        .winner(outer_pos)
+        .expect("a board with lines of both players is won");
+      match outer_pos {
+        Some(outer_pos) => write!(f, "{}", outer_pos)?,
+        None => write!(f, "{}", NO_OUTER_POS)?,
+      }
+      write!(f, ":{}", winner.as_char())?;
+    }
+    Ok(())
+  }
+}
+
+impl<Board: OuterBoardBackend> FromStr for RoundState<Board> {
+  type Err = PositionParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut fields = s.split_whitespace();
+    let mut next_field =
+      |name: &'static str| fields.next().ok_or(PositionParseError::MissingField(name));
+    let tiles_field = next_field("tiles")?;
+    let player_field = next_field("player to move")?;
+    let outer_pos_field = next_field("outer board")?;
+    let rules = match fields.next() {
+      Some(rules_field) => rules_field
+        .parse::<RuleSet>()
+        .map_err(|_| PositionParseError::InvalidRules(rules_field.to_string()))?,
+      None => RuleSet::default(),
+    };
+    let winners = match fields.next() {
+      Some(winners_field) => parse_winners(winners_field)?,
+      None => Vec::new(),
+    };
+    if let Some(field) = fields.next() {
+      return Err(PositionParseError::TrailingField(field.to_string()));
+    }
+
+    let tiles = parse_tiles(tiles_field)?;
+
+    let mut player_chars = player_field.chars();
+    let curr_player = match (
+      player_chars.next().and_then(PlayerSymbol::from_char),
+      player_chars.next(),
+    ) {
+      (Some(player), None) => player,
+      _ => return Err(PositionParseError::InvalidPlayer(player_field.to_string())),
+    };
+
+    let count = |player| {
+      tiles
+        .iter()
+        .filter(|&&tile| tile == TrivialTileState::Won(player))
+        .count()
+    };
+    let (nx, no) = (count(PlayerSymbol::X), count(PlayerSymbol::O));
+    let consistent = match curr_player {
+      PlayerSymbol::X => nx == no || no == nx + 1,
+      PlayerSymbol::O => nx == no || nx == no + 1,
+    };
+    if !consistent {
+      return Err(PositionParseError::BadSymbolCount {
+        nx,
+        no,
+        curr_player,
+      });
+    }
+
+    let curr_outer_pos = match outer_pos_field {
+      NO_OUTER_POS => None,
+      _ => Some(
+        outer_pos_field
+          .parse()
+          .map_err(|_| PositionParseError::InvalidOuterPos(outer_pos_field.to_string()))?,
+      ),
+    };
+
+    let board = Board::from_trivial_tiles(
+      |pos| tiles[pos.linear_idx()],
+      |outer_pos| {
+        winners
+          .iter()
+          .find(|&&(pos, _)| pos == outer_pos)
+          .map(|&(_, winner)| winner)
+      },
+    )
+    .map_err(|_| PositionParseError::UndecidedWinner)?;
+    if let Some(&(outer_pos, _)) = winners
+      .iter()
+      .find(|&&(outer_pos, _)| !board.is_board_disputed(outer_pos))
+    {
+      return Err(PositionParseError::UndisputedWinner(outer_pos));
+    }
+    let round = RoundState::from_position(board, curr_player, curr_outer_pos, rules);
+    if let Some(outer_pos) = curr_outer_pos {
+      if !round.is_sub_board_playable(outer_pos) {
+        return Err(PositionParseError::OuterPosNotPlaceable(outer_pos));
+      }
+    }
+    Ok(round)
+  }
+}
+
+/// Parses the winners field into the winner of each listed board.
+fn parse_winners(field: &str) -> Result<Vec<(Option<OuterPos>, PlayerSymbol)>, PositionParseError> {
+  let mut winners: Vec<(Option<OuterPos>, PlayerSymbol)> = Vec::new();
+  for entry in field.split(',') {
+    let err = || PositionParseError::InvalidWinner(entry.to_string());
+    let (pos, winner) = entry.split_once(':').ok_or_else(err)?;
+    let outer_pos = match pos {
+      NO_OUTER_POS => None,
+      _ => Some(pos.parse().map_err(|_| err())?),
+    };
+    let mut winner_chars = winner.chars();
+    let winner = match (
+      winner_chars.next().and_then(PlayerSymbol::from_char),
+      winner_chars.next(),
+    ) {
+      (Some(winner), None) => winner,
+      _ => return Err(err()),
+    };
+    if winners.iter().any(|&(pos, _)| pos == outer_pos) {
+      return Err(err());
+    }
+    winners.push((outer_pos, winner));
+  }
+  Ok(winners)
+}
+
+/// Parses the tiles field into tiles indexed by [`GlobalPos::linear_idx`].
+fn parse_tiles(field: &str) -> Result<[TrivialTileState; 81], PositionParseError> {
+  let rows: Vec<_> = field.split('/').collect();
+  if rows.len() != 9 {
+    return Err(PositionParseError::BadRowCount(rows.len()));
+  }
+
+  let mut tiles = [TrivialTileState::Free; 81];
+  for (y, row) in rows.into_iter().enumerate() {
+    let mut x = 0;
+    let mut prev_run = false;
+    for c in row.chars() {
+      let (tile, run) = match c {
+        '1'..='9' if prev_run => return Err(PositionParseError::SplitFreeRun { row: y }),
+        '1'..='9' => (TrivialTileState::Free, c as usize - '0' as usize),
+        _ => match PlayerSymbol::from_char(c) {
+          Some(p) => (TrivialTileState::Won(p), 1),
+          None => return Err(PositionParseError::InvalidTileChar { row: y, c }),
+        },
+      };
+      prev_run = tile == TrivialTileState::Free;
+      for _ in 0..run {
+        if x < 9 {
+          tiles[GlobalPos::new(x as u8, y as u8).linear_idx()] = tile;
+        }
+        x += 1;
+      }
+    }
+    if x != 9 {
+      return Err(PositionParseError::BadRowLength { row: y, len: x });
+    }
+  }
+  Ok(tiles)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionParseError {
+  MissingField(&'static str),
+  TrailingField(String),
+  BadRowCount(usize),
+  BadRowLength {
+    row: usize,
+    len: usize,
+  },
+  InvalidTileChar {
+    row: usize,
+    c: char,
+  },
+  /// a run of free tiles written as several digits
+  SplitFreeRun {
+    row: usize,
+  },
+  InvalidPlayer(String),
+  BadSymbolCount {
+    nx: usize,
+    no: usize,
+    curr_player: PlayerSymbol,
+  },
+  InvalidOuterPos(String),
+  InvalidRules(String),
+  OuterPosNotPlaceable(OuterPos),
+  /// a malformed or repeated entry of the winners field
+  InvalidWinner(String),
+  /// a board with lines of both players that the winners field does not list
+  UndecidedWinner,
+  /// a winner listed for a board that its tiles decide
+  UndisputedWinner(Option<OuterPos>),
+}
+
+impl fmt::Display for PositionParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::MissingField(name) => write!(f, "missing field: {}", name),
+      Self::TrailingField(field) => write!(f, "unexpected trailing field `{}`", field),
+      Self::BadRowCount(n) => write!(f, "expected 9 rows, found {}", n),
+      Self::BadRowLength { row, len } => {
+        write!(f, "row {} describes {} tiles instead of 9", row + 1, len)
+      }
+      Self::InvalidTileChar { row, c } => write!(f, "invalid tile `{}` in row {}", c, row + 1),
+      Self::SplitFreeRun { row } => write!(
+        f,
+        "run of free tiles in row {} is split into several digits",
+        row + 1
+      ),
+      Self::InvalidPlayer(s) => write!(f, "invalid player to move `{}`, expected X or O", s),
+      Self::BadSymbolCount {
+        nx,
+        no,
+        curr_player,
+      } => write!(
+        f,
+        "{} X and {} O symbols are impossible with {} to move",
+        nx,
+        no,
+        curr_player.as_char()
+      ),
+      Self::InvalidOuterPos(s) => write!(f, "invalid outer board `{}`, expected a1-c3 or -", s),
+      Self::InvalidRules(s) => write!(f, "invalid rules `{}`", s),
+      Self::OuterPosNotPlaceable(outer_pos) => write!(
+        f,
+        "outer board {} is decided and cannot be played in",
+        outer_pos
+      ),
+      Self::InvalidWinner(s) => write!(f, "invalid winner `{}`, expected e.g. a3:O", s),
+      Self::UndecidedWinner => write!(f, "a board with lines of both players has no winner"),
+      Self::UndisputedWinner(Some(outer_pos)) => write!(
+        f,
+        "sub-board {} does not have lines of both players",
+        outer_pos
+      ),
+      Self::UndisputedWinner(None) => {
+        write!(f, "the outer board does not have lines of both players")
+      }
+    }
+  }
+}
+impl std::error::Error for PositionParseError {}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::PositionParseError;
+  use crate::{
+    bitboard::BitBoard,
+    game::{PlayerAction, RoundState},
+    nested::NestedPos,
+    rules::{RuleSet, WonBoardRule},
+    GlobalPos, InnerPos, OuterPos, PlayerSymbol,
+  };
+
+  #[test]
+  fn check_move_notation() {
+    assert_eq!(OuterPos::new(0, 0).to_string(), "a3");
+    assert_eq!(InnerPos::new(2, 2).to_string(), "c1");
+    assert_eq!(GlobalPos::new(4, 7).to_string(), "b1/b2");
+    assert_eq!(
+      PlayerAction::MakeMove(GlobalPos::new(0, 8)).to_string(),
+      "a1/a1"
+    );
+    assert_eq!(PlayerAction::GiveUp.to_string(), "resign");
+
+    for pos in GlobalPos::all() {
+      assert_eq!(pos.to_string().parse::<GlobalPos>().unwrap(), pos);
+    }
+    assert!(matches!(
+      "resign".parse::<PlayerAction>(),
+      Ok(PlayerAction::GiveUp)
+    ));
+    for invalid in ["", "b2", "b2/", "b2/d1", "b0/a1", "B2/a1", "b2/a1/a1"] {
+      assert!(invalid.parse::<GlobalPos>().is_err(), "{}", invalid);
+    }
+
+    for pos in GlobalPos::all() {
+      assert_eq!(NestedPos::<2>::from(pos).to_string(), pos.to_string());
+    }
+    let nested: NestedPos<3> = "a3/b2/c1".parse().unwrap();
+    assert_eq!(nested.to_string(), "a3/b2/c1");
+    assert!("a3/b2".parse::<NestedPos<3>>().is_err());
+    assert!("a3/b2/c1/a1".parse::<NestedPos<3>>().is_err());
+  }
+
+  #[test]
+  fn check_notation_roundtrip() {
+    let empty = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    assert_eq!(empty.to_string(), "9/9/9/9/9/9/9/9/9 X -");
+
+    let mut rng = StdRng::seed_from_u64(5);
+    for _ in 0..20 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      while round.outcome().is_none() {
+        let notation = round.to_string();
+        let parsed: RoundState = notation.parse().unwrap();
+        assert_eq!(parsed.board(), round.board());
+        assert_eq!(parsed.current_player(), round.current_player());
+        assert_eq!(parsed.current_outer_pos(), round.current_outer_pos());
+        assert_eq!(parsed.zobrist_hash(), round.zobrist_hash());
+        assert_eq!(parsed.to_string(), notation);
+
+        let bit_parsed: RoundState<BitBoard> = notation.parse().unwrap();
+        assert_eq!(bit_parsed.to_string(), notation);
+
+        let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+      }
+    }
+  }
+
+  #[test]
+  fn check_notation_coordinates() {
+    let mut round = RoundState::new(PlayerSymbol::O, RuleSet::default());
+    round
+      .try_play_move(PlayerSymbol::O, GlobalPos::new(1, 0))
+      .unwrap();
+    assert_eq!(round.to_string(), "1O7/9/9/9/9/9/9/9/9 X b3");
+    assert_eq!(round.current_outer_pos(), Some(OuterPos::new(1, 0)));
+  }
+
+  #[test]
+  fn check_notation_errors() {
+    use PositionParseError as E;
+    let parse = |s: &str| s.parse::<RoundState>().map(|_| ()).unwrap_err();
+
+    assert_eq!(parse("9/9/9/9/9/9/9/9/9 X"), E::MissingField("outer board"));
+    assert_eq!(
+      parse("9/9/9/9/9/9/9/9/9 X - majority a3:X 1"),
+      E::TrailingField("1".into())
+    );
+    assert_eq!(parse("9/9/9/9/9/9/9/9 X -"), E::BadRowCount(8));
+    assert_eq!(
+      parse("9/9/9/9/9X/9/9/9/9 X -"),
+      E::BadRowLength { row: 4, len: 10 }
+    );
+    assert_eq!(parse("9/9/9/9/45/9/9/9/9 X -"), E::SplitFreeRun { row: 4 });
+    assert_eq!(parse("9/9/9/9/X44/9/9/9/9 O -"), E::SplitFreeRun { row: 4 });
+    assert_eq!(
+      parse("9/9/8Z/9/9/9/9/9/9 X -"),
+      E::InvalidTileChar { row: 2, c: 'Z' }
+    );
+    assert_eq!(parse("9/9/9/9/9/9/9/9/9 x -"), E::InvalidPlayer("x".into()));
+    assert_eq!(
+      parse("XX7/9/9/9/9/9/9/9/9 O -"),
+      E::BadSymbolCount {
+        nx: 2,
+        no: 0,
+        curr_player: PlayerSymbol::O
+      }
+    );
+    assert_eq!(
+      parse("9/9/9/9/9/9/9/9/9 X d1"),
+      E::InvalidOuterPos("d1".into())
+    );
+    assert_eq!(
+      parse("9/9/9/9/9/9/9/9/9 X - 1"),
+      E::InvalidRules("1".into())
+    );
+    assert_eq!(
+      parse("XXX3OO1/9/9/9/9/9/9/9/9 O a3"),
+      E::OuterPosNotPlaceable(OuterPos::new(0, 0))
+    );
+
+    let disputed = "OOO3XX1/XXX6/9/O8/9/9/9/9/9 O c2 won-playable";
+    assert_eq!(parse(disputed), E::UndecidedWinner);
+    for invalid in ["a3", "a3:x", "d1:O", "a3:OX", "a3:O,a3:O"] {
+      assert_eq!(
+        parse(&format!("{} {}", disputed, invalid)),
+        E::InvalidWinner(invalid.rsplit(',').next().unwrap().into())
+      );
+    }
+    assert_eq!(
+      parse(&format!("{} a3:O,b3:X", disputed)),
+      E::UndisputedWinner(Some(OuterPos::new(1, 0)))
+    );
+    assert_eq!(
+      parse(&format!("{} a3:O,-:X", disputed)),
+      E::UndisputedWinner(None)
+    );
+  }
+
+  #[test]
+  fn check_notation_rules() {
+    let rules = RuleSet {
+      won_board: WonBoardRule::Playable,
+      ..RuleSet::default()
+    };
+    // O has to play in the top left sub-board, which X won
+    let notation = "XXX3OO1/9/9/9/9/9/9/9/9 O a3 won-playable";
+    let round: RoundState = notation.parse().unwrap();
+    assert_eq!(round.rules(), rules);
+    assert_eq!(round.to_string(), notation);
+    assert_eq!(round.legal_moves().count(), 6);
+
+    let standard: RoundState = "9/9/9/9/9/9/9/9/9 X - standard".parse().unwrap();
+    assert_eq!(standard.to_string(), "9/9/9/9/9/9/9/9/9 X -");
+  }
+}
@@ -0,0 +1,131 @@
+//! Move generation validation by counting the leaf nodes of the game tree ("perft").
+//!
+//! Known node counts catch bugs in move generation, forced outer boards and outcome handling,
+//! which are hard to spot in single games. All board backends have to produce the same counts.
+
+use crate::{game::RoundState, GlobalPos, OuterBoardBackend};
+
+impl<Board: OuterBoardBackend> RoundState<Board> {
+  /// Counts the positions reachable in exactly `depth` moves.
+  /// Rounds ending earlier do not contribute.
+  pub fn perft(&self, depth: usize) -> u64 {
+    perft_rec(&mut self.clone(), depth)
+  }
+
+  /// Like [`Self::perft`], but broken down by the first move, in the order of [`Self::legal_moves`].
+  pub fn divide(&self, depth: usize) -> Vec<(GlobalPos, u64)> {
+    assert!(depth >= 1, "divide needs at least one move");
+    let mut round = self.clone();
+    let moves: Vec<_> = round.legal_moves().collect();
+    moves
+      .into_iter()
+      .map(|global_pos| {
+        round
+          .try_play_move(round.current_player(), global_pos)
+          .expect("legal move must be playable");
+        let nodes = perft_rec(&mut round, depth - 1);
+        round.undo_move();
+        (global_pos, nodes)
+      })
+      .collect()
+  }
+}
+
+fn perft_rec<Board: OuterBoardBackend>(round: &mut RoundState<Board>, depth: usize) -> u64 {
+  match depth {
+    0 => 1,
+    // bulk counting at the frontier
+    1 => round.legal_moves().count() as u64,
+    _ => {
+      let moves: Vec<_> = round.legal_moves().collect();
+      moves
+        .into_iter()
+        .map(|global_pos| {
+          round
+            .try_play_move(round.current_player(), global_pos)
+            .expect("legal move must be playable");
+          let nodes = perft_rec(round, depth - 1);
+          round.undo_move();
+          nodes
+        })
+        .sum()
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::{bitboard::BitBoard, game::RoundState, rules::RuleSet, PlayerSymbol};
+
+  /// Reference counts from the empty board, starting at depth 1.
+  const EMPTY_BOARD_COUNTS: [u64; 4] = [81, 720, 6336, 55080];
+
+  /// Reference positions in [`crate::notation`], with their counts starting at depth 1.
+  const POSITIONS: [(&str, [u64; 3]); 5] = [
+    // free choice, with won sub-boards
+    (
+      "OXX1OXO1O/X1O1X1XXX/OXXXO2O1/O1OXXOXOX/X1OX1O1XO/O2O4X/X1OO2OO1/1O2OX1O1/XXXOX4 X -",
+      [18, 150, 1263],
+    ),
+    // forced into a drawn, but still placeable sub-board
+    (
+      "OOXX1OXO1/XXOX1X1OX/OXO1X2O1/OO1O2X1O/8O/1XO1XXXX1/OXXXOXX2/X1O3OO1/O1XOOOXX1 O b2",
+      [6, 44, 304],
+    ),
+    // rounds ending within the horizon
+    (
+      "OOX1XXXOO/OXXXXOO1O/1OOOOXXO1/XOXO2OOO/4O2XX/1OO2OO1O/X2X1XXOX/XXX1OXXO1/O2X1XOXX X b3",
+      [1, 1, 7],
+    ),
+    (
+      "OXOO2O1O/OOX1O1XOX/XOX1OO1OX/OXXX2XOX/OX1OXO1X1/O1XO1XXOO/1X1XOX1XO/XXXOO1X1O/3XX3O X c3",
+      [2, 6, 6],
+    ),
+    (
+      "1OOXOO2X/OOX1X3X/OXXO1XX1X/XX1XOOXXX/OX1OXXXO1/XX1XOO1OO/O1OOXXXXO/2OOOOOO1/XOO2XXXO O -",
+      [1, 0, 0],
+    ),
+  ];
+
+  #[test]
+  fn check_empty_board_perft() {
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    let bit_round = RoundState::<BitBoard>::new_with_backend(PlayerSymbol::X, RuleSet::default());
+    for (depth, &expected) in (1..).zip(EMPTY_BOARD_COUNTS.iter()) {
+      assert_eq!(round.perft(depth), expected, "depth {}", depth);
+      assert_eq!(bit_round.perft(depth), expected, "depth {}", depth);
+    }
+    assert_eq!(round.perft(0), 1);
+  }
+
+  #[test]
+  fn check_position_perft() {
+    for (position, counts) in POSITIONS {
+      let round: RoundState = position.parse().unwrap();
+      let bit_round: RoundState<BitBoard> = position.parse().unwrap();
+      for (depth, &expected) in (1..).zip(counts.iter()) {
+        assert_eq!(round.perft(depth), expected, "{} depth {}", position, depth);
+        assert_eq!(
+          bit_round.perft(depth),
+          expected,
+          "{} depth {}",
+          position,
+          depth
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn check_divide_sums_to_perft() {
+    let (position, counts) = POSITIONS[0];
+    let round: RoundState = position.parse().unwrap();
+    let divided = round.divide(3);
+    assert_eq!(divided.len() as u64, counts[0]);
+    assert!(divided.iter().map(|&(pos, _)| pos).eq(round.legal_moves()));
+    assert_eq!(
+      divided.iter().map(|&(_, nodes)| nodes).sum::<u64>(),
+      counts[2]
+    );
+  }
+}
@@ -0,0 +1,1035 @@
+//! A line-based text protocol for engines running in their own process, in the spirit of UCI.
+//!
+//! The player (a GUI, server or arena) writes [`Command`]s to the standard input of the engine,
+//! which answers with [`Response`]s on its standard output. Every message is a single line,
+//! positions and moves use the notation of [`crate::notation`].
+//!
+//! ```text
+//! > uti
+//! < id name my-engine
+//! < utiok
+//! > isready
+//! < readyok
+//! > newgame
+//! > position startpos X rules standard moves b2/b2 b2/a3
+//! > go movetime 100
+//! < info depth 5 score 12
+//! < bestmove a3/c1
+//! > quit
+//! ```
+//!
+//! # Commands
+//!
+//! - `uti` starts the session. The engine answers with `id name <name>` and `utiok`.
+//! - `isready` is answered with `readyok` once the engine has processed all previous commands.
+//! - `newgame` tells the engine that the following positions belong to a new round.
+//! - `position (startpos <X|O> | fen <position>) [rules <rules>] [moves <move>...]` sets up the
+//!   empty board with the given starting player or a position in position notation, and plays
+//!   the moves on it. The rules default to those of the position notation, i.e. `standard` unless
+//!   it has a rules field.
+//! - `go [movetime <ms>] [depth <plies>] [nodes <n>]` searches the position and is answered with
+//!   `bestmove <move>`, or `bestmove none` if there is no legal move.
+//!   Without any limit, the engine chooses its own.
+//! - `quit` ends the session. Closing the input does the same.
+//!
+//! Engines may send `info <text>` lines at any time, e.g. to report search progress or errors.
+//! Players ignore them.
+//!
+//! [`ExternalEngine`] drives an engine process as a player, [`run_engine`] lets our own engines
+//! speak the protocol. The `uttt-engine` binary runs the built-in engines that way.
+
+use std::{
+  fmt,
+  io::{self, BufRead, BufReader, Write},
+  process::{Child, ChildStdin, Stdio},
+  str::FromStr,
+  sync::mpsc::{self, Receiver, RecvTimeoutError},
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  bot::{Bot, RandomBot},
+  game::{MoveError, RoundState},
+  mcts::{Mcts, MctsLimit},
+  notation::PositionParseError,
+  rules::RuleSet,
+  search::{SearchLimits, Searcher, MAX_DEPTH},
+  GlobalPos, OuterBoardBackend, PlayerSymbol,
+};
+
+/// Time per move of the built-in engines when `go` has no limits.
+pub const DEFAULT_MOVETIME: Duration = Duration::from_millis(1000);
+/// Time an external engine may take to answer by default, in addition to the movetime of a search.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time an external engine is given to quit before it is killed.
+pub const QUIT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+/// Environment variable holding the command line of an external engine,
+/// for players that can let an engine play for them.
+pub const ENGINE_ENV_VAR: &str = "UTTT_ENGINE";
+
+const NONE: &str = "none";
+
+/// A message from the player to the engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+  Uti,
+  IsReady,
+  NewGame,
+  Position(Position),
+  Go(GoLimits),
+  Quit,
+}
+
+/// A message from the engine to the player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+  Id {
+    name: String,
+  },
+  UtiOk,
+  ReadyOk,
+  /// `None` if the position has no legal moves
+  BestMove(Option<GlobalPos>),
+  Info(String),
+}
+
+/// A position given as a start position and the moves played from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+  pub start: StartPosition,
+  pub rules: RuleSet,
+  pub moves: Vec<GlobalPos>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartPosition {
+  /// the empty board, with the starting player
+  Empty(PlayerSymbol),
+  /// a position in position notation, without its rules field but with any winners field
+  Fen(String),
+}
+
+/// The budget of a `go` command. Engines stop once any of the limits is reached.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GoLimits {
+  pub movetime: Option<Duration>,
+  pub depth: Option<usize>,
+  pub nodes: Option<u64>,
+}
+
+impl Position {
+  /// Describes the round by its starting player and move history,
+  /// or by its current position if the round was not played from the empty board.
+  pub fn from_round<Board: OuterBoardBackend>(round: &RoundState<Board>) -> Self {
+    let nmoves = round.move_history().len();
+    let noccupied = GlobalPos::all()
+      .filter(|&pos| !round.board().trivial_tile(pos).is_free())
+      .count();
+    match nmoves == noccupied {
+      true => {
+        let mut starting_player = round.current_player();
+        if nmoves % 2 == 1 {
+          starting_player.switch();
+        }
+        Self {
+          start: StartPosition::Empty(starting_player),
+          rules: round.rules(),
+          moves: round.move_history().collect(),
+        }
+      }
+      false => Self {
+        start: StartPosition::Fen(fen_without_rules(&round.to_string())),
+        rules: round.rules(),
+        moves: Vec::new(),
+      },
+    }
+  }
+
+  /// Sets up the round. A start position in position notation is validated under the rules.
+  pub fn to_round(&self) -> Result<RoundState, InvalidPositionError> {
+    let mut round = match &self.start {
+      StartPosition::Empty(starting_player) => RoundState::new(*starting_player, self.rules),
+      StartPosition::Fen(fen) => fen_with_rules(fen, self.rules)
+        .parse::<RoundState>()
+        .map_err(InvalidPositionError::Fen)?,
+    };
+    for &global_pos in &self.moves {
+      round
+        .try_play_move(round.current_player(), global_pos)
+        .map_err(|e| InvalidPositionError::IllegalMove(global_pos, e))?;
+    }
+    Ok(round)
+  }
+}
+
+/// The position notation, leaving out the rules field.
+fn fen_without_rules(fen: &str) -> String {
+  let mut fields: Vec<_> = fen.split_whitespace().collect();
+  if fields.len() > 3 {
+    fields.remove(3);
+  }
+  fields.join(" ")
+}
+
+/// The position notation without its rules field, with the rules field inserted.
+fn fen_with_rules(fen: &str, rules: RuleSet) -> String {
+  let rules = rules.to_string();
+  let mut fields: Vec<_> = fen.split_whitespace().collect();
+  fields.insert(fields.len().min(3), &rules);
+  fields.join(" ")
+}
+
+impl GoLimits {
+  pub fn is_unlimited(&self) -> bool {
+    *self == Self::default()
+  }
+
+  /// The limits of an alpha-beta search, using [`DEFAULT_MOVETIME`] if there are none.
+  pub fn search_limits(&self) -> SearchLimits {
+    match self.is_unlimited() {
+      true => SearchLimits::time(DEFAULT_MOVETIME),
+      false => SearchLimits {
+        depth: self.depth.unwrap_or(MAX_DEPTH),
+        time: self.movetime,
+        nodes: self.nodes,
+      },
+    }
+  }
+
+  /// The limit of a tree search, counting iterations as nodes and ignoring the depth.
+  /// Uses [`DEFAULT_MOVETIME`] if there is no limit left.
+  pub fn mcts_limit(&self) -> MctsLimit {
+    match (self.movetime, self.nodes) {
+      (Some(movetime), _) => MctsLimit::Time(movetime),
+      (None, Some(nodes)) => MctsLimit::Iterations(nodes),
+      (None, None) => MctsLimit::Time(DEFAULT_MOVETIME),
+    }
+  }
+}
+
+impl fmt::Display for Command {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Uti => write!(f, "uti"),
+      Self::IsReady => write!(f, "isready"),
+      Self::NewGame => write!(f, "newgame"),
+      Self::Position(position) => write!(f, "position {}", position),
+      Self::Go(limits) => {
+        write!(f, "go")?;
+        if let Some(movetime) = limits.movetime {
+          write!(f, " movetime {}", movetime.as_millis())?;
+        }
+        if let Some(depth) = limits.depth {
+          write!(f, " depth {}", depth)?;
+        }
+        if let Some(nodes) = limits.nodes {
+          write!(f, " nodes {}", nodes)?;
+        }
+        Ok(())
+      }
+      Self::Quit => write!(f, "quit"),
+    }
+  }
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.start {
+      StartPosition::Empty(starting_player) => write!(f, "startpos {}", starting_player.as_char())?,
+      StartPosition::Fen(fen) => write!(f, "fen {}", fen_with_rules(fen, self.rules))?,
+    }
+    write!(f, " rules {}", self.rules)?;
+    if !self.moves.is_empty() {
+      write!(f, " moves")?;
+      for global_pos in &self.moves {
+        write!(f, " {}", global_pos)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for Response {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Id { name } => write!(f, "id name {}", name),
+      Self::UtiOk => write!(f, "utiok"),
+      Self::ReadyOk => write!(f, "readyok"),
+      Self::BestMove(Some(global_pos)) => write!(f, "bestmove {}", global_pos),
+      Self::BestMove(None) => write!(f, "bestmove {}", NONE),
+      Self::Info(text) => write!(f, "info {}", text),
+    }
+  }
+}
+
+/// Splits off the first word of the line.
+fn split_keyword(line: &str) -> (&str, &str) {
+  let line = line.trim();
+  match line.split_once(char::is_whitespace) {
+    Some((keyword, rest)) => (keyword, rest.trim_start()),
+    None => (line, ""),
+  }
+}
+
+fn parse_arg<T: FromStr>(name: &'static str, value: Option<&str>) -> Result<T, ProtocolParseError> {
+  let value = value.ok_or(ProtocolParseError::MissingArgument(name))?;
+  value
+    .parse()
+    .map_err(|_| ProtocolParseError::InvalidArgument {
+      name,
+      value: value.to_string(),
+    })
+}
+
+impl FromStr for Command {
+  type Err = ProtocolParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (keyword, rest) = split_keyword(s);
+    let mut args = rest.split_whitespace();
+    let command = match keyword {
+      "uti" => Self::Uti,
+      "isready" => Self::IsReady,
+      "newgame" => Self::NewGame,
+      "position" => return rest.parse().map(Self::Position),
+      "go" => {
+        let mut limits = GoLimits::default();
+        while let Some(name) = args.next() {
+          match name {
+            "movetime" => {
+              limits.movetime = Some(Duration::from_millis(parse_arg("movetime", args.next())?))
+            }
+            "depth" => limits.depth = Some(parse_arg("depth", args.next())?),
+            "nodes" => limits.nodes = Some(parse_arg("nodes", args.next())?),
+            _ => return Err(ProtocolParseError::UnexpectedArgument(name.to_string())),
+          }
+        }
+        Self::Go(limits)
+      }
+      "quit" => Self::Quit,
+      "" => return Err(ProtocolParseError::Empty),
+      _ => return Err(ProtocolParseError::UnknownKeyword(keyword.to_string())),
+    };
+    match args.next() {
+      Some(arg) => Err(ProtocolParseError::UnexpectedArgument(arg.to_string())),
+      None => Ok(command),
+    }
+  }
+}
+
+impl FromStr for Position {
+  type Err = ProtocolParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut args = s.split_whitespace().peekable();
+    let start = match args.next() {
+      Some("startpos") => {
+        let player = args
+          .next()
+          .ok_or(ProtocolParseError::MissingArgument("startpos"))?;
+        let mut chars = player.chars();
+        match (chars.next().and_then(PlayerSymbol::from_char), chars.next()) {
+          (Some(player), None) => StartPosition::Empty(player),
+          _ => {
+            return Err(ProtocolParseError::InvalidArgument {
+              name: "startpos",
+              value: player.to_string(),
+            })
+          }
+        }
+      }
+      Some("fen") => {
+        let mut fields: Vec<_> = args.by_ref().take(3).collect();
+        // the optional rules and winners fields of the notation
+        for _ in 0..2 {
+          fields.extend(args.next_if(|&arg| arg != "rules" && arg != "moves"));
+        }
+        StartPosition::Fen(fields.join(" "))
+      }
+      Some(arg) => return Err(ProtocolParseError::UnexpectedArgument(arg.to_string())),
+      None => return Err(ProtocolParseError::MissingArgument("startpos or fen")),
+    };
+
+    let rules = match args.next_if_eq(&"rules") {
+      Some(_) => Some(parse_arg("rules", args.next())?),
+      None => None,
+    };
+    // the position is only valid under the rules it is played by
+    let (start, rules) = match start {
+      StartPosition::Fen(fen) => {
+        let fen = match rules {
+          Some(rules) => fen_with_rules(&fen_without_rules(&fen), rules),
+          None => fen,
+        };
+        let round: RoundState = fen
+          .parse()
+          .map_err(|_| ProtocolParseError::InvalidArgument {
+            name: "fen",
+            value: fen.clone(),
+          })?;
+        (
+          StartPosition::Fen(fen_without_rules(&round.to_string())),
+          round.rules(),
+        )
+      }
+      start => (start, rules.unwrap_or_default()),
+    };
+    let mut moves = Vec::new();
+    if args.next_if_eq(&"moves").is_some() {
+      for global_pos in args.by_ref() {
+        moves.push(parse_arg("moves", Some(global_pos))?);
+      }
+    }
+    match args.next() {
+      Some(arg) => Err(ProtocolParseError::UnexpectedArgument(arg.to_string())),
+      None => Ok(Self {
+        start,
+        rules,
+        moves,
+      }),
+    }
+  }
+}
+
+impl FromStr for Response {
+  type Err = ProtocolParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (keyword, rest) = split_keyword(s);
+    let response = match keyword {
+      "id" => match split_keyword(rest) {
+        ("name", name) if !name.is_empty() => {
+          return Ok(Self::Id {
+            name: name.to_string(),
+          })
+        }
+        _ => return Err(ProtocolParseError::MissingArgument("name")),
+      },
+      "utiok" => Self::UtiOk,
+      "readyok" => Self::ReadyOk,
+      "bestmove" => {
+        let mut args = rest.split_whitespace();
+        let best_move = match args.next() {
+          Some(NONE) => None,
+          global_pos => Some(parse_arg("bestmove", global_pos)?),
+        };
+        return match args.next() {
+          Some(arg) => Err(ProtocolParseError::UnexpectedArgument(arg.to_string())),
+          None => Ok(Self::BestMove(best_move)),
+        };
+      }
+      "info" => return Ok(Self::Info(rest.to_string())),
+      "" => return Err(ProtocolParseError::Empty),
+      _ => return Err(ProtocolParseError::UnknownKeyword(keyword.to_string())),
+    };
+    match rest.is_empty() {
+      true => Ok(response),
+      false => Err(ProtocolParseError::UnexpectedArgument(rest.to_string())),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolParseError {
+  Empty,
+  UnknownKeyword(String),
+  MissingArgument(&'static str),
+  InvalidArgument { name: &'static str, value: String },
+  UnexpectedArgument(String),
+}
+
+impl fmt::Display for ProtocolParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Empty => write!(f, "empty message"),
+      Self::UnknownKeyword(keyword) => write!(f, "unknown message `{}`", keyword),
+      Self::MissingArgument(name) => write!(f, "missing argument: {}", name),
+      Self::InvalidArgument { name, value } => {
+        write!(f, "invalid argument `{}` of {}", value, name)
+      }
+      Self::UnexpectedArgument(arg) => write!(f, "unexpected argument `{}`", arg),
+    }
+  }
+}
+impl std::error::Error for ProtocolParseError {}
+
+/// A position that does not describe a round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidPositionError {
+  Fen(PositionParseError),
+  IllegalMove(GlobalPos, MoveError),
+}
+
+impl fmt::Display for InvalidPositionError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Fen(e) => write!(f, "invalid position: {}", e),
+      Self::IllegalMove(global_pos, e) => write!(f, "move {} is illegal: {:?}", global_pos, e),
+    }
+  }
+}
+impl std::error::Error for InvalidPositionError {}
+
+// engine side
+
+/// An engine that can be run by [`run_engine`].
+pub trait Engine {
+  /// Forgets everything learned about the previous round.
+  fn new_game(&mut self) {}
+
+  /// Chooses a move for the current player of the round within the limits.
+  ///
+  /// Panics if the round has no legal moves.
+  fn go(&mut self, round: &RoundState, limits: GoLimits) -> GlobalPos;
+}
+
+/// Forgets the transposition table between rounds.
+impl Engine for Searcher {
+  fn new_game(&mut self) {
+    self.clear_transposition_table();
+  }
+
+  fn go(&mut self, round: &RoundState, limits: GoLimits) -> GlobalPos {
+    self
+      .search(round, limits.search_limits())
+      .expect("no legal moves left")
+      .best_move
+  }
+}
+
+/// Keeps its tree while the rounds continue each other.
+impl Engine for Mcts {
+  fn go(&mut self, round: &RoundState, limits: GoLimits) -> GlobalPos {
+    self.set_limit(limits.mcts_limit());
+    self.choose_move(round)
+  }
+}
+
+impl Engine for RandomBot {
+  fn go(&mut self, round: &RoundState, _limits: GoLimits) -> GlobalPos {
+    self.choose_move(round)
+  }
+}
+
+/// Answers the commands read from the input until it is closed or `quit` is received.
+///
+/// Invalid commands and positions are reported as `info` and otherwise ignored.
+/// Searching before any position has been set searches the empty board with `X` to move.
+pub fn run_engine(
+  name: &str,
+  engine: &mut impl Engine,
+  input: impl BufRead,
+  mut output: impl Write,
+) -> io::Result<()> {
+  let mut send = |response: Response| {
+    writeln!(output, "{}", response)?;
+    output.flush()
+  };
+
+  let mut round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+  for line in input.lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    match line.parse() {
+      Ok(Command::Uti) => {
+        send(Response::Id {
+          name: name.to_string(),
+        })?;
+        send(Response::UtiOk)?;
+      }
+      Ok(Command::IsReady) => send(Response::ReadyOk)?,
+      Ok(Command::NewGame) => engine.new_game(),
+      Ok(Command::Position(position)) => match position.to_round() {
+        Ok(new_round) => round = new_round,
+        Err(e) => send(Response::Info(format!("error: {}", e)))?,
+      },
+      Ok(Command::Go(limits)) => {
+        let best_move = round
+          .legal_moves()
+          .next()
+          .map(|_| engine.go(&round, limits));
+        send(Response::BestMove(best_move))?;
+      }
+      Ok(Command::Quit) => break,
+      Err(e) => send(Response::Info(format!("error: {}", e)))?,
+    }
+  }
+  Ok(())
+}
+
+// player side
+
+/// An engine process speaking the protocol, playing as a [`Bot`].
+///
+/// Answers are awaited for at most the [timeout](Self::with_timeout). An engine that misses it
+/// is sent `isready` and its late answers are skipped up to `readyok`, so the session stays in
+/// step. If it misses that too, it counts as unresponsive and is not asked anything anymore.
+/// The process is asked to quit when this is dropped and killed if it does not within the
+/// [`QUIT_GRACE_PERIOD`].
+#[derive(Debug)]
+pub struct ExternalEngine {
+  process: Child,
+  stdin: ChildStdin,
+  /// lines of the standard output, read on their own thread
+  lines: Receiver<io::Result<String>>,
+  name: String,
+  limits: GoLimits,
+  timeout: Duration,
+  /// set once the engine failed to catch up after a timeout
+  unresponsive: bool,
+}
+
+impl ExternalEngine {
+  /// Starts the engine process and waits until it is ready.
+  /// Its moves are searched within the given limits.
+  pub fn spawn(mut command: std::process::Command, limits: GoLimits) -> Result<Self, EngineError> {
+    let mut process = command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .spawn()
+      .map_err(EngineError::Io)?;
+    let stdin = process.stdin.take().expect("stdin is piped");
+    let stdout = BufReader::new(process.stdout.take().expect("stdout is piped"));
+    let (line_sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+      for line in stdout.lines() {
+        if line_sender.send(line).is_err() {
+          break;
+        }
+      }
+    });
+    let mut engine = Self {
+      process,
+      stdin,
+      lines,
+      name: String::new(),
+      limits,
+      timeout: DEFAULT_RESPONSE_TIMEOUT,
+      unresponsive: false,
+    };
+
+    engine.send(&Command::Uti)?;
+    loop {
+      match engine.receive(engine.timeout)? {
+        Response::Id { name } => engine.name = name,
+        Response::UtiOk => break,
+        response => return Err(EngineError::UnexpectedResponse(response)),
+      }
+    }
+    engine.wait_until_ready()?;
+    Ok(engine)
+  }
+
+  /// Starts the engine given by a command line, the program followed by its arguments,
+  /// separated by whitespace.
+  pub fn spawn_command_line(command_line: &str, limits: GoLimits) -> Result<Self, EngineError> {
+    let mut args = command_line.split_whitespace();
+    let mut command = std::process::Command::new(args.next().unwrap_or_default());
+    command.args(args);
+    Self::spawn(command, limits)
+  }
+
+  /// Starts the engine whose command line is given by the [`ENGINE_ENV_VAR`] environment
+  /// variable, if it is set.
+  pub fn from_env(limits: GoLimits) -> Option<Result<Self, EngineError>> {
+    let command_line = std::env::var(ENGINE_ENV_VAR).ok()?;
+    Some(Self::spawn_command_line(&command_line, limits))
+  }
+
+  /// Sets the time the engine may take to answer, in addition to the movetime of a search.
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  /// The name the engine introduced itself with.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Tells the engine that a new round starts.
+  pub fn new_game(&mut self) -> Result<(), EngineError> {
+    self.send(&Command::NewGame)?;
+    self.wait_until_ready()
+  }
+
+  /// Asks the engine for its move in the round.
+  pub fn best_move(&mut self, round: &RoundState) -> Result<GlobalPos, EngineError> {
+    self.send(&Command::Position(Position::from_round(round)))?;
+    self.send(&Command::Go(self.limits))?;
+    let timeout = self.limits.movetime.unwrap_or_default() + self.timeout;
+    match self.receive_in_step(timeout)? {
+      Response::BestMove(Some(global_pos)) => {
+        match round.could_play_move(round.current_player(), global_pos) {
+          true => Ok(global_pos),
+          false => Err(EngineError::IllegalMove(global_pos)),
+        }
+      }
+      Response::BestMove(None) => Err(EngineError::NoMove),
+      response => Err(EngineError::UnexpectedResponse(response)),
+    }
+  }
+
+  fn wait_until_ready(&mut self) -> Result<(), EngineError> {
+    self.send(&Command::IsReady)?;
+    match self.receive_in_step(self.timeout)? {
+      Response::ReadyOk => Ok(()),
+      response => Err(EngineError::UnexpectedResponse(response)),
+    }
+  }
+
+  /// Like [`Self::receive`], but catches up with the engine after a timeout, as the late answer
+  /// would otherwise be taken as the answer to the next command.
+  fn receive_in_step(&mut self, timeout: Duration) -> Result<Response, EngineError> {
+    let result = self.receive(timeout);
+    if let Err(EngineError::Timeout) = result {
+      self.unresponsive = self.skip_until_ready(timeout).is_err();
+    }
+    result
+  }
+
+  /// Sends `isready` and skips all responses up to `readyok`.
+  fn skip_until_ready(&mut self, timeout: Duration) -> Result<(), EngineError> {
+    self.send(&Command::IsReady)?;
+    let deadline = Instant::now() + timeout;
+    loop {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if let Response::ReadyOk = self.receive(remaining)? {
+        return Ok(());
+      }
+    }
+  }
+
+  fn send(&mut self, command: &Command) -> Result<(), EngineError> {
+    if self.unresponsive {
+      return Err(EngineError::Unresponsive);
+    }
+    writeln!(self.stdin, "{}", command)
+      .and_then(|()| self.stdin.flush())
+      .map_err(EngineError::Io)
+  }
+
+  /// Reads the next response within the timeout, skipping `info` lines.
+  fn receive(&mut self, timeout: Duration) -> Result<Response, EngineError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      let line = match self.lines.recv_timeout(remaining) {
+        Ok(line) => line.map_err(EngineError::Io)?,
+        Err(RecvTimeoutError::Timeout) => return Err(EngineError::Timeout),
+        Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Closed),
+      };
+      if line.trim().is_empty() {
+        continue;
+      }
+      match line.parse().map_err(EngineError::Parse)? {
+        Response::Info(_) => (),
+        response => return Ok(response),
+      }
+    }
+  }
+}
+
+impl Bot for ExternalEngine {
+  /// Panics if the engine fails to answer with a legal move.
+  fn choose_move(&mut self, round: &RoundState) -> GlobalPos {
+    self
+      .best_move(round)
+      .unwrap_or_else(|e| panic!("engine {} failed: {}", self.name, e))
+  }
+}
+
+impl Drop for ExternalEngine {
+  fn drop(&mut self) {
+    // the engine may already be gone
+    let _ = self.send(&Command::Quit);
+    let deadline = Instant::now() + QUIT_GRACE_PERIOD;
+    while let Ok(None) = self.process.try_wait() {
+      if Instant::now() >= deadline {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        return;
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum EngineError {
+  Io(io::Error),
+  Parse(ProtocolParseError),
+  UnexpectedResponse(Response),
+  IllegalMove(GlobalPos),
+  NoMove,
+  Closed,
+  Timeout,
+  /// the engine did not catch up after a timeout and is not asked anymore
+  Unresponsive,
+}
+
+impl fmt::Display for EngineError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(e) => write!(f, "communicating with engine failed: {}", e),
+      Self::Parse(e) => write!(f, "invalid engine response: {}", e),
+      Self::UnexpectedResponse(response) => write!(f, "unexpected engine response `{}`", response),
+      Self::IllegalMove(global_pos) => write!(f, "engine chose illegal move {}", global_pos),
+      Self::NoMove => write!(f, "engine found no move"),
+      Self::Closed => write!(f, "engine closed its output"),
+      Self::Timeout => write!(f, "engine did not answer in time"),
+      Self::Unresponsive => write!(f, "engine stopped responding"),
+    }
+  }
+}
+impl std::error::Error for EngineError {}
+
+#[cfg(test)]
+mod test {
+  use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+  };
+
+  use super::{
+    run_engine, Command, Engine, EngineError, ExternalEngine, GoLimits, Position, Response,
+    StartPosition, QUIT_GRACE_PERIOD,
+  };
+  use crate::{
+    bot::RandomBot, game::RoundState, rules::RuleSet, search::Searcher, tt::TranspositionTable,
+    GlobalPos, PlayerSymbol,
+  };
+
+  #[test]
+  fn check_messages_roundtrip() {
+    let mut round = RoundState::new(PlayerSymbol::O, "won-playable".parse().unwrap());
+    for global_pos in [GlobalPos::new(4, 4), GlobalPos::new(3, 5)] {
+      round
+        .try_play_move(round.current_player(), global_pos)
+        .unwrap();
+    }
+    let fen: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X -".parse().unwrap();
+    // O is sent into the won top left board, which only the rules allow
+    let forced: RoundState = "XXX3OO1/9/9/9/9/9/9/9/9 O a3 won-playable".parse().unwrap();
+    let commands = [
+      Command::Uti,
+      Command::IsReady,
+      Command::NewGame,
+      Command::Position(Position::from_round(&round)),
+      Command::Position(Position::from_round(&fen)),
+      Command::Position(Position::from_round(&forced)),
+      Command::Go(GoLimits::default()),
+      Command::Go(GoLimits {
+        movetime: Some(Duration::from_millis(100)),
+        depth: Some(4),
+        nodes: Some(10_000),
+      }),
+      Command::Quit,
+    ];
+    for command in commands {
+      assert_eq!(command.to_string().parse::<Command>(), Ok(command));
+    }
+    let responses = [
+      Response::Id {
+        name: "an engine".to_string(),
+      },
+      Response::UtiOk,
+      Response::ReadyOk,
+      Response::BestMove(Some(GlobalPos::new(8, 0))),
+      Response::BestMove(None),
+      Response::Info("depth 3 score -5".to_string()),
+    ];
+    for response in responses {
+      assert_eq!(response.to_string().parse::<Response>(), Ok(response));
+    }
+
+    let position: Position = "startpos O rules won-playable moves b2/b2 b2/a1"
+      .parse()
+      .unwrap();
+    assert_eq!(position.start, StartPosition::Empty(PlayerSymbol::O));
+    let replayed = position.to_round().unwrap();
+    assert_eq!(replayed.zobrist_hash(), round.zobrist_hash());
+    assert_eq!(replayed.rules(), round.rules());
+    assert!(Position::from_round(&fen).to_round().is_ok());
+    let replayed = Position::from_round(&forced).to_round().unwrap();
+    assert_eq!(replayed.zobrist_hash(), forced.zobrist_hash());
+    assert_eq!(replayed.rules(), forced.rules());
+    let with_rules_field: Position = "fen XXX3OO1/9/9/9/9/9/9/9/9 O a3 won-playable"
+      .parse()
+      .unwrap();
+    assert_eq!(with_rules_field, Position::from_round(&forced));
+    let with_rules_argument: Position = "fen XXX3OO1/9/9/9/9/9/9/9/9 O a3 rules won-playable"
+      .parse()
+      .unwrap();
+    assert_eq!(with_rules_argument, Position::from_round(&forced));
+
+    // the winner of a sub-board with lines of both players is kept
+    let disputed: RoundState = "OOO3XX1/XXX6/9/O8/9/9/9/9/9 O c2 won-playable a3:O"
+      .parse()
+      .unwrap();
+    let position = Position::from_round(&disputed);
+    assert_eq!(position.to_string().parse::<Position>().unwrap(), position);
+    let replayed = position.to_round().unwrap();
+    assert_eq!(replayed.to_string(), disputed.to_string());
+    assert_eq!(replayed.zobrist_hash(), disputed.zobrist_hash());
+
+    for invalid in [
+      "",
+      "go movetime",
+      "go depth -1",
+      "isready now",
+      "position startpos",
+      "position fen 9/9 X -",
+      "position fen XXX3OO1/9/9/9/9/9/9/9/9 O a3",
+      "position fen XXX3OO1/9/9/9/9/9/9/9/9 O a3 won-playable rules standard",
+      "position startpos X moves d4/a1",
+    ] {
+      assert!(invalid.parse::<Command>().is_err(), "{}", invalid);
+    }
+    let illegal: Position = "startpos X moves a1/a1 a1/a1".parse().unwrap();
+    assert!(illegal.to_round().is_err());
+  }
+
+  #[test]
+  fn check_engine_session() {
+    let input = "uti\n\
+                 isready\n\
+                 newgame\n\
+                 position fen XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X -\n\
+                 go depth 2\n\
+                 position startpos X moves a1/a1 a1/a1\n\
+                 position startpos X rules standard moves b2/b2\n\
+                 go nodes 100\n\
+                 quit\n\
+                 go\n";
+    let mut output = Vec::new();
+    run_engine(
+      "searcher",
+      &mut Searcher::default(),
+      input.as_bytes(),
+      &mut output,
+    )
+    .unwrap();
+    let responses: Vec<Response> = String::from_utf8(output)
+      .unwrap()
+      .lines()
+      .map(|line| line.parse().unwrap())
+      .collect();
+    assert_eq!(responses.len(), 6);
+    assert_eq!(
+      responses[..4],
+      [
+        Response::Id {
+          name: "searcher".to_string()
+        },
+        Response::UtiOk,
+        Response::ReadyOk,
+        Response::BestMove(Some(GlobalPos::new(8, 1))),
+      ]
+    );
+    assert!(matches!(responses[4], Response::Info(_)));
+    let Response::BestMove(Some(global_pos)) = responses[5] else {
+      panic!("expected a best move, got {:?}", responses[5]);
+    };
+    let round = "startpos X moves b2/b2"
+      .parse::<Position>()
+      .unwrap()
+      .to_round()
+      .unwrap();
+    assert!(round.could_play_move(PlayerSymbol::O, global_pos));
+
+    let finished = "position fen XXXXXXXXX/9/9/OO1OO1OO1/O2O2O2/9/9/9/9 O -\ngo\n";
+    let mut output = Vec::new();
+    run_engine(
+      "random",
+      &mut RandomBot::new(0),
+      finished.as_bytes(),
+      &mut output,
+    )
+    .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "bestmove none\n");
+  }
+
+  #[test]
+  fn check_new_game_clears_the_table() {
+    let tt = Arc::new(TranspositionTable::new(1));
+    let mut searcher = Searcher::default().with_transposition_table(tt.clone());
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    let limits = GoLimits {
+      depth: Some(2),
+      ..GoLimits::default()
+    };
+    searcher.go(&round, limits);
+    assert!(tt.probe(round.zobrist_hash()).is_some());
+    searcher.new_game();
+    assert_eq!(tt.probe(round.zobrist_hash()), None);
+  }
+
+  /// An engine that introduces itself and then hangs, ignoring `quit`.
+  #[cfg(unix)]
+  fn hanging_engine() -> std::process::Command {
+    let mut command = std::process::Command::new("sh");
+    command.args([
+      "-c",
+      "read l; echo 'id name hanging'; echo utiok; read l; echo readyok; exec sleep 30",
+    ]);
+    command
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn check_external_engine_timeouts() {
+    let limits = GoLimits {
+      movetime: Some(Duration::from_millis(50)),
+      ..GoLimits::default()
+    };
+    let start = Instant::now();
+    let mut engine = ExternalEngine::spawn(hanging_engine(), limits)
+      .unwrap()
+      .with_timeout(Duration::from_millis(100));
+    assert_eq!(engine.name(), "hanging");
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    assert!(matches!(
+      engine.best_move(&round),
+      Err(EngineError::Timeout)
+    ));
+    assert!(matches!(
+      engine.best_move(&round),
+      Err(EngineError::Unresponsive)
+    ));
+    drop(engine);
+    assert!(start.elapsed() < QUIT_GRACE_PERIOD + Duration::from_secs(2));
+  }
+
+  /// An engine that answers its first `go` late, with a different move than later ones.
+  #[cfg(unix)]
+  fn slow_engine() -> std::process::Command {
+    let mut command = std::process::Command::new("sh");
+    command.args([
+      "-c",
+      "move=a1/a1; while read cmd rest; do case $cmd in \
+         uti) echo 'id name slow'; echo utiok;; \
+         isready) echo readyok;; \
+         go) [ $move = a1/a1 ] && sleep 0.3; echo bestmove $move; move=b2/b2;; \
+         quit) exit;; \
+       esac; done",
+    ]);
+    command
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn check_external_engine_catches_up_after_timeouts() {
+    let mut engine = ExternalEngine::spawn(slow_engine(), GoLimits::default())
+      .unwrap()
+      .with_timeout(Duration::from_millis(200));
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    assert!(matches!(
+      engine.best_move(&round),
+      Err(EngineError::Timeout)
+    ));
+    // the late answer to the first `go` is skipped
+    assert_eq!(engine.best_move(&round).unwrap(), GlobalPos::new(4, 4));
+    engine.new_game().unwrap();
+  }
+}
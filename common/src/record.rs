@@ -0,0 +1,482 @@
+//! Game records, a PGN-like text format for whole rounds.
+//!
+//! A record starts with header tags of the form `[Name "value"]`, one per line,
+//! followed by the movetext. The movetext lists the [`PlayerAction`]s in move notation
+//! (see [`crate::notation`]), grouped into numbered move pairs. A comment in braces
+//! attaches to the action right before it and is kept verbatim, including its whitespace.
+//! Within comments, `}` and `\` are escaped by a preceding `\`.
+//!
+//! ```text
+//! [PlayerX "alice"]
+//! [PlayerO "bob"]
+//! [Date "2024.01.31"]
+//! [StartingPlayer "X"]
+//! [Variant "standard"]
+//! [Result "X"]
+//!
+//! 1. b2/b2 b2/a3 {forcing the corner}
+//! 2. a3/c1 resign
+//! ```
+//!
+//! `StartingPlayer` is the only required tag. `Variant` names the [`RuleSet`] and defaults
+//! to `standard`. `Result` is `X`, `O`, `draw` or `*` for an unfinished round.
+//! Unknown tags are preserved.
+
+use std::{fmt, io::Write, path::Path, str::FromStr};
+
+use crate::{
+  game::{MoveError, PlayerAction, RoundOutcome, RoundState},
+  rules::RuleSet,
+  PlayerSymbol,
+};
+
+const TAG_PLAYER_X: &str = "PlayerX";
+const TAG_PLAYER_O: &str = "PlayerO";
+const TAG_DATE: &str = "Date";
+const TAG_STARTING_PLAYER: &str = "StartingPlayer";
+const TAG_VARIANT: &str = "Variant";
+const TAG_RESULT: &str = "Result";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+  /// player names, indexed by `PlayerSymbol`
+  pub players: [Option<String>; 2],
+  pub date: Option<String>,
+  pub starting_player: PlayerSymbol,
+  pub rules: RuleSet,
+  /// `None` if the round is unfinished
+  pub result: Option<RoundOutcome>,
+  /// unknown tags, in order of appearance
+  pub other_tags: Vec<(String, String)>,
+
+  pub actions: Vec<RecordedAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedAction {
+  pub action: PlayerAction,
+  pub comment: Option<String>,
+}
+
+impl GameRecord {
+  pub fn new(starting_player: PlayerSymbol) -> Self {
+    Self {
+      players: [None, None],
+      date: None,
+      starting_player,
+      rules: RuleSet::default(),
+      result: None,
+      other_tags: Vec::new(),
+      actions: Vec::new(),
+    }
+  }
+
+  pub fn push_action(&mut self, action: PlayerAction) {
+    self.actions.push(RecordedAction {
+      action,
+      comment: None,
+    });
+  }
+
+  /// Replays the recorded actions from the start.
+  /// Returns the final round state, or the first action that could not be played.
+  pub fn replay(&self) -> Result<RoundState, ReplayError> {
+    let mut round = RoundState::new(self.starting_player, self.rules);
+    let mut given_up = false;
+    for (idx, recorded) in self.actions.iter().enumerate() {
+      let error = |kind| ReplayError {
+        idx,
+        action: recorded.action,
+        kind,
+      };
+      if given_up {
+        return Err(error(ReplayErrorKind::AfterGiveUp));
+      }
+      match recorded.action {
+        PlayerAction::MakeMove(pos) => round
+          .try_play_move(round.current_player(), pos)
+          .map_err(|e| error(ReplayErrorKind::IllegalMove(e)))?,
+        PlayerAction::GiveUp => given_up = true,
+      }
+    }
+    Ok(round)
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadRecordError> {
+    let content = std::fs::read_to_string(path).map_err(LoadRecordError::Io)?;
+    content.parse().map_err(LoadRecordError::Parse)
+  }
+  pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, self.to_string())
+  }
+  /// Like [`Self::save`], but fails with [`std::io::ErrorKind::AlreadyExists`] instead of
+  /// overwriting an existing file.
+  pub fn save_new(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+    let mut file = std::fs::File::options()
+      .write(true)
+      .create_new(true)
+      .open(path)?;
+    file.write_all(self.to_string().as_bytes())
+  }
+}
+
+impl fmt::Display for GameRecord {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let write_tag = |f: &mut fmt::Formatter<'_>, name: &str, value: &str| {
+      let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+      writeln!(f, "[{} \"{}\"]", name, value)
+    };
+
+    for (name, player) in [TAG_PLAYER_X, TAG_PLAYER_O].into_iter().zip(&self.players) {
+      if let Some(player) = player {
+        write_tag(f, name, player)?;
+      }
+    }
+    if let Some(date) = &self.date {
+      write_tag(f, TAG_DATE, date)?;
+    }
+    write_tag(
+      f,
+      TAG_STARTING_PLAYER,
+      &self.starting_player.as_char().to_string(),
+    )?;
+    write_tag(f, TAG_VARIANT, &self.rules.to_string())?;
+    write_tag(f, TAG_RESULT, &fmt_result(self.result))?;
+    for (name, value) in &self.other_tags {
+      write_tag(f, name, value)?;
+    }
+
+    for (i, pair) in self.actions.chunks(2).enumerate() {
+      write!(f, "\n{}.", i + 1)?;
+      for recorded in pair {
+        write!(f, " {}", recorded.action)?;
+        if let Some(comment) = &recorded.comment {
+          let comment = comment.replace('\\', "\\\\").replace('}', "\\}");
+          write!(f, " {{{}}}", comment)?;
+        }
+      }
+    }
+    writeln!(f)
+  }
+}
+
+impl FromStr for GameRecord {
+  type Err = RecordParseError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut starting_player = None;
+    let mut record = GameRecord::new(PlayerSymbol::X);
+
+    let mut lines = s.lines().enumerate().peekable();
+    while let Some((line_idx, line)) = lines.next_if(|(_, line)| {
+      let line = line.trim();
+      line.is_empty() || line.starts_with('[')
+    }) {
+      let line = line.trim();
+      if line.is_empty() {
+        continue;
+      }
+      let (name, value) = parse_tag(line).ok_or(RecordParseError::InvalidTag(line_idx + 1))?;
+      match name {
+        TAG_PLAYER_X => record.players[PlayerSymbol::X.idx()] = Some(value),
+        TAG_PLAYER_O => record.players[PlayerSymbol::O.idx()] = Some(value),
+        TAG_DATE => record.date = Some(value),
+        TAG_STARTING_PLAYER => {
+          let mut chars = value.chars();
+          starting_player = match (chars.next().and_then(PlayerSymbol::from_char), chars.next()) {
+            (Some(p), None) => Some(p),
+            _ => return Err(RecordParseError::InvalidStartingPlayer(value)),
+          };
+        }
+        TAG_VARIANT => {
+          record.rules = value
+            .parse()
+            .map_err(|_| RecordParseError::InvalidVariant(value))?;
+        }
+        TAG_RESULT => {
+          record.result = parse_result(&value).ok_or(RecordParseError::InvalidResult(value))?;
+        }
+        _ => record.other_tags.push((name.to_string(), value)),
+      }
+    }
+    record.starting_player = starting_player.ok_or(RecordParseError::MissingStartingPlayer)?;
+
+    let movetext: String = lines.map(|(_, line)| format!("{}\n", line)).collect();
+    let mut rest = movetext.as_str();
+    loop {
+      rest = rest.trim_start();
+      if rest.is_empty() {
+        break;
+      }
+      if let Some(comment_start) = rest.strip_prefix('{') {
+        let (comment, after) =
+          parse_comment(comment_start).ok_or(RecordParseError::UnterminatedComment)?;
+        let last = record
+          .actions
+          .last_mut()
+          .ok_or(RecordParseError::CommentWithoutAction)?;
+        last.comment = Some(comment);
+        rest = after;
+        continue;
+      }
+
+      let token_end = rest
+        .find(|c: char| c.is_whitespace() || c == '{')
+        .unwrap_or(rest.len());
+      let (token, after) = rest.split_at(token_end);
+      rest = after;
+
+      let is_move_number =
+        token.ends_with('.') && token[..token.len() - 1].chars().all(|c| c.is_ascii_digit());
+      if is_move_number {
+        continue;
+      }
+      let action = token
+        .parse()
+        .map_err(|_| RecordParseError::InvalidAction(token.to_string()))?;
+      record.push_action(action);
+    }
+
+    Ok(record)
+  }
+}
+
+fn parse_tag(line: &str) -> Option<(&str, String)> {
+  let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+  let (name, value) = inner.split_once(char::is_whitespace)?;
+  let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+  let mut unescaped = String::new();
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => unescaped.push(chars.next()?),
+      '"' => return None,
+      _ => unescaped.push(c),
+    }
+  }
+  Some((name, unescaped))
+}
+
+/// Parses an escaped comment up to its closing brace.
+/// Returns the unescaped comment and the rest after the brace.
+fn parse_comment(s: &str) -> Option<(String, &str)> {
+  let mut unescaped = String::new();
+  let mut chars = s.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '\\' => unescaped.push(chars.next()?.1),
+      '}' => return Some((unescaped, &s[i + 1..])),
+      _ => unescaped.push(c),
+    }
+  }
+  None
+}
+
+fn fmt_result(result: Option<RoundOutcome>) -> String {
+  match result {
+    Some(RoundOutcome::Win(p)) => p.as_char().to_string(),
+    Some(RoundOutcome::Draw) => "draw".to_string(),
+    None => "*".to_string(),
+  }
+}
+fn parse_result(s: &str) -> Option<Option<RoundOutcome>> {
+  match s {
+    "*" => Some(None),
+    "draw" => Some(Some(RoundOutcome::Draw)),
+    _ => {
+      let mut chars = s.chars();
+      match (chars.next().and_then(PlayerSymbol::from_char), chars.next()) {
+        (Some(p), None) => Some(Some(RoundOutcome::Win(p))),
+        _ => None,
+      }
+    }
+  }
+}
+
+/// The first recorded action that could not be replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayError {
+  /// index into [`GameRecord::actions`]
+  pub idx: usize,
+  pub action: PlayerAction,
+  pub kind: ReplayErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayErrorKind {
+  IllegalMove(MoveError),
+  AfterGiveUp,
+}
+
+impl fmt::Display for ReplayError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "action {} ({}) ", self.idx + 1, self.action)?;
+    match self.kind {
+      ReplayErrorKind::IllegalMove(e) => write!(f, "is illegal: {:?}", e),
+      ReplayErrorKind::AfterGiveUp => write!(f, "follows a give up"),
+    }
+  }
+}
+impl std::error::Error for ReplayError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordParseError {
+  /// contains the 1-based line number
+  InvalidTag(usize),
+  MissingStartingPlayer,
+  InvalidStartingPlayer(String),
+  InvalidVariant(String),
+  InvalidResult(String),
+  InvalidAction(String),
+  UnterminatedComment,
+  CommentWithoutAction,
+}
+
+impl fmt::Display for RecordParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::InvalidTag(line) => write!(f, "invalid tag in line {}", line),
+      Self::MissingStartingPlayer => write!(f, "missing {} tag", TAG_STARTING_PLAYER),
+      Self::InvalidStartingPlayer(s) => write!(f, "invalid starting player `{}`", s),
+      Self::InvalidVariant(s) => write!(f, "invalid variant `{}`", s),
+      Self::InvalidResult(s) => write!(f, "invalid result `{}`", s),
+      Self::InvalidAction(s) => write!(f, "invalid action `{}`", s),
+      Self::UnterminatedComment => write!(f, "unterminated comment"),
+      Self::CommentWithoutAction => write!(f, "comment before the first action"),
+    }
+  }
+}
+impl std::error::Error for RecordParseError {}
+
+#[derive(Debug)]
+pub enum LoadRecordError {
+  Io(std::io::Error),
+  Parse(RecordParseError),
+}
+
+impl fmt::Display for LoadRecordError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io(e) => write!(f, "reading record failed: {}", e),
+      Self::Parse(e) => write!(f, "parsing record failed: {}", e),
+    }
+  }
+}
+impl std::error::Error for LoadRecordError {}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::{GameRecord, RecordParseError, ReplayErrorKind};
+  use crate::{
+    board::PlaceSymbolError,
+    game::{MoveError, PlayerAction, RoundOutcome, RoundState},
+    rules::RuleSet,
+    GlobalPos, PlayerSymbol,
+  };
+
+  #[test]
+  fn check_record_roundtrip() {
+    let mut rng = StdRng::seed_from_u64(6);
+    for _ in 0..20 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      let mut record = GameRecord::new(round.current_player());
+      record.players = [Some("alice".into()), Some("bob \"the bot\"".into())];
+      record.date = Some("2024.01.31".into());
+      record.other_tags.push(("Site".into(), "office".into()));
+
+      while round.outcome().is_none() {
+        let chosen_tile = round.legal_moves().choose(&mut rng).unwrap();
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+        record.push_action(PlayerAction::MakeMove(chosen_tile));
+        if rng.gen_bool(0.1) {
+          record.actions.last_mut().unwrap().comment = Some("interesting".into());
+        }
+      }
+      record.result = round.outcome();
+
+      let parsed: GameRecord = record.to_string().parse().unwrap();
+      assert_eq!(parsed, record);
+
+      let replayed = parsed.replay().unwrap();
+      assert_eq!(replayed.board(), round.board());
+      assert_eq!(replayed.outcome(), record.result);
+    }
+  }
+
+  #[test]
+  fn check_record_parsing() {
+    let record: GameRecord = r#"
+      [StartingPlayer "O"]
+      [Result "X"]
+
+      1. b2/b2 {center} b2/a3 2. a3/c1
+      resign
+    "#
+    .parse()
+    .unwrap();
+    assert_eq!(record.starting_player, PlayerSymbol::O);
+    assert_eq!(record.result, Some(RoundOutcome::Win(PlayerSymbol::X)));
+    assert_eq!(record.rules, RuleSet::default());
+    assert_eq!(record.actions.len(), 4);
+    assert_eq!(record.actions[0].comment.as_deref(), Some("center"));
+    assert_eq!(
+      record.actions[1].action,
+      PlayerAction::MakeMove(GlobalPos::new(3, 3))
+    );
+    assert_eq!(record.actions[3].action, PlayerAction::GiveUp);
+    assert!(record.replay().is_ok());
+
+    assert_eq!(
+      "1. b2/b2".parse::<GameRecord>(),
+      Err(RecordParseError::MissingStartingPlayer)
+    );
+    assert_eq!(
+      "[StartingPlayer \"X\"]\n1. b2/b4".parse::<GameRecord>(),
+      Err(RecordParseError::InvalidAction("b2/b4".into()))
+    );
+    assert_eq!(
+      "[StartingPlayer \"X\"]\n1. b2/b2 {open \\}".parse::<GameRecord>(),
+      Err(RecordParseError::UnterminatedComment)
+    );
+  }
+
+  #[test]
+  fn check_comment_escaping() {
+    let mut record = GameRecord::new(PlayerSymbol::X);
+    record.push_action(PlayerAction::MakeMove(GlobalPos::new(4, 4)));
+    record.push_action(PlayerAction::GiveUp);
+    record.actions[0].comment = Some(r"a {braced} comment \ with a backslash\".into());
+    record.actions[1].comment = Some(" } ".into());
+
+    let text = record.to_string();
+    assert!(text.contains(r"{a {braced\} comment \\ with a backslash\\}"));
+    let parsed: GameRecord = text.parse().unwrap();
+    assert_eq!(parsed, record);
+  }
+
+  #[test]
+  fn check_replay_reports_first_illegal_move() {
+    let record: GameRecord = "[StartingPlayer \"X\"]\n1. b2/b2 b2/b2 2. a1/a1"
+      .parse()
+      .unwrap();
+    let error = record.replay().unwrap_err();
+    assert_eq!(error.idx, 1);
+    assert_eq!(
+      error.kind,
+      ReplayErrorKind::IllegalMove(MoveError::PlaceSymbol(PlaceSymbolError::TrivialTileNotFree))
+    );
+
+    let record: GameRecord = "[StartingPlayer \"X\"]\n1. b2/b2 resign 2. b2/a1"
+      .parse()
+      .unwrap();
+    assert_eq!(
+      record.replay().unwrap_err().kind,
+      ReplayErrorKind::AfterGiveUp
+    );
+  }
+}
@@ -0,0 +1,276 @@
+//! Configurable rule variants.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  board::{line::LinePos, tile::TilePos, TileBoardState},
+  game::RoundOutcome,
+  OuterPos, PlayerSymbol, PLAYERS,
+};
+
+/// The rules a round is played by. The default is the standard rule set.
+///
+/// Rule sets are written as the names of their non-default rules joined by `+`,
+/// e.g. `won-playable+majority`. The default rule set is written as `standard`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSet {
+  pub won_board: WonBoardRule,
+  pub drawn_board: DrawnBoardRule,
+  pub draw: DrawRule,
+}
+
+/// Whether sub-boards accept further symbols once they are won.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WonBoardRule {
+  /// Won sub-boards are closed. Being sent to one gives a free choice.
+  #[default]
+  Closed,
+  /// Won sub-boards stay playable, as long as they have free tiles.
+  /// Their winner does not change anymore.
+  Playable,
+}
+
+/// How drawn sub-boards count in the lines of the outer board.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DrawnBoardRule {
+  /// Drawn sub-boards block every line they are part of.
+  #[default]
+  Neutral,
+  /// Drawn sub-boards count for both players.
+  /// A line needs at least one sub-board won by the player to count as theirs.
+  /// If a move completes lines for both players, the player who made it wins.
+  CountsForBoth,
+}
+
+/// How a round ends, in which no line of the outer board can be completed anymore.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DrawRule {
+  /// The round is drawn as soon as no line can be completed anymore.
+  #[default]
+  Draw,
+  /// The round is played until every sub-board is decided
+  /// and then won by the player with more won sub-boards.
+  MajorityOfWonBoards,
+}
+
+impl RuleSet {
+  pub fn is_standard(self) -> bool {
+    self == Self::default()
+  }
+
+  /// Whether a sub-board in the given state accepts symbols at all.
+  /// Playability of a won sub-board additionally requires a free tile.
+  pub fn is_placeable(self, sub_board_state: TileBoardState) -> bool {
+    match sub_board_state {
+      TileBoardState::Won(_) => self.won_board == WonBoardRule::Playable,
+      _ => sub_board_state.is_placeable(),
+    }
+  }
+
+  /// Determines the outcome of a round from the states of its sub-boards.
+  ///
+  /// `last_mover` is the player who made the last move.
+  /// They win if their move completed lines for both players.
+  pub fn outcome(
+    self,
+    sub_board_state: impl Fn(OuterPos) -> TileBoardState,
+    last_mover: PlayerSymbol,
+  ) -> Option<RoundOutcome> {
+    let states: [TileBoardState; 9] = std::array::from_fn(|i| {
+      let pos = TilePos::from_linear_idx(i);
+      sub_board_state(OuterPos::new(pos.x(), pos.y()))
+    });
+    let line_states = |line: LinePos| line.iter::<3>().map(|pos| states[pos.linear_idx()]);
+    let counts_for = |state: TileBoardState, player: PlayerSymbol| match state {
+      TileBoardState::Won(p) => p == player,
+      TileBoardState::Drawn | TileBoardState::FullyDrawn => {
+        self.drawn_board == DrawnBoardRule::CountsForBoth
+      }
+      TileBoardState::Free => false,
+    };
+
+    let has_line = |player: PlayerSymbol| {
+      LinePos::all::<3, 3>().any(|line| {
+        line_states(line).all(|state| counts_for(state, player))
+          && line_states(line).any(|state| state == TileBoardState::Won(player))
+      })
+    };
+    match (has_line(PlayerSymbol::X), has_line(PlayerSymbol::O)) {
+      (true, true) => return Some(RoundOutcome::Win(last_mover)),
+      (true, false) => return Some(RoundOutcome::Win(PlayerSymbol::X)),
+      (false, true) => return Some(RoundOutcome::Win(PlayerSymbol::O)),
+      (false, false) => {}
+    }
+
+    match self.draw {
+      DrawRule::Draw => {
+        let could_complete = |player: PlayerSymbol| {
+          LinePos::all::<3, 3>().any(|line| {
+            line_states(line).all(|state| state.is_free() || counts_for(state, player))
+              && line_states(line).any(|state| state.is_free() || state.is_won())
+          })
+        };
+        (!PLAYERS.into_iter().any(could_complete)).then_some(RoundOutcome::Draw)
+      }
+      DrawRule::MajorityOfWonBoards => {
+        if !states.iter().all(|state| state.is_decided()) {
+          return None;
+        }
+        let nwon = |player| {
+          states
+            .iter()
+            .filter(|&&state| state == TileBoardState::Won(player))
+            .count()
+        };
+        Some(match nwon(PlayerSymbol::X).cmp(&nwon(PlayerSymbol::O)) {
+          Ordering::Greater => RoundOutcome::Win(PlayerSymbol::X),
+          Ordering::Less => RoundOutcome::Win(PlayerSymbol::O),
+          Ordering::Equal => RoundOutcome::Draw,
+        })
+      }
+    }
+  }
+}
+
+const STANDARD: &str = "standard";
+const WON_PLAYABLE: &str = "won-playable";
+const DRAWN_COUNTS_FOR_BOTH: &str = "drawn-counts-for-both";
+const MAJORITY: &str = "majority";
+
+impl fmt::Display for RuleSet {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut names = Vec::new();
+    if self.won_board == WonBoardRule::Playable {
+      names.push(WON_PLAYABLE);
+    }
+    if self.drawn_board == DrawnBoardRule::CountsForBoth {
+      names.push(DRAWN_COUNTS_FOR_BOTH);
+    }
+    if self.draw == DrawRule::MajorityOfWonBoards {
+      names.push(MAJORITY);
+    }
+    match names.is_empty() {
+      true => write!(f, "{}", STANDARD),
+      false => write!(f, "{}", names.join("+")),
+    }
+  }
+}
+
+impl FromStr for RuleSet {
+  type Err = UnknownRuleError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut rules = RuleSet::default();
+    if s == STANDARD {
+      return Ok(rules);
+    }
+    for name in s.split('+') {
+      match name {
+        WON_PLAYABLE => rules.won_board = WonBoardRule::Playable,
+        DRAWN_COUNTS_FOR_BOTH => rules.drawn_board = DrawnBoardRule::CountsForBoth,
+        MAJORITY => rules.draw = DrawRule::MajorityOfWonBoards,
+        _ => return Err(UnknownRuleError(name.to_string())),
+      }
+    }
+    Ok(rules)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRuleError(pub String);
+impl fmt::Display for UnknownRuleError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unknown rule `{}`", self.0)
+  }
+}
+impl std::error::Error for UnknownRuleError {}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::{DrawRule, DrawnBoardRule, RuleSet, WonBoardRule};
+  use crate::{
+    game::{RoundOutcome, RoundState},
+    GlobalPos, OuterBoardBackend, OuterPos, PlayerSymbol,
+  };
+
+  fn all_rule_sets() -> impl Iterator<Item = RuleSet> {
+    [WonBoardRule::Closed, WonBoardRule::Playable]
+      .into_iter()
+      .flat_map(|won_board| {
+        [DrawnBoardRule::Neutral, DrawnBoardRule::CountsForBoth]
+          .into_iter()
+          .map(move |drawn_board| (won_board, drawn_board))
+      })
+      .flat_map(|(won_board, drawn_board)| {
+        [DrawRule::Draw, DrawRule::MajorityOfWonBoards]
+          .into_iter()
+          .map(move |draw| RuleSet {
+            won_board,
+            drawn_board,
+            draw,
+          })
+      })
+  }
+
+  #[test]
+  fn check_rule_set_notation() {
+    assert_eq!(RuleSet::default().to_string(), "standard");
+    for rules in all_rule_sets() {
+      assert_eq!(rules.to_string().parse::<RuleSet>().unwrap(), rules);
+    }
+    assert!("won-playable+house".parse::<RuleSet>().is_err());
+  }
+
+  #[test]
+  fn check_random_games_terminate() {
+    let mut rng = StdRng::seed_from_u64(5);
+    for rules in all_rule_sets() {
+      for _ in 0..30 {
+        let mut round = RoundState::new(rng.gen(), rules);
+        while round.outcome().is_none() {
+          let chosen_tile = round
+            .legal_moves()
+            .choose(&mut rng)
+            .expect("unfinished round without legal moves");
+          round
+            .try_play_move(round.current_player(), chosen_tile)
+            .unwrap();
+        }
+
+        let board = round.board();
+        if rules.draw == DrawRule::MajorityOfWonBoards
+          && round.outcome() == Some(RoundOutcome::Draw)
+        {
+          assert!(OuterPos::all().all(|pos| board.sub_board_state(pos).is_decided()));
+        }
+        if rules.is_standard() {
+          let last_mover = round.current_player().other();
+          let outcome = rules.outcome(|pos| board.sub_board_state(pos), last_mover);
+          assert_eq!(outcome, round.outcome());
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn check_won_board_playable() {
+    let rules = RuleSet {
+      won_board: WonBoardRule::Playable,
+      ..RuleSet::default()
+    };
+    let mut round = RoundState::new(PlayerSymbol::X, rules);
+    // X wins the top left sub-board, O keeps sending X back there
+    for (x, y) in [(0, 1), (0, 3), (0, 2), (0, 6), (0, 0)] {
+      round
+        .try_play_move(round.current_player(), GlobalPos::new(x, y))
+        .unwrap();
+    }
+    assert!(round.board().sub_board_state(OuterPos::new(0, 0)).is_won());
+    // O is sent to the won sub-board and has to play there
+    assert_eq!(round.current_outer_pos(), Some(OuterPos::new(0, 0)));
+    assert_eq!(round.legal_moves().count(), 6);
+  }
+}
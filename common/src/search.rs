@@ -0,0 +1,614 @@
+//! Negamax alpha-beta search with iterative deepening.
+//!
+//! Every iteration searches one move deeper than the last, trying the best moves found so far
+//! first. The search stops once the [`SearchLimits`] are exhausted and reports the result of the
+//! deepest completed iteration. The first iteration always completes, so there is always a move.
+//!
+//! With several threads, the search is a lazy SMP: helper threads search the same position
+//! alongside the main thread, half of them one ply ahead, and share their results through the
+//! transposition table. Helpers keep to the depth and time limits and stop once the main thread
+//! is done, which alone reports a result.
+//! A single thread searches deterministically.
+
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  eval::{evaluate, EvalWeights, Score, WIN_SCORE},
+  game::{RoundOutcome, RoundState},
+  tt::{Bound, TranspositionTable, TtEntry},
+  zobrist, GlobalPos, OuterBoardBackend,
+};
+
+/// Bound exceeding every score, used as the initial search window.
+const INFINITY: Score = WIN_SCORE + 1;
+/// A round cannot last longer than there are tiles.
+pub const MAX_DEPTH: usize = 81;
+/// Number of nodes between checks of the clock.
+const CLOCK_CHECK_INTERVAL: u64 = 1024;
+/// Size of the table shared by the threads of a searcher without a configured one.
+const SMP_TT_SIZE_MB: usize = 16;
+
+/// The budget of a search. The search stops once any of the limits is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchLimits {
+  pub depth: usize,
+  pub time: Option<Duration>,
+  pub nodes: Option<u64>,
+}
+
+impl SearchLimits {
+  pub fn depth(depth: usize) -> Self {
+    Self {
+      depth,
+      time: None,
+      nodes: None,
+    }
+  }
+  pub fn time(time: Duration) -> Self {
+    Self {
+      depth: MAX_DEPTH,
+      time: Some(time),
+      nodes: None,
+    }
+  }
+  pub fn nodes(nodes: u64) -> Self {
+    Self {
+      depth: MAX_DEPTH,
+      time: None,
+      nodes: Some(nodes),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+  pub best_move: GlobalPos,
+  /// score of the best move, from the point of view of the player to move
+  pub score: Score,
+  /// principal variation, the expected continuation starting with the best move
+  pub pv: Vec<GlobalPos>,
+  /// depth of the deepest completed iteration
+  pub depth: usize,
+  /// number of searched nodes, over all iterations
+  pub nodes: u64,
+}
+
+impl SearchResult {
+  /// The number of moves until the round is won, if the score says it is.
+  /// Negative if the player to move loses.
+  pub fn moves_to_win(&self) -> Option<i32> {
+    let plies = WIN_SCORE - self.score.abs();
+    (plies as usize <= MAX_DEPTH).then_some(self.score.signum() * plies)
+  }
+}
+
+/// Searches positions using the static evaluation of [`crate::eval`] at its leaves.
+#[derive(Debug, Clone)]
+pub struct Searcher {
+  weights: EvalWeights,
+  /// shared with other searches, keeping its entries between searches
+  tt: Option<Arc<TranspositionTable>>,
+  /// number of threads of a search, at least 1
+  threads: usize,
+}
+
+impl Default for Searcher {
+  fn default() -> Self {
+    Self::new(EvalWeights::default())
+  }
+}
+
+impl Searcher {
+  pub fn new(weights: EvalWeights) -> Self {
+    Self {
+      weights,
+      tt: None,
+      threads: 1,
+    }
+  }
+
+  /// Caches search results in the table, which may be shared with other searchers.
+  pub fn with_transposition_table(mut self, tt: Arc<TranspositionTable>) -> Self {
+    self.tt = Some(tt);
+    self
+  }
+
+  /// Searches with the given number of threads. The threads share their results through a
+  /// transposition table, so a searcher with several threads and without one allocates it here,
+  /// keeping it for all its searches.
+  pub fn with_threads(mut self, threads: usize) -> Self {
+    self.threads = threads.max(1);
+    if self.threads > 1 && self.tt.is_none() {
+      self.tt = Some(Arc::new(TranspositionTable::new(SMP_TT_SIZE_MB)));
+    }
+    self
+  }
+
+  /// Removes all entries of the transposition table, if there is one.
+  pub fn clear_transposition_table(&self) {
+    if let Some(tt) = &self.tt {
+      tt.clear();
+    }
+  }
+
+  /// Searches for the best move of the current player.
+  /// Returns `None` if the round has no legal moves.
+  ///
+  /// The node limit only counts the nodes of the main thread,
+  /// the reported nodes are the ones of all threads.
+  pub fn search<Board: OuterBoardBackend + Sync>(
+    &self,
+    round: &RoundState<Board>,
+    limits: SearchLimits,
+  ) -> Option<SearchResult> {
+    round.legal_moves().next()?;
+    if self.threads == 1 {
+      return Some(Search::new(&self.weights, self.tt.as_deref(), limits, None).iterate(round, 1));
+    }
+
+    let tt = self
+      .tt
+      .as_deref()
+      .expect("searchers with several threads have a table");
+    let stop = AtomicBool::new(false);
+    let result = thread::scope(|scope| {
+      let helpers: Vec<_> = (1..self.threads)
+        .map(|id| {
+          let stop = &stop;
+          scope.spawn(move || {
+            let limits = SearchLimits {
+              nodes: None,
+              ..limits
+            };
+            let first_depth = (1 + id % 2).min(limits.depth.max(1));
+            let mut search = Search::new(&self.weights, Some(tt), limits, Some(stop));
+            search.iterate(round, first_depth);
+            search.nodes
+          })
+        })
+        .collect();
+
+      let mut search = Search::new(&self.weights, Some(tt), limits, Some(&stop));
+      let result = search.iterate(round, 1);
+      stop.store(true, Ordering::Relaxed);
+      let helper_nodes: u64 = helpers
+        .into_iter()
+        .map(|helper| helper.join().expect("search thread panicked"))
+        .sum();
+      SearchResult {
+        nodes: result.nodes + helper_nodes,
+        ..result
+      }
+    });
+    Some(result)
+  }
+}
+
+/// The state of a single search.
+struct Search<'a> {
+  weights: &'a EvalWeights,
+  tt: Option<&'a TranspositionTable>,
+  limits: SearchLimits,
+  /// set by the main thread once it is done, stopping the helper threads
+  stop: Option<&'a AtomicBool>,
+  start: Instant,
+  nodes: u64,
+  /// set once a limit is reached, aborting the current iteration
+  stopped: bool,
+
+  /// per ply, the last two moves that caused a beta cutoff
+  killers: [[Option<GlobalPos>; 2]; MAX_DEPTH],
+  /// per tile, a score of how often moves on it caused a beta cutoff, weighted by depth
+  history: [u64; 81],
+  /// principal variation of the last completed iteration
+  prev_pv: Vec<GlobalPos>,
+}
+
+impl<'a> Search<'a> {
+  fn new(
+    weights: &'a EvalWeights,
+    tt: Option<&'a TranspositionTable>,
+    limits: SearchLimits,
+    stop: Option<&'a AtomicBool>,
+  ) -> Self {
+    Self {
+      weights,
+      tt,
+      limits,
+      stop,
+      start: Instant::now(),
+      nodes: 0,
+      stopped: false,
+      killers: [[None; 2]; MAX_DEPTH],
+      history: [0; 81],
+      prev_pv: Vec::new(),
+    }
+  }
+
+  /// Deepens the search iteration by iteration, starting at the given depth.
+  /// The round must have legal moves.
+  fn iterate<Board: OuterBoardBackend>(
+    &mut self,
+    round: &RoundState<Board>,
+    first_depth: usize,
+  ) -> SearchResult {
+    let mut round = round.clone();
+    let mut result: Option<SearchResult> = None;
+    for depth in first_depth..=self.limits.depth.clamp(first_depth, MAX_DEPTH) {
+      let mut pv = Vec::new();
+      let score = self.negamax(&mut round, depth, 0, -INFINITY, INFINITY, &mut pv);
+      if self.stopped && result.is_some() {
+        break;
+      }
+      result = Some(SearchResult {
+        best_move: pv[0],
+        score,
+        pv: pv.clone(),
+        depth,
+        nodes: self.nodes,
+      });
+      self.prev_pv = pv;
+      // the remaining iterations cannot change a decided result
+      if score.abs() >= WIN_SCORE - depth as Score || self.limit_reached() {
+        break;
+      }
+    }
+    SearchResult {
+      nodes: self.nodes,
+      ..result.expect("the first iteration always completes")
+    }
+  }
+
+  fn negamax<Board: OuterBoardBackend>(
+    &mut self,
+    round: &mut RoundState<Board>,
+    depth: usize,
+    ply: usize,
+    mut alpha: Score,
+    beta: Score,
+    pv: &mut Vec<GlobalPos>,
+  ) -> Score {
+    pv.clear();
+    self.nodes += 1;
+    if self.nodes.is_multiple_of(CLOCK_CHECK_INTERVAL) && self.limit_reached() {
+      self.stopped = true;
+    }
+
+    match round.outcome() {
+      // prefer faster wins and slower losses
+      Some(RoundOutcome::Win(p)) if p == round.current_player() => return WIN_SCORE - ply as Score,
+      Some(RoundOutcome::Win(_)) => return -WIN_SCORE + ply as Score,
+      Some(RoundOutcome::Draw) => return 0,
+      None => {}
+    }
+    if depth == 0 {
+      return evaluate(round, self.weights);
+    }
+
+    // the rules decide the legal moves and outcomes, so tables may not mix them
+    let hash = round.zobrist_hash() ^ zobrist::rules_key(round.rules());
+    let entry = self.tt.and_then(|tt| tt.probe(hash));
+    if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth as usize >= depth) {
+      let score = score_from_tt(entry.score, ply);
+      let cutoff = match entry.bound {
+        Bound::Exact => true,
+        Bound::Lower => score >= beta,
+        Bound::Upper => score <= alpha,
+      };
+      if cutoff {
+        return score;
+      }
+    }
+
+    let mut moves: Vec<_> = round.legal_moves().collect();
+    self.order_moves(&mut moves, ply, entry.and_then(|entry| entry.best_move));
+
+    let alpha_orig = alpha;
+    let mut best_score = -INFINITY;
+    let mut best_move = None;
+    let mut child_pv = Vec::new();
+    for global_pos in moves {
+      round
+        .try_play_move(round.current_player(), global_pos)
+        .expect("legal move must be playable");
+      let score = -self.negamax(round, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+      round.undo_move();
+      // results of an aborted search are meaningless, except for the first iteration
+      if self.stopped && !self.prev_pv.is_empty() {
+        return 0;
+      }
+
+      if score > best_score {
+        best_score = score;
+        best_move = Some(global_pos);
+        if score > alpha {
+          alpha = score;
+          pv.clear();
+          pv.push(global_pos);
+          pv.extend_from_slice(&child_pv);
+        }
+        if alpha >= beta {
+          self.store_cutoff(global_pos, depth, ply);
+          break;
+        }
+      }
+    }
+
+    if let Some(tt) = self.tt.filter(|_| !self.stopped) {
+      let bound = if best_score <= alpha_orig {
+        Bound::Upper
+      } else if best_score >= beta {
+        Bound::Lower
+      } else {
+        Bound::Exact
+      };
+      let entry = TtEntry {
+        score: score_to_tt(best_score, ply),
+        bound,
+        depth: depth as u8,
+        best_move,
+      };
+      tt.store(hash, entry);
+    }
+    best_score
+  }
+
+  /// Orders the moves by the principal variation of the last iteration, the best move of
+  /// the transposition table, the killer moves and the history heuristic.
+  fn order_moves(&self, moves: &mut [GlobalPos], ply: usize, tt_move: Option<GlobalPos>) {
+    let pv_move = self.prev_pv.get(ply).copied();
+    let killers = self.killers[ply];
+    moves.sort_by_cached_key(|&global_pos| {
+      let priority = if Some(global_pos) == pv_move {
+        0
+      } else if Some(global_pos) == tt_move {
+        1
+      } else if killers.contains(&Some(global_pos)) {
+        2
+      } else {
+        3
+      };
+      (
+        priority,
+        std::cmp::Reverse(self.history[global_pos.linear_idx()]),
+      )
+    });
+  }
+
+  fn store_cutoff(&mut self, global_pos: GlobalPos, depth: usize, ply: usize) {
+    let killers = &mut self.killers[ply];
+    if killers[0] != Some(global_pos) {
+      killers[1] = killers[0];
+      killers[0] = Some(global_pos);
+    }
+    self.history[global_pos.linear_idx()] += (depth * depth) as u64;
+  }
+
+  fn limit_reached(&self) -> bool {
+    self
+      .stop
+      .map(|stop| stop.load(Ordering::Relaxed))
+      .unwrap_or(false)
+      || self
+        .limits
+        .time
+        .map(|time| self.start.elapsed() >= time)
+        .unwrap_or(false)
+      || self
+        .limits
+        .nodes
+        .map(|nodes| self.nodes >= nodes)
+        .unwrap_or(false)
+  }
+}
+
+/// Win scores count the plies from the root of the search,
+/// stored scores count them from the stored position instead.
+fn score_to_tt(score: Score, ply: usize) -> Score {
+  match score.abs() >= WIN_SCORE - MAX_DEPTH as Score {
+    true => score + score.signum() * ply as Score,
+    false => score,
+  }
+}
+fn score_from_tt(score: Score, ply: usize) -> Score {
+  match score.abs() >= WIN_SCORE - MAX_DEPTH as Score {
+    true => score - score.signum() * ply as Score,
+    false => score,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::{sync::Arc, time::Duration};
+
+  use rand::prelude::*;
+
+  use super::{SearchLimits, Searcher, INFINITY};
+  use crate::{
+    eval::{evaluate, EvalWeights, Score, WIN_SCORE},
+    game::{RoundOutcome, RoundState},
+    rules::{RuleSet, WonBoardRule},
+    tt::TranspositionTable,
+    PlayerSymbol,
+  };
+
+  fn minimax(round: &mut RoundState, depth: usize, ply: usize, weights: &EvalWeights) -> Score {
+    match round.outcome() {
+      Some(RoundOutcome::Win(p)) if p == round.current_player() => return WIN_SCORE - ply as Score,
+      Some(RoundOutcome::Win(_)) => return -WIN_SCORE + ply as Score,
+      Some(RoundOutcome::Draw) => return 0,
+      None => {}
+    }
+    if depth == 0 {
+      return evaluate(round, weights);
+    }
+    let moves: Vec<_> = round.legal_moves().collect();
+    let mut best = -INFINITY;
+    for global_pos in moves {
+      round
+        .try_play_move(round.current_player(), global_pos)
+        .unwrap();
+      best = best.max(-minimax(round, depth - 1, ply + 1, weights));
+      round.undo_move();
+    }
+    best
+  }
+
+  #[test]
+  fn check_search_matches_minimax() {
+    let weights = EvalWeights::default();
+    let searcher = Searcher::new(weights);
+    let mut rng = StdRng::seed_from_u64(12);
+    for _ in 0..6 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      for _ in 0..rng.gen_range(10..40) {
+        let Some(chosen_tile) = round.legal_moves().choose(&mut rng) else {
+          break;
+        };
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+      }
+      let Some(result) = searcher.search(&round, SearchLimits::depth(3)) else {
+        continue;
+      };
+      assert_eq!(
+        result.score,
+        minimax(&mut round.clone(), result.depth, 0, &weights)
+      );
+      // positions are only transposed into at the same remaining depth within an iteration
+      let tt = Arc::new(TranspositionTable::new(1));
+      let tt_result = Searcher::new(weights)
+        .with_transposition_table(tt)
+        .search(&round, SearchLimits::depth(3))
+        .unwrap();
+      assert_eq!(tt_result.score, result.score);
+
+      // the principal variation is playable and leads to the reported score
+      let mut pv_round = round.clone();
+      for &global_pos in &result.pv {
+        pv_round
+          .try_play_move(pv_round.current_player(), global_pos)
+          .unwrap();
+      }
+      let sign = match result.pv.len() % 2 {
+        0 => 1,
+        _ => -1,
+      };
+      if pv_round.outcome().is_none() {
+        assert_eq!(result.pv.len(), result.depth);
+        assert_eq!(sign * evaluate(&pv_round, &weights), result.score);
+      }
+    }
+  }
+
+  #[test]
+  fn check_search_finds_win() {
+    // X can win the round by completing the top row of sub-boards
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X -".parse().unwrap();
+    let result = Searcher::default()
+      .search(&round, SearchLimits::depth(4))
+      .unwrap();
+    assert_eq!(result.moves_to_win(), Some(1));
+    let mut round = round;
+    round
+      .try_play_move(PlayerSymbol::X, result.best_move)
+      .unwrap();
+    assert_eq!(round.outcome(), Some(RoundOutcome::Win(PlayerSymbol::X)));
+  }
+
+  #[test]
+  fn check_search_limits() {
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    let searcher = Searcher::default();
+
+    let result = searcher.search(&round, SearchLimits::nodes(1)).unwrap();
+    assert_eq!(result.depth, 1);
+    assert!(round.could_play_move(PlayerSymbol::X, result.best_move));
+
+    let result = searcher
+      .search(&round, SearchLimits::time(Duration::from_millis(50)))
+      .unwrap();
+    assert!(result.depth >= 2);
+
+    let finished: RoundState = "XXXXXXXXX/9/9/OO1OO1OO1/O2O2O2/9/9/9/9 O -"
+      .parse()
+      .unwrap();
+    assert!(finished.outcome().is_some());
+    assert!(searcher.search(&finished, SearchLimits::depth(2)).is_none());
+  }
+
+  #[test]
+  fn check_parallel_search() {
+    let searcher = Searcher::default().with_threads(4);
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X -".parse().unwrap();
+    let result = searcher.search(&round, SearchLimits::depth(2)).unwrap();
+    assert_eq!(result.moves_to_win(), Some(1));
+
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    let result = searcher.search(&round, SearchLimits::depth(3)).unwrap();
+    assert_eq!(result.depth, 3);
+    assert!(round.could_play_move(PlayerSymbol::X, result.best_move));
+    // the helpers stop with the main thread
+    let result = searcher.search(&round, SearchLimits::nodes(1)).unwrap();
+    assert_eq!(result.depth, 1);
+    // and do not search deeper than the limit, each visiting the root and its moves at most
+    let result = searcher.search(&round, SearchLimits::depth(1)).unwrap();
+    assert!(result.nodes <= 4 * 82, "{} nodes", result.nodes);
+
+    // a single thread is deterministic
+    let searcher = Searcher::default().with_threads(1);
+    assert_eq!(
+      searcher.search(&round, SearchLimits::depth(2)),
+      searcher.search(&round, SearchLimits::depth(2))
+    );
+  }
+
+  #[test]
+  fn check_tt_separates_rules() {
+    let won_playable = RuleSet {
+      won_board: WonBoardRule::Playable,
+      ..RuleSet::default()
+    };
+    let mut rng = StdRng::seed_from_u64(13);
+    let mut nchecked = 0;
+    while nchecked < 4 {
+      let mut round = RoundState::new(rng.gen(), won_playable);
+      for _ in 0..40 {
+        let Some(chosen_tile) = round.legal_moves().choose(&mut rng) else {
+          break;
+        };
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+      }
+      // the same tiles under the standard rules, if the forced sub-board is still placeable there
+      let notation = round.to_string();
+      let Ok(standard) = notation
+        .trim_end_matches(" won-playable")
+        .parse::<RoundState>()
+      else {
+        continue;
+      };
+      if round.outcome().is_some() || standard.outcome().is_some() {
+        continue;
+      }
+
+      let searcher =
+        Searcher::default().with_transposition_table(Arc::new(TranspositionTable::new(1)));
+      searcher.search(&standard, SearchLimits::depth(3)).unwrap();
+      let fresh = Searcher::default()
+        .with_transposition_table(Arc::new(TranspositionTable::new(1)))
+        .search(&round, SearchLimits::depth(3));
+      assert_eq!(searcher.search(&round, SearchLimits::depth(3)), fresh);
+      nchecked += 1;
+    }
+  }
+}
@@ -0,0 +1,412 @@
+//! Exact solver for late positions, finding the outcome under perfect play of both players.
+//!
+//! The solver runs an alpha-beta search on the three values win, draw and loss, caching solved
+//! positions. Under the standard rules it additionally uses the derived states of the boards:
+//! - A player without any completable outer line cannot win, which narrows the search window.
+//! - Sub-boards no outer line can be completed through anymore are dead.
+//!   Once closed, it does not matter who won them, so such positions share their cache entry.
+//! - The symbols of a drawn sub-board do not matter anymore, only its free tiles do.
+
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap},
+  hash::{Hash, Hasher},
+};
+
+use crate::{
+  board::{
+    line::{LinePos, LineState},
+    tile::{TilePos, TrivialTileState},
+    TileBoardState,
+  },
+  game::{RoundOutcome, RoundState},
+  tt::Bound,
+  GlobalPos, InnerPos, OuterBoardBackend, OuterPos, PlayerSymbol,
+};
+
+/// Value of a position for the player to move.
+type Value = i8;
+const WIN: Value = 1;
+const DRAW: Value = 0;
+const LOSS: Value = -1;
+
+/// Value of a position under perfect play, for the player to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolvedValue {
+  Win,
+  Draw,
+  Loss,
+}
+
+impl SolvedValue {
+  fn from_value(value: Value) -> Self {
+    match value {
+      WIN => Self::Win,
+      LOSS => Self::Loss,
+      _ => Self::Draw,
+    }
+  }
+
+  /// The value for the other player.
+  pub fn flipped(self) -> Self {
+    match self {
+      Self::Win => Self::Loss,
+      Self::Draw => Self::Draw,
+      Self::Loss => Self::Win,
+    }
+  }
+
+  /// The outcome of the round, if `player` is the player to move.
+  pub fn outcome(self, player: PlayerSymbol) -> RoundOutcome {
+    match self {
+      Self::Win => RoundOutcome::Win(player),
+      Self::Draw => RoundOutcome::Draw,
+      Self::Loss => RoundOutcome::Win(player.other()),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+  /// value of the position under perfect play, for the player to move
+  pub value: SolvedValue,
+  /// a move achieving the outcome, `None` if the round is already over
+  pub best_move: Option<GlobalPos>,
+  /// number of searched nodes
+  pub nodes: u64,
+}
+
+/// The solver gave up, because the position needs more nodes than allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeLimitError(pub u64);
+impl std::fmt::Display for NodeLimitError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "position not solved within {} nodes", self.0)
+  }
+}
+impl std::error::Error for NodeLimitError {}
+
+/// Solves the round, searching at most `node_limit` nodes.
+pub fn solve<Board: OuterBoardBackend>(
+  round: &RoundState<Board>,
+  node_limit: u64,
+) -> Result<Solution, NodeLimitError> {
+  let mut solver = Solver {
+    node_limit,
+    nodes: 0,
+    cache: HashMap::new(),
+  };
+  let mut round = round.clone();
+  let curr_player = round.current_player();
+
+  if let Some(outcome) = round.outcome() {
+    let value = match outcome {
+      RoundOutcome::Win(p) if p == curr_player => SolvedValue::Win,
+      RoundOutcome::Win(_) => SolvedValue::Loss,
+      RoundOutcome::Draw => SolvedValue::Draw,
+    };
+    return Ok(Solution {
+      value,
+      best_move: None,
+      nodes: 0,
+    });
+  }
+
+  if let Some(global_pos) = winning_move(&mut round) {
+    return Ok(Solution {
+      value: SolvedValue::Win,
+      best_move: Some(global_pos),
+      nodes: 1,
+    });
+  }
+  let moves: Vec<_> = round.legal_moves().collect();
+  let mut best = (LOSS - 1, moves[0]);
+  for global_pos in moves {
+    round.try_play_move(curr_player, global_pos).unwrap();
+    let value = solver.negamax(&mut round, LOSS, -best.0.max(LOSS));
+    round.undo_move();
+    let value = -value?;
+    if value > best.0 {
+      best = (value, global_pos);
+      if value == WIN {
+        break;
+      }
+    }
+  }
+  Ok(Solution {
+    value: SolvedValue::from_value(best.0),
+    best_move: Some(best.1),
+    nodes: solver.nodes,
+  })
+}
+
+struct Solver {
+  node_limit: u64,
+  nodes: u64,
+  /// solved bounds by position key
+  cache: HashMap<u64, (Value, Bound)>,
+}
+
+impl Solver {
+  fn negamax<Board: OuterBoardBackend>(
+    &mut self,
+    round: &mut RoundState<Board>,
+    mut alpha: Value,
+    mut beta: Value,
+  ) -> Result<Value, NodeLimitError> {
+    self.nodes += 1;
+    if self.nodes > self.node_limit {
+      return Err(NodeLimitError(self.node_limit));
+    }
+
+    let curr_player = round.current_player();
+    match round.outcome() {
+      Some(RoundOutcome::Win(p)) if p == curr_player => return Ok(WIN),
+      Some(RoundOutcome::Win(_)) => return Ok(LOSS),
+      Some(RoundOutcome::Draw) => return Ok(DRAW),
+      None => {}
+    }
+
+    let standard = round.rules().is_standard();
+    if standard {
+      let outer_states = outer_states(round);
+      if !could_complete_line(&outer_states, curr_player) {
+        beta = beta.min(DRAW);
+      }
+      if !could_complete_line(&outer_states, curr_player.other()) {
+        alpha = alpha.max(DRAW);
+      }
+      if alpha >= beta {
+        return Ok(alpha);
+      }
+    }
+
+    let key = match standard {
+      true => position_key(round),
+      false => round.zobrist_hash(),
+    };
+    if let Some(&(value, bound)) = self.cache.get(&key) {
+      match bound {
+        Bound::Exact => return Ok(value),
+        Bound::Lower => alpha = alpha.max(value),
+        Bound::Upper => beta = beta.min(value),
+      }
+      if alpha >= beta {
+        return Ok(value);
+      }
+    }
+
+    if winning_move(round).is_some() {
+      self.cache.insert(key, (WIN, Bound::Exact));
+      return Ok(WIN);
+    }
+    // moves keeping the opponent in a single sub-board first
+    let mut moves: Vec<_> = round.legal_moves().collect();
+    moves.sort_by_key(|&global_pos| {
+      let next_outer_pos = InnerPos::from(global_pos).as_outer();
+      !round.board().sub_board_state(next_outer_pos).is_placeable()
+    });
+
+    let alpha_orig = alpha;
+    let mut best = LOSS - 1;
+    for global_pos in moves {
+      round.try_play_move(curr_player, global_pos).unwrap();
+      let value = self.negamax(round, -beta, -alpha);
+      round.undo_move();
+      let value = -value?;
+      best = best.max(value);
+      alpha = alpha.max(value);
+      if alpha >= beta {
+        break;
+      }
+    }
+
+    let bound = if best <= alpha_orig {
+      Bound::Upper
+    } else if best >= beta {
+      Bound::Lower
+    } else {
+      Bound::Exact
+    };
+    self.cache.insert(key, (best, bound));
+    Ok(best)
+  }
+}
+
+/// A move winning the round right away.
+fn winning_move<Board: OuterBoardBackend>(round: &mut RoundState<Board>) -> Option<GlobalPos> {
+  let curr_player = round.current_player();
+  let moves: Vec<_> = round.legal_moves().collect();
+  moves.into_iter().find(|&global_pos| {
+    round.try_play_move(curr_player, global_pos).unwrap();
+    let wins = round.outcome() == Some(RoundOutcome::Win(curr_player));
+    round.undo_move();
+    wins
+  })
+}
+
+fn outer_states<Board: OuterBoardBackend>(round: &RoundState<Board>) -> [TileBoardState; 9] {
+  std::array::from_fn(|i| {
+    let pos = TilePos::from_linear_idx(i);
+    round
+      .board()
+      .sub_board_state(OuterPos::new(pos.x(), pos.y()))
+  })
+}
+
+fn outer_line_state(outer_states: &[TileBoardState; 9], line: LinePos) -> LineState {
+  line
+    .iter::<3>()
+    .map(|pos| LineState::from(outer_states[pos.linear_idx()]))
+    .fold(LineState::free(), LineState::combine)
+}
+
+/// Whether the line holds nothing but free sub-boards and sub-boards won by the player.
+fn is_line_open_for(line_state: LineState, player: PlayerSymbol) -> bool {
+  line_state.is_free() || line_state.occupant() == Some(player)
+}
+
+fn could_complete_line(outer_states: &[TileBoardState; 9], player: PlayerSymbol) -> bool {
+  LinePos::all::<3, 3>().any(|line| is_line_open_for(outer_line_state(outer_states, line), player))
+}
+
+/// Hashes the position, forgetting details which cannot influence the outcome anymore.
+fn position_key<Board: OuterBoardBackend>(round: &RoundState<Board>) -> u64 {
+  const DEAD_CLOSED: u8 = 3;
+  const FULLY_DRAWN: u8 = 4;
+  const DRAWN_OCCUPIED: u8 = 5;
+  const WON: [u8; 2] = [6, 7];
+
+  let outer_states = outer_states(round);
+  let is_dead = |outer_pos: TilePos| {
+    LinePos::all_through_point::<3, 3>(outer_pos).all(|line| {
+      let line_state = outer_line_state(&outer_states, line);
+      !is_line_open_for(line_state, PlayerSymbol::X)
+        && !is_line_open_for(line_state, PlayerSymbol::O)
+    })
+  };
+
+  let mut key = [0u8; 83];
+  for (i, &state) in outer_states.iter().enumerate() {
+    let outer_tile_pos = TilePos::from_linear_idx(i);
+    let outer_pos = OuterPos::new(outer_tile_pos.x(), outer_tile_pos.y());
+    let closed = match state {
+      _ if state.is_placeable() => None,
+      _ if is_dead(outer_tile_pos) => Some(DEAD_CLOSED),
+      TileBoardState::Won(p) => Some(WON[p.idx()]),
+      _ => Some(FULLY_DRAWN),
+    };
+    for inner_pos in InnerPos::all() {
+      let tile = round
+        .board()
+        .trivial_tile(GlobalPos::from((outer_pos, inner_pos)));
+      key[i * 9 + TilePos::from(inner_pos).linear_idx()] = match (closed, tile) {
+        (Some(closed), _) => closed,
+        (None, TrivialTileState::Free) => 0,
+        (None, TrivialTileState::Won(_)) if state.is_drawn() => DRAWN_OCCUPIED,
+        (None, TrivialTileState::Won(p)) => 1 + p.idx() as u8,
+      };
+    }
+  }
+  key[81] = round
+    .current_outer_pos()
+    .map(|outer_pos| TilePos::from(outer_pos).linear_idx() as u8)
+    .unwrap_or(9);
+  key[82] = round.current_player().idx() as u8;
+
+  let mut hasher = DefaultHasher::new();
+  key.hash(&mut hasher);
+  hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::{solve, NodeLimitError, SolvedValue};
+  use crate::{
+    game::{RoundOutcome, RoundState},
+    rules::RuleSet,
+    PlayerSymbol,
+  };
+
+  /// Outcome under perfect play by plain minimax, from the point of view of the player to move.
+  /// `None` if it takes more than `budget` nodes.
+  fn brute_force(round: &mut RoundState, budget: &mut u64) -> Option<i8> {
+    *budget = budget.checked_sub(1)?;
+    match round.outcome() {
+      Some(RoundOutcome::Win(p)) if p == round.current_player() => return Some(1),
+      Some(RoundOutcome::Win(_)) => return Some(-1),
+      Some(RoundOutcome::Draw) => return Some(0),
+      None => {}
+    }
+    let moves: Vec<_> = round.legal_moves().collect();
+    let mut best = -1;
+    for global_pos in moves {
+      round
+        .try_play_move(round.current_player(), global_pos)
+        .unwrap();
+      let value = brute_force(round, budget);
+      round.undo_move();
+      best = best.max(-value?);
+      if best == 1 {
+        break;
+      }
+    }
+    Some(best)
+  }
+
+  #[test]
+  fn check_solutions_match_brute_force() {
+    let mut rng = StdRng::seed_from_u64(13);
+    let mut nsolved = 0;
+    while nsolved < 5 {
+      let mut round = RoundState::new(rng.gen(), RuleSet::default());
+      for _ in 0..rng.gen_range(50..60) {
+        let Some(chosen_tile) = round.legal_moves().choose(&mut rng) else {
+          break;
+        };
+        round
+          .try_play_move(round.current_player(), chosen_tile)
+          .unwrap();
+      }
+      let Some(value) = brute_force(&mut round.clone(), &mut 5_000) else {
+        continue;
+      };
+      let solution = solve(&round, 5_000).unwrap();
+      nsolved += 1;
+
+      let expected = match value {
+        1 => SolvedValue::Win,
+        -1 => SolvedValue::Loss,
+        _ => SolvedValue::Draw,
+      };
+      assert_eq!(solution.value, expected);
+
+      // the best move keeps the outcome
+      let curr_player = round.current_player();
+      if let Some(best_move) = solution.best_move {
+        round.try_play_move(curr_player, best_move).unwrap();
+        let solution = solve(&round, u64::MAX).unwrap();
+        assert_eq!(solution.value, expected.flipped());
+        assert_eq!(
+          solution.value.outcome(round.current_player()),
+          expected.outcome(curr_player)
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn check_solver_limits() {
+    let round: RoundState = "XXXXXX3/6XX1/9/OO1OO1OO1/O2O5/9/9/9/9 X -".parse().unwrap();
+    let solution = solve(&round, 100).unwrap();
+    assert_eq!(solution.value, SolvedValue::Win);
+    assert_eq!(
+      solution.value.outcome(PlayerSymbol::X),
+      RoundOutcome::Win(PlayerSymbol::X)
+    );
+
+    let round = RoundState::new(PlayerSymbol::X, RuleSet::default());
+    assert_eq!(solve(&round, 1000), Err(NodeLimitError(1000)));
+  }
+}
@@ -0,0 +1,195 @@
+//! The 8 symmetries of the square (dihedral group D4), acting on positions.
+//!
+//! Ultimate tic-tac-toe is invariant under these symmetries, so positions that map onto each other
+//! under one of them are equivalent. See [`crate::game::RoundState::canonical`].
+
+use crate::{board::tile::TilePos, GlobalPos, InnerPos, OuterPos};
+
+/// A rotation or reflection of the square.
+///
+/// Rotations are clockwise, as seen with `y` pointing down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+  Identity,
+  Rotate90,
+  Rotate180,
+  Rotate270,
+  /// mirrors `x`
+  FlipX,
+  /// mirrors `y`
+  FlipY,
+  /// swaps `x` and `y`
+  FlipMainDiagonal,
+  /// swaps and mirrors `x` and `y`
+  FlipAntiDiagonal,
+}
+
+impl Symmetry {
+  pub const ALL: [Self; 8] = [
+    Self::Identity,
+    Self::Rotate90,
+    Self::Rotate180,
+    Self::Rotate270,
+    Self::FlipX,
+    Self::FlipY,
+    Self::FlipMainDiagonal,
+    Self::FlipAntiDiagonal,
+  ];
+
+  pub fn inverse(self) -> Self {
+    match self {
+      Self::Rotate90 => Self::Rotate270,
+      Self::Rotate270 => Self::Rotate90,
+      other => other,
+    }
+  }
+
+  /// Maps coordinates on a square of the given side length.
+  pub fn apply(self, [x, y]: [u8; 2], side_length: u8) -> [u8; 2] {
+    let m = side_length - 1;
+    match self {
+      Self::Identity => [x, y],
+      Self::Rotate90 => [m - y, x],
+      Self::Rotate180 => [m - x, m - y],
+      Self::Rotate270 => [y, m - x],
+      Self::FlipX => [m - x, y],
+      Self::FlipY => [x, m - y],
+      Self::FlipMainDiagonal => [y, x],
+      Self::FlipAntiDiagonal => [m - y, m - x],
+    }
+  }
+}
+
+impl TilePos {
+  /// Image of the position on a standard 3x3 board.
+  pub fn transformed(self, symmetry: Symmetry) -> Self {
+    Self::new_arr(symmetry.apply(self.into(), 3))
+  }
+}
+impl OuterPos {
+  pub fn transformed(self, symmetry: Symmetry) -> Self {
+    Self::new_arr(symmetry.apply(self.into(), 3))
+  }
+}
+impl InnerPos {
+  pub fn transformed(self, symmetry: Symmetry) -> Self {
+    Self::new_arr(symmetry.apply(self.into(), 3))
+  }
+}
+/// Transforming the whole 9x9 grid transforms the outer and inner position alike.
+impl GlobalPos {
+  pub fn transformed(self, symmetry: Symmetry) -> Self {
+    Self::new_arr(symmetry.apply(self.into(), 9))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use rand::prelude::*;
+
+  use super::Symmetry;
+  use crate::{
+    game::RoundState,
+    rules::{RuleSet, WonBoardRule},
+    GlobalPos, InnerPos, OuterBoardBackend, OuterPos, PlayerSymbol, TileBoardState,
+  };
+
+  #[test]
+  fn check_symmetries_are_bijections() {
+    for symmetry in Symmetry::ALL {
+      let mut images: Vec<_> = GlobalPos::all()
+        .map(|pos| pos.transformed(symmetry).linear_idx())
+        .collect();
+      images.sort();
+      assert!(images.into_iter().eq(0..81));
+
+      for pos in GlobalPos::all() {
+        assert_eq!(
+          pos.transformed(symmetry).transformed(symmetry.inverse()),
+          pos
+        );
+        assert_eq!(
+          GlobalPos::from((
+            OuterPos::from(pos).transformed(symmetry),
+            InnerPos::from(pos).transformed(symmetry),
+          )),
+          pos.transformed(symmetry)
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn check_transformed_rounds() {
+    let mut rng = StdRng::seed_from_u64(10);
+    let won_playable = RuleSet {
+      won_board: WonBoardRule::Playable,
+      ..RuleSet::default()
+    };
+    // rounds with won sub-boards staying playable are longer
+    for (rules, nrounds) in [(RuleSet::default(), 2), (won_playable, 1)] {
+      for _ in 0..nrounds {
+        check_transformed_round(&mut rng, rules);
+      }
+    }
+
+    // the top left sub-board has lines of both players and stays with O
+    let round: RoundState = "OOO3XX1/XXX6/9/O8/9/9/9/9/9 O c2 won-playable a3:O"
+      .parse()
+      .unwrap();
+    for symmetry in Symmetry::ALL {
+      let image = round.transformed(symmetry);
+      assert_eq!(image.zobrist_hash(), round.symmetric_hash(symmetry));
+      assert_eq!(
+        image
+          .board()
+          .sub_board_state(OuterPos::new(0, 0).transformed(symmetry)),
+        TileBoardState::Won(PlayerSymbol::O)
+      );
+    }
+  }
+
+  fn check_transformed_round(rng: &mut StdRng, rules: RuleSet) {
+    let mut round = RoundState::new(rng.gen(), rules);
+    while round.outcome().is_none() {
+      let (canonical, _) = round.canonical();
+      for symmetry in Symmetry::ALL {
+        let image = round.transformed(symmetry);
+        assert_eq!(image.zobrist_hash(), round.symmetric_hash(symmetry));
+        for outer_pos in OuterPos::all() {
+          assert_eq!(
+            image
+              .board()
+              .sub_board_state(outer_pos.transformed(symmetry)),
+            round.board().sub_board_state(outer_pos)
+          );
+        }
+        assert_eq!(
+          image.current_outer_pos(),
+          round
+            .current_outer_pos()
+            .map(|pos| pos.transformed(symmetry))
+        );
+        let mut legal_moves: Vec<_> = round
+          .legal_moves()
+          .map(|pos| pos.transformed(symmetry).linear_idx())
+          .collect();
+        let mut image_moves: Vec<_> = image.legal_moves().map(|pos| pos.linear_idx()).collect();
+        legal_moves.sort();
+        image_moves.sort();
+        assert_eq!(legal_moves, image_moves);
+
+        assert_eq!(image.canonical().0.board(), canonical.board());
+      }
+
+      let chosen_tile = round.legal_moves().choose(rng).unwrap();
+      round
+        .try_play_move(round.current_player(), chosen_tile)
+        .unwrap();
+    }
+
+    let image = round.transformed(Symmetry::Rotate90);
+    assert_eq!(image.outcome(), round.outcome());
+    assert_eq!(image.board().board_state(), round.board().board_state());
+  }
+}
@@ -0,0 +1,202 @@
+//! A fixed-size transposition table, caching search results by the zobrist hash of positions.
+//!
+//! Entries are stored in atomics, so a table can be shared by searches running in parallel
+//! without locking. Every entry is stored as its data and its key xor-ed with its data.
+//! Entries torn by concurrent writes fail the key check and are treated as missing.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{eval::Score, GlobalPos};
+
+/// How the stored score relates to the true score of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+  /// the score is exact
+  Exact,
+  /// the true score is at least the score, the search failed high
+  Lower,
+  /// the true score is at most the score, the search failed low
+  Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtEntry {
+  pub score: Score,
+  pub bound: Bound,
+  /// remaining depth the position was searched with
+  pub depth: u8,
+  pub best_move: Option<GlobalPos>,
+}
+
+const BOUND_SHIFT: u32 = 32;
+const DEPTH_SHIFT: u32 = 34;
+const MOVE_SHIFT: u32 = 42;
+const NO_MOVE: u64 = 0x7f;
+
+impl TtEntry {
+  /// Packs the entry into 49 bits. Never zero, which marks empty slots.
+  fn pack(self) -> u64 {
+    let bound = match self.bound {
+      Bound::Exact => 1,
+      Bound::Lower => 2,
+      Bound::Upper => 3,
+    };
+    let best_move = self
+      .best_move
+      .map(|global_pos| global_pos.linear_idx() as u64)
+      .unwrap_or(NO_MOVE);
+    self.score as u32 as u64
+      | bound << BOUND_SHIFT
+      | (self.depth as u64) << DEPTH_SHIFT
+      | best_move << MOVE_SHIFT
+  }
+
+  fn unpack(data: u64) -> Option<Self> {
+    let bound = match (data >> BOUND_SHIFT) & 0b11 {
+      1 => Bound::Exact,
+      2 => Bound::Lower,
+      3 => Bound::Upper,
+      _ => return None,
+    };
+    let best_move = match (data >> MOVE_SHIFT) & 0x7f {
+      NO_MOVE => None,
+      idx => Some(GlobalPos::from_linear_idx(idx as usize)),
+    };
+    Some(Self {
+      score: data as u32 as i32,
+      bound,
+      depth: (data >> DEPTH_SHIFT) as u8,
+      best_move,
+    })
+  }
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+  /// hash of the position xor-ed with the data
+  key: AtomicU64,
+  data: AtomicU64,
+}
+
+/// A hash table of search results with one entry per slot.
+///
+/// Entries are replaced by depth: an entry of another position only replaces one searched less deeply.
+pub struct TranspositionTable {
+  slots: Vec<Slot>,
+}
+
+impl std::fmt::Debug for TranspositionTable {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("TranspositionTable")
+      .field("capacity", &self.capacity())
+      .finish()
+  }
+}
+
+impl TranspositionTable {
+  /// Creates a table taking about the given number of megabytes.
+  pub fn new(size_mb: usize) -> Self {
+    let nslots = (size_mb * 1024 * 1024 / std::mem::size_of::<Slot>()).max(1);
+    Self {
+      slots: (0..nslots).map(|_| Slot::default()).collect(),
+    }
+  }
+
+  /// The number of entries the table can hold.
+  pub fn capacity(&self) -> usize {
+    self.slots.len()
+  }
+
+  pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+    let slot = self.slot(hash);
+    let data = slot.data.load(Ordering::Relaxed);
+    let key = slot.key.load(Ordering::Relaxed);
+    (key ^ data == hash)
+      .then(|| TtEntry::unpack(data))
+      .flatten()
+  }
+
+  pub fn store(&self, hash: u64, entry: TtEntry) {
+    let slot = self.slot(hash);
+    let old_data = slot.data.load(Ordering::Relaxed);
+    let old_key = slot.key.load(Ordering::Relaxed) ^ old_data;
+    let replace = match TtEntry::unpack(old_data) {
+      Some(old) => old_key == hash || old.depth <= entry.depth,
+      None => true,
+    };
+    if replace {
+      let data = entry.pack();
+      slot.key.store(hash ^ data, Ordering::Relaxed);
+      slot.data.store(data, Ordering::Relaxed);
+    }
+  }
+
+  /// Removes all entries, e.g. before starting a new round.
+  pub fn clear(&self) {
+    for slot in &self.slots {
+      slot.key.store(0, Ordering::Relaxed);
+      slot.data.store(0, Ordering::Relaxed);
+    }
+  }
+
+  fn slot(&self, hash: u64) -> &Slot {
+    &self.slots[(hash % self.slots.len() as u64) as usize]
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Bound, TranspositionTable, TtEntry};
+  use crate::{eval::WIN_SCORE, GlobalPos};
+
+  #[test]
+  fn check_entries_roundtrip() {
+    let table = TranspositionTable::new(1);
+    assert_eq!(table.capacity(), 1 << 16);
+    assert_eq!(table.probe(42), None);
+
+    for (i, score) in [-WIN_SCORE, -17, 0, 3, WIN_SCORE - 5]
+      .into_iter()
+      .enumerate()
+    {
+      for bound in [Bound::Exact, Bound::Lower, Bound::Upper] {
+        for best_move in [None, Some(GlobalPos::new(8, 8)), Some(GlobalPos::new(0, 0))] {
+          let entry = TtEntry {
+            score,
+            bound,
+            depth: i as u8 * 20,
+            best_move,
+          };
+          table.store(1234, entry);
+          assert_eq!(table.probe(1234), Some(entry));
+        }
+      }
+    }
+    table.clear();
+    assert_eq!(table.probe(1234), None);
+  }
+
+  #[test]
+  fn check_replacement_by_depth() {
+    let table = TranspositionTable::new(1);
+    let entry = |depth| TtEntry {
+      score: 0,
+      bound: Bound::Exact,
+      depth,
+      best_move: None,
+    };
+    // the hashes share a slot
+    let (hash, other) = (7, 7 + table.capacity() as u64);
+    table.store(hash, entry(5));
+    table.store(other, entry(4));
+    assert_eq!(table.probe(hash), Some(entry(5)));
+    assert_eq!(table.probe(other), None);
+
+    table.store(other, entry(5));
+    assert_eq!(table.probe(hash), None);
+    assert_eq!(table.probe(other), Some(entry(5)));
+    // the same position is always updated
+    table.store(other, entry(1));
+    assert_eq!(table.probe(other), Some(entry(1)));
+  }
+}
@@ -0,0 +1,81 @@
+//! Random keys for the incrementally updated Zobrist hash of a [`crate::game::RoundState`].
+
+use crate::{
+  board::tile::TilePos,
+  rules::{DrawRule, DrawnBoardRule, RuleSet, WonBoardRule},
+  GlobalPos, OuterPos, PlayerSymbol,
+};
+
+const NTILE_KEYS: usize = 2 * 81;
+const NOUTER_POS_KEYS: usize = 9;
+const SIDE_KEY_IDX: usize = NTILE_KEYS + NOUTER_POS_KEYS;
+/// one per non-default rule
+const NRULE_KEYS: usize = 3;
+const WINNER_KEYS_IDX: usize = SIDE_KEY_IDX + 1 + NRULE_KEYS;
+/// per player, for the 9 sub-boards and the outer board
+const NWINNER_KEYS: usize = 2 * 10;
+const NKEYS: usize = WINNER_KEYS_IDX + NWINNER_KEYS;
+
+/// Keys generated at compile time by a fixed-seed splitmix64, so hashes are stable across builds and machines.
+const KEYS: [u64; NKEYS] = {
+  let mut keys = [0; NKEYS];
+  let mut state: u64 = 0x005e_ed0f_0777;
+  let mut i = 0;
+  while i < NKEYS {
+    state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    keys[i] = z ^ (z >> 31);
+    i += 1;
+  }
+  keys
+};
+
+pub(crate) fn tile_key(player: PlayerSymbol, pos: GlobalPos) -> u64 {
+  KEYS[player.idx() * 81 + pos.linear_idx()]
+}
+
+/// Key for the forced outer position. A free choice hashes to `0`.
+pub(crate) fn outer_pos_key(outer_pos: Option<OuterPos>) -> u64 {
+  outer_pos
+    .map(|outer_pos| KEYS[NTILE_KEYS + TilePos::from(outer_pos).linear_idx()])
+    .unwrap_or(0)
+}
+
+/// Key xored in while `O` is the player to move.
+pub(crate) fn side_key(player: PlayerSymbol) -> u64 {
+  match player {
+    PlayerSymbol::X => 0,
+    PlayerSymbol::O => KEYS[SIDE_KEY_IDX],
+  }
+}
+
+/// Key for the winner of a board with lines of both players, given the sub-board's position or
+/// `None` for the outer board. Other boards are decided by their tiles and hash to `0`.
+pub(crate) fn disputed_winner_key(
+  outer_pos: Option<OuterPos>,
+  winner: Option<PlayerSymbol>,
+) -> u64 {
+  let board_idx = outer_pos
+    .map(|outer_pos| TilePos::from(outer_pos).linear_idx())
+    .unwrap_or(9);
+  winner
+    .map(|winner| KEYS[WINNER_KEYS_IDX + winner.idx() * 10 + board_idx])
+    .unwrap_or(0)
+}
+
+/// Key of the rules, for tables shared by rounds of different rules.
+/// Not part of the hash of a round. The standard rules hash to `0`.
+pub(crate) fn rules_key(rules: RuleSet) -> u64 {
+  let non_default = [
+    rules.won_board == WonBoardRule::Playable,
+    rules.drawn_board == DrawnBoardRule::CountsForBoth,
+    rules.draw == DrawRule::MajorityOfWonBoards,
+  ];
+  non_default
+    .into_iter()
+    .zip(&KEYS[SIDE_KEY_IDX + 1..])
+    .filter(|&(is_set, _)| is_set)
+    .fold(0, |hash, (_, key)| hash ^ key)
+}
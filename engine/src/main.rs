@@ -0,0 +1,98 @@
+//! Runs a built-in engine speaking the protocol of [`common::protocol`]
+//! on its standard input and output.
+
+use std::io;
+
+use common::{
+  bot::RandomBot,
+  game::RoundState,
+  mcts::{Mcts, MctsConfig},
+  protocol::run_engine,
+  rules::RuleSet,
+  search::Searcher,
+  PlayerSymbol,
+};
+
+const USAGE: &str = "usage: uttt-engine [search|mcts|random] [--threads <n>] [--seed <seed>]";
+
+fn main() {
+  let config = match Config::from_args(std::env::args().skip(1)) {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("{}", e);
+      eprintln!("{}", USAGE);
+      std::process::exit(1);
+    }
+  };
+  if let Err(e) = config.run() {
+    eprintln!("communicating failed: {}", e);
+    std::process::exit(1);
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EngineKind {
+  Search,
+  Mcts,
+  Random,
+}
+
+struct Config {
+  engine: EngineKind,
+  threads: usize,
+  seed: u64,
+}
+
+impl Config {
+  fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+    let mut config = Self {
+      engine: EngineKind::Search,
+      threads: 1,
+      seed: 0,
+    };
+    while let Some(arg) = args.next() {
+      let mut value = |name: &str| args.next().ok_or(format!("missing value of {}", name));
+      match arg.as_str() {
+        "search" => config.engine = EngineKind::Search,
+        "mcts" => config.engine = EngineKind::Mcts,
+        "random" => config.engine = EngineKind::Random,
+        "--threads" => {
+          config.threads = value(&arg)?
+            .parse()
+            .map_err(|e| format!("invalid number of threads: {}", e))?
+        }
+        "--seed" => {
+          config.seed = value(&arg)?
+            .parse()
+            .map_err(|e| format!("invalid seed: {}", e))?
+        }
+        _ => return Err(format!("unknown argument `{}`", arg)),
+      }
+    }
+    Ok(config)
+  }
+
+  fn run(&self) -> io::Result<()> {
+    let (input, output) = (io::stdin().lock(), io::stdout().lock());
+    match self.engine {
+      EngineKind::Search => {
+        let mut searcher = Searcher::default().with_threads(self.threads);
+        run_engine("uttt-search", &mut searcher, input, output)
+      }
+      EngineKind::Mcts => {
+        let mut mcts = Mcts::new(
+          RoundState::new(PlayerSymbol::X, RuleSet::default()),
+          MctsConfig {
+            seed: self.seed,
+            threads: self.threads,
+            ..MctsConfig::default()
+          },
+        );
+        run_engine("uttt-mcts", &mut mcts, input, output)
+      }
+      EngineKind::Random => {
+        run_engine("uttt-random", &mut RandomBot::new(self.seed), input, output)
+      }
+    }
+  }
+}
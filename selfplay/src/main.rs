@@ -0,0 +1,161 @@
+//! Plays MCTS self-play rounds and writes their searched positions as training data,
+//! in the CSV format of [`common::dataset`].
+
+use std::{
+  fs::File,
+  io::{self, BufWriter, Write},
+};
+
+use common::{
+  dataset::{cell_idx, CsvWriter, EncodedPosition, Sample, NCELLS},
+  game::RoundState,
+  mcts::{Mcts, MctsConfig, MctsLimit},
+  rules::RuleSet,
+  PlayerSymbol,
+};
+use rand::prelude::*;
+
+const USAGE: &str = "usage: uttt-selfplay [--games <n>] [--iterations <n>] [--threads <n>] \
+                     [--sampled-plies <n>] [--seed <seed>] [--rules <rules>] [--output <path>]";
+
+fn main() {
+  let config = match Config::from_args(std::env::args().skip(1)) {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("{}", e);
+      eprintln!("{}", USAGE);
+      std::process::exit(1);
+    }
+  };
+  if let Err(e) = config.run() {
+    eprintln!("writing samples failed: {}", e);
+    std::process::exit(1);
+  }
+}
+
+struct Config {
+  ngames: u64,
+  /// search iterations per move
+  iterations: u64,
+  threads: usize,
+  /// number of plies at the start of a round whose moves are sampled by their visits,
+  /// so that rounds differ from each other
+  sampled_plies: usize,
+  seed: u64,
+  rules: RuleSet,
+  /// standard output if `None`
+  output: Option<String>,
+}
+
+impl Config {
+  fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+    let mut config = Self {
+      ngames: 100,
+      iterations: 1000,
+      threads: 1,
+      sampled_plies: 8,
+      seed: 0,
+      rules: RuleSet::default(),
+      output: None,
+    };
+    while let Some(arg) = args.next() {
+      let value = args.next().ok_or(format!("missing value of {}", arg))?;
+      let invalid =
+        |e: &dyn std::fmt::Display| format!("invalid value `{}` of {}: {}", value, arg, e);
+      match arg.as_str() {
+        "--games" => config.ngames = value.parse().map_err(|e| invalid(&e))?,
+        "--iterations" => config.iterations = value.parse().map_err(|e| invalid(&e))?,
+        "--threads" => config.threads = value.parse().map_err(|e| invalid(&e))?,
+        "--sampled-plies" => config.sampled_plies = value.parse().map_err(|e| invalid(&e))?,
+        "--seed" => config.seed = value.parse().map_err(|e| invalid(&e))?,
+        "--rules" => config.rules = value.parse().map_err(|e| invalid(&e))?,
+        "--output" => config.output = Some(value),
+        _ => return Err(format!("unknown argument `{}`", arg)),
+      }
+    }
+    // a search without iterations has no move to play
+    if config.iterations == 0 {
+      return Err("invalid value `0` of --iterations: must be positive".to_string());
+    }
+    Ok(config)
+  }
+
+  fn run(&self) -> io::Result<()> {
+    let output: Box<dyn Write> = match &self.output {
+      Some(path) => Box::new(File::create(path)?),
+      None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = CsvWriter::new(BufWriter::new(output))?;
+    for game in 0..self.ngames {
+      let samples = self.play_game(game);
+      for sample in &samples {
+        writer.write(sample)?;
+      }
+      eprintln!(
+        "game {}/{}: {} positions",
+        game + 1,
+        self.ngames,
+        samples.len()
+      );
+    }
+    writer.into_inner().flush()
+  }
+
+  /// Plays a round and returns its positions. Rounds only depend on the seed and their index.
+  fn play_game(&self, game: u64) -> Vec<Sample> {
+    let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(game));
+    let starting_player = match game % 2 {
+      0 => PlayerSymbol::X,
+      _ => PlayerSymbol::O,
+    };
+    let mut round = RoundState::new(starting_player, self.rules);
+    let mut mcts = Mcts::new(
+      round.clone(),
+      MctsConfig {
+        limit: MctsLimit::Iterations(self.iterations),
+        seed: rng.gen(),
+        threads: self.threads,
+        ..MctsConfig::default()
+      },
+    );
+
+    let mut positions = Vec::new();
+    let outcome = loop {
+      if let Some(outcome) = round.outcome() {
+        break outcome;
+      }
+      mcts.search();
+      let stats = mcts.move_stats();
+      let mut visits = [0; NCELLS];
+      for move_stats in &stats {
+        visits[cell_idx(move_stats.global_pos)] = move_stats.visits;
+      }
+      let ply = round.move_history().len();
+      positions.push((ply, EncodedPosition::new(&round), visits));
+
+      let chosen = match ply < self.sampled_plies {
+        true => stats
+          .choose_weighted(&mut rng, |move_stats| move_stats.visits)
+          .expect("searched moves must have visits"),
+        false => &stats[0],
+      };
+      round
+        .try_play_move(round.current_player(), chosen.global_pos)
+        .expect("searched move must be playable");
+      mcts
+        .advance(chosen.global_pos)
+        .expect("searched move must be playable");
+    };
+
+    positions
+      .into_iter()
+      .map(|(ply, position, visits)| Sample {
+        game,
+        ply,
+        position,
+        visits,
+        outcome,
+      })
+      .collect()
+  }
+}
@@ -6,22 +6,39 @@ use common::{
     receive_msg_from_stream, send_msg_to_stream, ClientMsgAction, ClientReqRoundStart,
     ServerMsgOpponentAction, ServerMsgRoundStart, ServerMsgSymbolAssignment,
   },
+  protocol::{ExternalEngine, GoLimits},
+  record::GameRecord,
+  rules::RuleSet,
   PlayerSymbol, DEFAULT_SOCKET_ADDR, PLAYERS,
 };
 
 use std::{
   io,
   net::{SocketAddrV4, TcpListener, TcpStream},
+  time::Duration,
 };
 
+/// Thinking time of an external engine playing against a client.
+const ENGINE_MOVETIME: Duration = Duration::from_millis(1000);
+
 fn main() {
   let mut server = Server::connect();
   server.play_game()
 }
 
+/// Who plays a symbol.
+pub enum Seat {
+  Client(TcpStream),
+  /// an engine process, started if its command line is given by the
+  /// [`ENGINE_ENV_VAR`](common::protocol::ENGINE_ENV_VAR) environment variable
+  Engine(ExternalEngine),
+}
+
 pub struct Server {
   /// sorted according to `Player`
-  streams: [TcpStream; 2],
+  seats: [Seat; 2],
+  /// number of rounds played so far, where the search for a free record name starts
+  nrounds: usize,
 }
 
 impl Server {
@@ -36,10 +53,20 @@ impl Server {
     };
     let listener = TcpListener::bind(socket_addr).expect("Failed to bind TcpListener.");
 
+    let engine = ExternalEngine::from_env(GoLimits {
+      movetime: Some(ENGINE_MOVETIME),
+      ..GoLimits::default()
+    })
+    .map(|engine| engine.expect("Starting engine failed."));
+    let nclients = match engine {
+      Some(_) => 1,
+      None => 2,
+    };
+
     let mut curr_player: PlayerSymbol = rand::random();
 
     println!("Waiting for connections...");
-    let mut streams: Vec<(PlayerSymbol, TcpStream)> = listener
+    let mut seats: Vec<(PlayerSymbol, Seat)> = listener
       .incoming()
       .filter_map(|stream| match stream {
         Ok(s) => Some(s),
@@ -49,28 +76,32 @@ impl Server {
           None
         }
       })
-      .take(2)
+      .take(nclients)
       .enumerate()
       .map(|(i, mut stream)| {
         send_msg_to_stream(&ServerMsgSymbolAssignment(curr_player), &mut stream)
           .expect("Sending message failed.");
         println!("Player{} connected {}", i, stream.peer_addr().unwrap());
 
-        let r = (curr_player, stream);
+        let r = (curr_player, Seat::Client(stream));
         curr_player.switch();
         r
       })
       .collect();
+    if let Some(engine) = engine {
+      println!("Engine {} plays {:?}.", engine.name(), curr_player);
+      seats.push((curr_player, Seat::Engine(engine)));
+    }
 
-    streams.sort_by_key(|&(s, _)| s);
-    let streams = streams
+    seats.sort_by_key(|&(s, _)| s);
+    let seats = seats
       .into_iter()
       .map(|(_, s)| s)
       .collect::<Vec<_>>()
       .try_into()
-      .unwrap();
+      .unwrap_or_else(|_| unreachable!("both symbols are seated"));
 
-    Self { streams }
+    Self { seats, nrounds: 0 }
   }
 
   pub fn play_game(&mut self) {
@@ -87,7 +118,9 @@ impl Server {
       }
 
       for player in PLAYERS {
-        let _: ClientReqRoundStart = self.receive_msg(player).unwrap();
+        if let Seat::Client(stream) = self.seat_mut(player) {
+          let _: ClientReqRoundStart = receive_msg_from_stream(stream).unwrap();
+        }
       }
     }
   }
@@ -95,19 +128,40 @@ impl Server {
   fn play_round(&mut self) -> RoundOutcome {
     println!("New round started.");
     let starting_player: PlayerSymbol = rand::random();
-    let mut round_state = RoundState::new(starting_player);
+    let mut round_state = RoundState::new(starting_player, RuleSet::default());
+    let mut record = GameRecord::new(starting_player);
 
     self
       .broadcast_msg(&ServerMsgRoundStart(starting_player))
       .unwrap();
+    for seat in &mut self.seats {
+      if let Seat::Engine(engine) = seat {
+        if let Err(e) = engine.new_game() {
+          println!("Engine failed: {}", e);
+        }
+      }
+    }
 
     // main round loop
-    loop {
+    let outcome = loop {
       if let Some(outcome) = round_state.outcome() {
-        return outcome;
+        break outcome;
       }
 
-      let ClientMsgAction(action) = self.receive_msg(round_state.current_player()).unwrap();
+      let action = match self.seat_mut(round_state.current_player()) {
+        Seat::Client(stream) => {
+          let ClientMsgAction(action) = receive_msg_from_stream(stream).unwrap();
+          action
+        }
+        Seat::Engine(engine) => match engine.best_move(&round_state) {
+          Ok(chosen_tile) => PlayerAction::MakeMove(chosen_tile),
+          Err(e) => {
+            println!("Engine failed: {}", e);
+            PlayerAction::GiveUp
+          }
+        },
+      };
+      record.push_action(action);
 
       let opponent_msg = ServerMsgOpponentAction(action);
       self
@@ -118,23 +172,43 @@ impl Server {
         PlayerAction::MakeMove(chosen_tile) => round_state
           .try_play_move(round_state.current_player(), chosen_tile)
           .unwrap(),
-        PlayerAction::GiveUp => return RoundOutcome::Win(round_state.current_player().other()),
+        PlayerAction::GiveUp => break RoundOutcome::Win(round_state.current_player().other()),
       };
+    };
+
+    record.result = Some(outcome);
+    self.nrounds += 1;
+    match self.save_record(&record) {
+      Ok(record_path) => println!("Saved round record to {}.", record_path),
+      Err(e) => println!("Saving round record failed: {}", e),
     }
+    outcome
   }
 
-  fn stream_mut(&mut self, player: PlayerSymbol) -> &mut TcpStream {
-    &mut self.streams[player.idx()]
+  /// Saves the record as `round-<n>.uttt` with the first `n` not taken yet, so the records of
+  /// earlier sessions are kept. Returns the path.
+  fn save_record(&self, record: &GameRecord) -> io::Result<String> {
+    let mut n = self.nrounds;
+    loop {
+      let record_path = format!("round-{}.uttt", n);
+      match record.save_new(&record_path) {
+        Ok(()) => return Ok(record_path),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+        Err(e) => return Err(e),
+      }
+    }
   }
 
-  fn receive_msg<Msg: serde::de::DeserializeOwned>(
-    &mut self,
-    player: PlayerSymbol,
-  ) -> io::Result<Msg> {
-    receive_msg_from_stream(self.stream_mut(player))
+  fn seat_mut(&mut self, player: PlayerSymbol) -> &mut Seat {
+    &mut self.seats[player.idx()]
   }
+
+  /// Engines are not sent messages, they are given the whole round when asked for a move.
   fn send_msg<Msg: serde::Serialize>(&mut self, msg: &Msg, player: PlayerSymbol) -> io::Result<()> {
-    send_msg_to_stream(msg, self.stream_mut(player))
+    match self.seat_mut(player) {
+      Seat::Client(stream) => send_msg_to_stream(msg, stream),
+      Seat::Engine(_) => Ok(()),
+    }
   }
   fn broadcast_msg<Msg: serde::Serialize>(&mut self, msge: &Msg) -> io::Result<()> {
     for p in PLAYERS {